@@ -0,0 +1,685 @@
+use std::collections::HashMap;
+use custom_error::custom_error;
+use crate::{Instruction, Register};
+use crate::serializer::serialize_instruction_bytes;
+
+const PROGRAM_START_ADDRESS: u32 = 0x200;
+const COMMENT_CHARACTER: char = ';';
+
+custom_error!{pub AssemblyError
+    UnknownMnemonic {line: usize, column: usize, mnemonic: String} = "Line {line}, column {column}: unknown mnemonic '{mnemonic}'",
+    InvalidOperands {line: usize, column: usize, mnemonic: String} = "Line {line}, column {column}: invalid operands for '{mnemonic}'",
+    InvalidOperand {line: usize, column: usize, operand: String} = "Line {line}, column {column}: '{operand}' is not a valid operand",
+    EmptyOperand {line: usize, column: usize} = "Line {line}, column {column}: expected an operand",
+    ValueOutOfRange {line: usize, column: usize, value: u32, maximum: u32} = "Line {line}, column {column}: value {value} is larger than the maximum of {maximum}",
+    InvalidLabelName {line: usize, column: usize, label: String} = "Line {line}, column {column}: '{label}' cannot be used as a label name",
+    DuplicateLabel {line: usize, column: usize, label: String} = "Line {line}, column {column}: label '{label}' is already defined",
+    UndefinedLabel {line: usize, column: usize, label: String} = "Line {line}, column {column}: label '{label}' is not defined",
+}
+
+impl AssemblyError {
+    pub fn position(&self) -> (usize, usize) {
+        match self {
+            AssemblyError::UnknownMnemonic {line, column, ..} => (*line, *column),
+            AssemblyError::InvalidOperands {line, column, ..} => (*line, *column),
+            AssemblyError::InvalidOperand {line, column, ..} => (*line, *column),
+            AssemblyError::EmptyOperand {line, column} => (*line, *column),
+            AssemblyError::ValueOutOfRange {line, column, ..} => (*line, *column),
+            AssemblyError::InvalidLabelName {line, column, ..} => (*line, *column),
+            AssemblyError::DuplicateLabel {line, column, ..} => (*line, *column),
+            AssemblyError::UndefinedLabel {line, column, ..} => (*line, *column),
+        }
+    }
+}
+
+struct Operand {
+    text: String,
+    column: usize,
+}
+
+struct ParsedLine {
+    labels: Vec<(String, usize)>,
+    statement: Option<Statement>,
+}
+
+struct Statement {
+    line: usize,
+    column: usize,
+    mnemonic: String,
+    operands: Vec<Operand>,
+}
+
+enum Argument {
+    Register(Register),
+    Key,
+    Font,
+    BigFont,
+    Bcd,
+    MemoryAtI,
+    Flags,
+    Value {value: u32, column: usize},
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblyError> {
+    // First pass finds where every statement will live so labels can be referenced before
+    // they are defined, second pass does the actual encoding
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = PROGRAM_START_ADDRESS;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let parsed_line = parse_line(line, line_number)?;
+        for (label, column) in parsed_line.labels {
+            if labels.contains_key(&label) {
+                return Err(AssemblyError::DuplicateLabel {line: line_number, column, label});
+            }
+
+            labels.insert(label, address);
+        }
+
+        if let Some(statement) = parsed_line.statement {
+            address += statement_size(&statement);
+            statements.push(statement);
+        }
+    }
+
+    let mut bytes = Vec::new();
+    for statement in statements {
+        bytes.extend(encode_statement(&statement, &labels)?);
+    }
+
+    Ok(bytes)
+}
+
+fn parse_line(line: &str, line_number: usize) -> Result<ParsedLine, AssemblyError> {
+    let content = match line.find(COMMENT_CHARACTER) {
+        Some(index) => &line[..index],
+        None => line,
+    };
+
+    let mut labels = Vec::new();
+    let mut offset = 0;
+    loop {
+        let remaining = &content[offset..];
+        let trimmed = remaining.trim_start();
+        if trimmed.is_empty() {
+            return Ok(ParsedLine {labels, statement: None});
+        }
+
+        let start = offset + (remaining.len() - trimmed.len());
+        let word_length = trimmed.find(|c: char| c.is_whitespace() || c == ':').unwrap_or(trimmed.len());
+        let word = &trimmed[..word_length];
+        if !trimmed[word_length..].starts_with(':') {
+            break;
+        }
+
+        let column = column_of(content, start);
+        if !is_valid_label_name(word) {
+            return Err(AssemblyError::InvalidLabelName {line: line_number, column, label: word.to_owned()});
+        }
+
+        labels.push((word.to_owned(), column));
+        offset = start + word_length + 1;
+    }
+
+    let remaining = &content[offset..];
+    let trimmed = remaining.trim_start();
+    let start = offset + (remaining.len() - trimmed.len());
+    let mnemonic_length = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+    let mnemonic = trimmed[..mnemonic_length].to_owned();
+
+    let mut operands = Vec::new();
+    let operand_text = &trimmed[mnemonic_length..];
+    if !operand_text.trim().is_empty() {
+        let mut operand_start = start + mnemonic_length;
+        for raw_operand in operand_text.split(',') {
+            let text = raw_operand.trim();
+            let leading_whitespace = raw_operand.len() - raw_operand.trim_start().len();
+            let column = column_of(content, operand_start + leading_whitespace);
+            if text.is_empty() {
+                return Err(AssemblyError::EmptyOperand {line: line_number, column});
+            }
+
+            operands.push(Operand {text: text.to_owned(), column});
+            operand_start += raw_operand.len() + 1;
+        }
+    }
+
+    let statement = Statement {
+        line: line_number,
+        column: column_of(content, start),
+        mnemonic,
+        operands,
+    };
+
+    Ok(ParsedLine {labels, statement: Some(statement)})
+}
+
+fn column_of(line: &str, byte_index: usize) -> usize {
+    line[..byte_index].chars().count() + 1
+}
+
+fn statement_size(statement: &Statement) -> u32 {
+    match statement.mnemonic.to_lowercase().as_ref() {
+        "db" => statement.operands.len() as u32,
+        "dw" => statement.operands.len() as u32 * 2,
+        "ldl" => 4,
+        _ => 2,
+    }
+}
+
+fn encode_statement(statement: &Statement, labels: &HashMap<String, u32>) -> Result<Vec<u8>, AssemblyError> {
+    let mnemonic = statement.mnemonic.to_uppercase();
+
+    // `F` and `B` are also valid hex values, so they are only treated as keywords when they
+    // are the destination of a load
+    let mut arguments = Vec::new();
+    for (index, operand) in statement.operands.iter().enumerate() {
+        let allow_keywords = mnemonic == "LD" && index == 0;
+        arguments.push(parse_argument(operand, statement.line, labels, allow_keywords)?);
+    }
+
+    match mnemonic.as_ref() {
+        "DB" => {
+            let mut bytes = Vec::new();
+            for argument in &arguments {
+                bytes.push(value_argument(argument, statement, 0xff)? as u8);
+            }
+
+            return Ok(bytes);
+        }
+
+        "DW" => {
+            let mut bytes = Vec::new();
+            for argument in &arguments {
+                let value = value_argument(argument, statement, 0xffff)?;
+                bytes.push((value >> 8) as u8);
+                bytes.push(value as u8);
+            }
+
+            return Ok(bytes);
+        }
+
+        _ => (),
+    }
+
+    let instruction = match (mnemonic.as_ref(), arguments.as_slice()) {
+        ("CLS", []) => Instruction::ClearDisplay,
+        ("RET", []) => Instruction::Return,
+        ("SCR", []) => Instruction::ScrollRight,
+        ("SCL", []) => Instruction::ScrollLeft,
+        ("EXIT", []) => Instruction::Exit,
+        ("LOW", []) => Instruction::DisableHighResolution,
+        ("HIGH", []) => Instruction::EnableHighResolution,
+        ("AUDIO", []) => Instruction::LoadAudioPattern,
+
+        ("SCD", [Argument::Value {value, column}]) => {
+            Instruction::ScrollDown {rows: check_range(*value, 0xf, statement.line, *column)? as u8}
+        }
+
+        ("SCU", [Argument::Value {value, column}]) => {
+            Instruction::ScrollUp {rows: check_range(*value, 0xf, statement.line, *column)? as u8}
+        }
+
+        ("PLANE", [Argument::Value {value, column}]) => {
+            Instruction::SelectPlanes {planes: check_range(*value, 0xf, statement.line, *column)? as u8}
+        }
+
+        ("PITCH", [Argument::Register(Register::General(x))]) => {
+            Instruction::SetPitch {register: Register::General(*x)}
+        }
+
+        ("SAVE", [Argument::Register(Register::General(x)), Argument::Register(Register::General(y))]) => {
+            Instruction::SaveRegisterRange {first_register: Register::General(*x), last_register: Register::General(*y)}
+        }
+
+        ("LOAD", [Argument::Register(Register::General(x)), Argument::Register(Register::General(y))]) => {
+            Instruction::LoadRegisterRange {first_register: Register::General(*x), last_register: Register::General(*y)}
+        }
+
+        ("LDL", [Argument::Register(Register::I), Argument::Value {value, column}]) => {
+            let address = check_range(*value, 0xffff, statement.line, *column)? as u16;
+            Instruction::LoadLongAddressIntoIRegister {address}
+        }
+
+        ("SYS", [Argument::Value {value, column}]) => {
+            Instruction::JumpToMachineCode {address: check_range(*value, 0xfff, statement.line, *column)? as u16}
+        }
+
+        ("JP", [Argument::Value {value, column}]) => {
+            let address = check_range(*value, 0xfff, statement.line, *column)? as u16;
+            Instruction::JumpToAddress {address, add_register_0: false}
+        }
+
+        ("JP", [Argument::Register(Register::General(0)), Argument::Value {value, column}]) => {
+            let address = check_range(*value, 0xfff, statement.line, *column)? as u16;
+            Instruction::JumpToAddress {address, add_register_0: true}
+        }
+
+        ("CALL", [Argument::Value {value, column}]) => {
+            Instruction::Call {address: check_range(*value, 0xfff, statement.line, *column)? as u16}
+        }
+
+        ("SE", [Argument::Register(Register::General(x)), Argument::Value {value, column}]) => {
+            let value = check_range(*value, 0xff, statement.line, *column)? as u8;
+            Instruction::SkipIfEqual {register: Register::General(*x), value}
+        }
+
+        ("SE", [Argument::Register(Register::General(x)), Argument::Register(Register::General(y))]) => {
+            Instruction::SkipIfRegistersEqual {register1: Register::General(*x), register2: Register::General(*y)}
+        }
+
+        ("SNE", [Argument::Register(Register::General(x)), Argument::Value {value, column}]) => {
+            let value = check_range(*value, 0xff, statement.line, *column)? as u8;
+            Instruction::SkipIfNotEqual {register: Register::General(*x), value}
+        }
+
+        ("SNE", [Argument::Register(Register::General(x)), Argument::Register(Register::General(y))]) => {
+            Instruction::SkipIfRegistersNotEqual {register1: Register::General(*x), register2: Register::General(*y)}
+        }
+
+        ("LD", [Argument::Register(Register::General(x)), Argument::Value {value, column}]) => {
+            let value = check_range(*value, 0xff, statement.line, *column)? as u8;
+            Instruction::LoadFromValue {destination: Register::General(*x), value}
+        }
+
+        ("LD", [Argument::Register(Register::I), Argument::Value {value, column}]) => {
+            let address = check_range(*value, 0xfff, statement.line, *column)? as u16;
+            Instruction::LoadAddressIntoIRegister {address}
+        }
+
+        ("LD", [Argument::Register(destination), Argument::Register(source)]) if *destination != Register::I && *source != Register::I => {
+            Instruction::LoadFromRegister {destination: destination.clone(), source: source.clone()}
+        }
+
+        ("LD", [Argument::Register(Register::General(x)), Argument::Key]) => {
+            Instruction::LoadFromKeyPress {destination: Register::General(*x)}
+        }
+
+        ("LD", [Argument::Font, Argument::Register(Register::General(x))]) => {
+            Instruction::LoadSpriteLocation {sprite_digit: Register::General(*x)}
+        }
+
+        ("LD", [Argument::BigFont, Argument::Register(Register::General(x))]) => {
+            Instruction::LoadBigSpriteLocation {sprite_digit: Register::General(*x)}
+        }
+
+        ("LD", [Argument::Flags, Argument::Register(Register::General(x))]) => {
+            Instruction::LoadIntoFlags {last_register: Register::General(*x)}
+        }
+
+        ("LD", [Argument::Register(Register::General(x)), Argument::Flags]) => {
+            Instruction::LoadFromFlags {last_register: Register::General(*x)}
+        }
+
+        ("LD", [Argument::Bcd, Argument::Register(Register::General(x))]) => {
+            Instruction::LoadBcdValue {source: Register::General(*x)}
+        }
+
+        ("LD", [Argument::MemoryAtI, Argument::Register(Register::General(x))]) => {
+            Instruction::LoadIntoMemory {last_register: Register::General(*x)}
+        }
+
+        ("LD", [Argument::Register(Register::General(x)), Argument::MemoryAtI]) => {
+            Instruction::LoadFromMemory {last_register: Register::General(*x)}
+        }
+
+        ("ADD", [Argument::Register(Register::General(x)), Argument::Value {value, column}]) => {
+            let value = check_range(*value, 0xff, statement.line, *column)? as u8;
+            Instruction::AddFromValue {register: Register::General(*x), value}
+        }
+
+        ("ADD", [Argument::Register(register1), Argument::Register(Register::General(y))]) if *register1 == Register::I || is_general(register1) => {
+            Instruction::AddFromRegister {register1: register1.clone(), register2: Register::General(*y)}
+        }
+
+        ("OR", [Argument::Register(Register::General(x)), Argument::Register(Register::General(y))]) => {
+            Instruction::Or {register1: Register::General(*x), register2: Register::General(*y)}
+        }
+
+        ("AND", [Argument::Register(Register::General(x)), Argument::Register(Register::General(y))]) => {
+            Instruction::And {register1: Register::General(*x), register2: Register::General(*y)}
+        }
+
+        ("XOR", [Argument::Register(Register::General(x)), Argument::Register(Register::General(y))]) => {
+            Instruction::Xor {register1: Register::General(*x), register2: Register::General(*y)}
+        }
+
+        ("SUB", [Argument::Register(Register::General(x)), Argument::Register(Register::General(y))]) => {
+            Instruction::Subtract {
+                minuend: Register::General(*x),
+                subtrahend: Register::General(*y),
+                stored_in: Register::General(*x),
+            }
+        }
+
+        // Operands are in the same order the disassembler displays them in (minuend first),
+        // so the result is stored in the second register
+        ("SUBN", [Argument::Register(Register::General(x)), Argument::Register(Register::General(y))]) => {
+            Instruction::Subtract {
+                minuend: Register::General(*x),
+                subtrahend: Register::General(*y),
+                stored_in: Register::General(*y),
+            }
+        }
+
+        // Without a source register the register shifts itself, which behaves the same with or
+        // without the shift quirk
+        ("SHR", [Argument::Register(Register::General(x))]) => {
+            Instruction::ShiftRight {register: Register::General(*x), source: Register::General(*x)}
+        }
+
+        ("SHR", [Argument::Register(Register::General(x)), Argument::Register(Register::General(y))]) => {
+            Instruction::ShiftRight {register: Register::General(*x), source: Register::General(*y)}
+        }
+
+        ("SHL", [Argument::Register(Register::General(x))]) => {
+            Instruction::ShiftLeft {register: Register::General(*x), source: Register::General(*x)}
+        }
+
+        ("SHL", [Argument::Register(Register::General(x)), Argument::Register(Register::General(y))]) => {
+            Instruction::ShiftLeft {register: Register::General(*x), source: Register::General(*y)}
+        }
+
+        ("RND", [Argument::Register(Register::General(x)), Argument::Value {value, column}]) => {
+            let and_value = check_range(*value, 0xff, statement.line, *column)? as u8;
+            Instruction::SetRandom {register: Register::General(*x), and_value}
+        }
+
+        ("DRW", [Argument::Register(Register::General(x)), Argument::Register(Register::General(y)), Argument::Value {value, column}]) => {
+            let height = check_range(*value, 0xf, statement.line, *column)? as u8;
+            Instruction::DrawSprite {x_register: Register::General(*x), y_register: Register::General(*y), height}
+        }
+
+        ("SKP", [Argument::Register(Register::General(x))]) => {
+            Instruction::SkipIfKeyPressed {register: Register::General(*x)}
+        }
+
+        ("SKNP", [Argument::Register(Register::General(x))]) => {
+            Instruction::SkipIfKeyNotPressed {register: Register::General(*x)}
+        }
+
+        ("UNK", [Argument::Value {value, column}]) => {
+            Instruction::Unknown {bytes: check_range(*value, 0xffff, statement.line, *column)? as u16}
+        }
+
+        (_, _) if is_known_mnemonic(&mnemonic) => {
+            return Err(AssemblyError::InvalidOperands {line: statement.line, column: statement.column, mnemonic: statement.mnemonic.clone()});
+        }
+
+        (_, _) => {
+            return Err(AssemblyError::UnknownMnemonic {line: statement.line, column: statement.column, mnemonic: statement.mnemonic.clone()});
+        }
+    };
+
+    match serialize_instruction_bytes(instruction) {
+        Ok(bytes) => Ok(bytes),
+        Err(_) => Err(AssemblyError::InvalidOperands {line: statement.line, column: statement.column, mnemonic: statement.mnemonic.clone()}),
+    }
+}
+
+fn parse_argument(operand: &Operand, line: usize, labels: &HashMap<String, u32>, allow_keywords: bool) -> Result<Argument, AssemblyError> {
+    if let Some(register) = parse_register(&operand.text) {
+        return Ok(Argument::Register(register));
+    }
+
+    match operand.text.to_uppercase().as_ref() {
+        "K" => return Ok(Argument::Key),
+        "HF" => return Ok(Argument::BigFont),
+        "R" => return Ok(Argument::Flags),
+        "[I]" => return Ok(Argument::MemoryAtI),
+        "F" if allow_keywords => return Ok(Argument::Font),
+        "B" if allow_keywords => return Ok(Argument::Bcd),
+        _ => (),
+    }
+
+    if is_number(&operand.text) {
+        return match parse_number(&operand.text) {
+            Some(value) => Ok(Argument::Value {value, column: operand.column}),
+            None => Err(AssemblyError::InvalidOperand {line, column: operand.column, operand: operand.text.clone()}),
+        };
+    }
+
+    if is_valid_label_name(&operand.text) {
+        return match labels.get(&operand.text) {
+            Some(address) => Ok(Argument::Value {value: *address, column: operand.column}),
+            None => Err(AssemblyError::UndefinedLabel {line, column: operand.column, label: operand.text.clone()}),
+        };
+    }
+
+    Err(AssemblyError::InvalidOperand {line, column: operand.column, operand: operand.text.clone()})
+}
+
+fn parse_register(text: &str) -> Option<Register> {
+    let lower = text.to_lowercase();
+    match lower.as_ref() {
+        "i" => return Some(Register::I),
+        "dt" => return Some(Register::DelayTimer),
+        "st" => return Some(Register::SoundTimer),
+        _ => (),
+    }
+
+    let mut chars = lower.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(digit), None) => digit.to_digit(16).map(|x| Register::General(x as u8)),
+        _ => None,
+    }
+}
+
+fn is_general(register: &Register) -> bool {
+    matches!(register, Register::General(_))
+}
+
+// Numbers are hexadecimal by default, since that is how the disassembler displays them
+fn is_number(text: &str) -> bool {
+    let lower = text.to_lowercase();
+    let digits = lower.strip_prefix("0x").or_else(|| lower.strip_prefix("0b")).unwrap_or(&lower);
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    let lower = text.to_lowercase();
+    if let Some(digits) = lower.strip_prefix("0x") {
+        u32::from_str_radix(digits, 16).ok()
+    } else if let Some(digits) = lower.strip_prefix("0b") {
+        u32::from_str_radix(digits, 2).ok()
+    } else {
+        u32::from_str_radix(&lower, 16).ok()
+    }
+}
+
+fn is_valid_label_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    let valid_start = match chars.next() {
+        Some(c) => c.is_ascii_alphabetic() || c == '_' || c == '.',
+        None => false,
+    };
+
+    let valid_characters = chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    let reserved = parse_register(text).is_some() || is_number(text) || ["k", "f", "hf", "b", "r"].contains(&text.to_lowercase().as_ref());
+
+    valid_start && valid_characters && !reserved
+}
+
+fn is_known_mnemonic(mnemonic: &str) -> bool {
+    const MNEMONICS: [&str; 34] = [
+        "CLS", "RET", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN",
+        "SHR", "SHL", "RND", "DRW", "SKP", "SKNP", "UNK", "SCD", "SCR", "SCL", "EXIT", "LOW", "HIGH",
+        "SCU", "PLANE", "AUDIO", "PITCH", "SAVE", "LOAD", "LDL",
+    ];
+
+    MNEMONICS.contains(&mnemonic)
+}
+
+fn value_argument(argument: &Argument, statement: &Statement, maximum: u32) -> Result<u32, AssemblyError> {
+    match argument {
+        Argument::Value {value, column} => check_range(*value, maximum, statement.line, *column),
+        _ => Err(AssemblyError::InvalidOperands {line: statement.line, column: statement.column, mnemonic: statement.mnemonic.clone()}),
+    }
+}
+
+fn check_range(value: u32, maximum: u32, line: usize, column: usize) -> Result<u32, AssemblyError> {
+    if value > maximum {
+        return Err(AssemblyError::ValueOutOfRange {line, column, value, maximum});
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_instruction;
+    use crate::serializer::serialize_instruction;
+
+    #[test]
+    fn can_assemble_single_instruction() {
+        let bytes = assemble("LD V3, 1f").unwrap();
+        assert_eq!(bytes, vec![0x63, 0x1f]);
+    }
+
+    #[test]
+    fn mnemonics_and_registers_are_case_insensitive() {
+        let bytes = assemble("drw v0, V1, 5\njp V0, 2a0").unwrap();
+        assert_eq!(bytes, vec![0xd0, 0x15, 0xb2, 0xa0]);
+    }
+
+    #[test]
+    fn every_displayed_instruction_assembles_to_the_same_bytes() {
+        for opcode in 0..=0xffff_u32 {
+            let instruction = get_instruction((opcode >> 8) as u8, opcode as u8);
+            let display = format!("{}", instruction);
+            let expected = serialize_instruction(instruction).unwrap();
+
+            let bytes = assemble(&display).unwrap_or_else(|e| panic!("Failed to assemble '{}': {}", display, e));
+            assert_eq!(bytes, vec![expected.0, expected.1], "Incorrect bytes for '{}'", display);
+        }
+    }
+
+    #[test]
+    fn can_assemble_super_chip_instructions() {
+        let source = "HIGH\nSCD 4\nSCR\nSCL\nLD HF, V2\nLD R, V7\nLD V7, R\nLOW\nEXIT";
+        let bytes = assemble(source).unwrap();
+        let expected = vec![
+            0x00, 0xff, 0x00, 0xc4, 0x00, 0xfb, 0x00, 0xfc, 0xf2, 0x30, 0xf7, 0x75, 0xf7, 0x85, 0x00, 0xfe, 0x00, 0xfd,
+        ];
+
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn can_assemble_xo_chip_instructions() {
+        let source = "SCU 3\nPLANE 2\nAUDIO\nPITCH V4\nSAVE V1, V5\nLOAD V5, V1\nLDL I, beef";
+        let bytes = assemble(source).unwrap();
+        let expected = vec![
+            0x00, 0xd3, 0xf2, 0x01, 0xf0, 0x02, 0xf4, 0x3a, 0x51, 0x52, 0x55, 0x13, 0xf0, 0x00, 0xbe, 0xef,
+        ];
+
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn labels_after_long_load_account_for_its_size() {
+        let bytes = assemble("LDL I, data\ndata: db 1").unwrap();
+        assert_eq!(bytes, vec![0xf0, 0x00, 0x02, 0x04, 0x01]);
+    }
+
+    #[test]
+    fn single_register_shift_uses_itself_as_source() {
+        let bytes = assemble("SHR V3\nSHL V3, V5").unwrap();
+        assert_eq!(bytes, vec![0x83, 0x36, 0x83, 0x5e]);
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_ignored() {
+        let source = "; a full line comment\n\n   CLS ; clear the screen\n";
+        let bytes = assemble(source).unwrap();
+        assert_eq!(bytes, vec![0x00, 0xe0]);
+    }
+
+    #[test]
+    fn labels_resolve_to_addresses_starting_at_0x200() {
+        let source = "start:\n  CLS\nloop: JP loop\n  CALL start\n";
+        let bytes = assemble(source).unwrap();
+        assert_eq!(bytes, vec![0x00, 0xe0, 0x12, 0x02, 0x22, 0x00]);
+    }
+
+    #[test]
+    fn labels_can_be_referenced_before_being_defined() {
+        let source = "LD I, sprite\nRET\nsprite: db 0xf0, 90";
+        let bytes = assemble(source).unwrap();
+        assert_eq!(bytes, vec![0xa2, 0x04, 0x00, 0xee, 0xf0, 0x90]);
+    }
+
+    #[test]
+    fn can_assemble_data_directives() {
+        let source = "db 1, 0b10000001, 0xff\ndw 1234, label\nlabel:";
+        let bytes = assemble(source).unwrap();
+        assert_eq!(bytes, vec![0x01, 0x81, 0xff, 0x12, 0x34, 0x02, 0x07]);
+    }
+
+    #[test]
+    fn subn_operands_follow_disassembler_order() {
+        let instruction = get_instruction(0x81, 0x27);
+        assert_eq!(format!("{}", instruction), "SUBN V2, V1");
+
+        let bytes = assemble("SUBN V2, V1").unwrap();
+        assert_eq!(bytes, vec![0x81, 0x27]);
+    }
+
+    #[test]
+    fn unknown_mnemonic_error_has_position() {
+        let error = assemble("CLS\n  FOO V1").unwrap_err();
+        match error {
+            AssemblyError::UnknownMnemonic {line: 2, column: 3, ref mnemonic} if mnemonic == "FOO" => (),
+            x => panic!("Expected UnknownMnemonic at 2:3, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn out_of_range_value_error_points_at_operand() {
+        let error = assemble("LD V1, 100").unwrap_err();
+        match error {
+            AssemblyError::ValueOutOfRange {line: 1, column: 8, value: 0x100, maximum: 0xff} => (),
+            x => panic!("Expected ValueOutOfRange at 1:8, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn undefined_label_error_points_at_operand() {
+        let error = assemble("CLS\nJP nowhere").unwrap_err();
+        assert_eq!(error.position(), (2, 4), "Incorrect error position");
+        match error {
+            AssemblyError::UndefinedLabel {ref label, ..} if label == "nowhere" => (),
+            x => panic!("Expected UndefinedLabel, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn duplicate_label_is_an_error() {
+        let error = assemble("here: CLS\nhere: RET").unwrap_err();
+        match error {
+            AssemblyError::DuplicateLabel {line: 2, column: 1, ..} => (),
+            x => panic!("Expected DuplicateLabel at 2:1, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn label_that_looks_like_a_number_is_an_error() {
+        let error = assemble("beef: CLS").unwrap_err();
+        match error {
+            AssemblyError::InvalidLabelName {line: 1, column: 1, ..} => (),
+            x => panic!("Expected InvalidLabelName at 1:1, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn wrong_operands_for_known_mnemonic_is_an_error() {
+        let error = assemble("  SKP 5").unwrap_err();
+        match error {
+            AssemblyError::InvalidOperands {line: 1, column: 3, ..} => (),
+            x => panic!("Expected InvalidOperands at 1:3, instead got {:?}", x),
+        }
+    }
+}
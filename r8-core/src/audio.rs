@@ -0,0 +1,165 @@
+use std::io;
+use std::io::{Write, Seek, SeekFrom};
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+pub const DEFAULT_TONE_FREQUENCY: f32 = 440.0;
+pub const DEFAULT_VOLUME: f32 = 0.25;
+pub const TIMER_TICKS_PER_SECOND: u32 = 60;
+
+const WAV_HEADER_SIZE: u32 = 44;
+const BITS_PER_SAMPLE: u16 = 16;
+const CHANNEL_COUNT: u16 = 1;
+
+// Square wave generator for the buzzer.  The phase carries over between calls so consecutive
+// frames join up without clicks.
+#[derive(Debug, Clone)]
+pub struct ToneGenerator {
+    sample_rate: u32,
+    frequency: f32,
+    volume: f32,
+    phase: f32,
+}
+
+impl ToneGenerator {
+    // Volume is a fraction of the loudest possible tone, from 0.0 to 1.0
+    pub fn new(sample_rate: u32, frequency: f32, volume: f32) -> Self {
+        ToneGenerator {
+            sample_rate,
+            frequency,
+            volume: volume.clamp(0.0, 1.0),
+            phase: 0.0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // Number of samples covering one tick of the sound timer
+    pub fn samples_per_tick(&self) -> usize {
+        (self.sample_rate / TIMER_TICKS_PER_SECOND) as usize
+    }
+
+    pub fn generate(&mut self, active: bool, sample_count: usize) -> Vec<i16> {
+        let amplitude = (i16::MAX as f32 * self.volume) as i16;
+        let phase_step = self.frequency / self.sample_rate as f32;
+
+        let mut samples = Vec::with_capacity(sample_count);
+        for _ in 0..sample_count {
+            let sample = match (active, self.phase < 0.5) {
+                (false, _) => 0,
+                (true, true) => amplitude,
+                (true, false) => -amplitude,
+            };
+
+            samples.push(sample);
+            self.phase = (self.phase + phase_step).fract();
+        }
+
+        samples
+    }
+}
+
+impl Default for ToneGenerator {
+    fn default() -> Self {
+        ToneGenerator::new(DEFAULT_SAMPLE_RATE, DEFAULT_TONE_FREQUENCY, DEFAULT_VOLUME)
+    }
+}
+
+// Writes 16 bit mono PCM samples as a WAV file.  The sizes in the header aren't known until all
+// samples have been written, so they are filled in by `finish()`.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    data_size: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        let block_align = CHANNEL_COUNT * BITS_PER_SAMPLE / 8;
+        let byte_rate = sample_rate * block_align as u32;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(WAV_HEADER_SIZE - 8).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16_u32.to_le_bytes())?;
+        writer.write_all(&1_u16.to_le_bytes())?; // PCM
+        writer.write_all(&CHANNEL_COUNT.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0_u32.to_le_bytes())?;
+
+        Ok(WavWriter {writer, data_size: 0})
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+
+        self.data_size += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(WAV_HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(WAV_HEADER_SIZE as u64 - 4))?;
+        self.writer.write_all(&self.data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn inactive_buzzer_is_silent() {
+        let mut generator = ToneGenerator::default();
+        let samples = generator.generate(false, 100);
+        assert!(samples.iter().all(|x| *x == 0), "Expected only silence");
+    }
+
+    #[test]
+    fn active_buzzer_produces_square_wave_at_frequency() {
+        let mut generator = ToneGenerator::new(8, 2.0, 1.0);
+        let samples = generator.generate(true, 8);
+        let high = i16::MAX;
+        assert_eq!(samples, vec![high, high, -high, -high, high, high, -high, -high]);
+    }
+
+    #[test]
+    fn volume_scales_amplitude() {
+        let mut generator = ToneGenerator::new(8, 2.0, 0.5);
+        let samples = generator.generate(true, 1);
+        assert_eq!(samples[0], i16::MAX / 2, "Incorrect amplitude");
+    }
+
+    #[test]
+    fn samples_per_tick_covers_one_sixtieth_of_a_second() {
+        let generator = ToneGenerator::default();
+        assert_eq!(generator.samples_per_tick(), 735, "Incorrect samples per tick");
+    }
+
+    #[test]
+    fn wav_header_sizes_are_filled_in_when_finished() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 22_050).unwrap();
+        writer.write_samples(&[1, -1, 2]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 6, "Incorrect file size");
+        assert_eq!(&bytes[0..4], b"RIFF", "Incorrect RIFF tag");
+        assert_eq!(&bytes[4..8], &(36_u32 + 6).to_le_bytes(), "Incorrect RIFF size");
+        assert_eq!(&bytes[24..28], &22_050_u32.to_le_bytes(), "Incorrect sample rate");
+        assert_eq!(&bytes[40..44], &6_u32.to_le_bytes(), "Incorrect data size");
+        assert_eq!(&bytes[44..46], &1_i16.to_le_bytes(), "Incorrect first sample");
+    }
+}
@@ -0,0 +1,246 @@
+use custom_error::custom_error;
+
+// The section of a config file that gives key bindings, which the key map reads instead of the settings
+pub const KEY_MAP_SECTION: &str = "keys";
+
+custom_error!{pub ConfigError
+    Syntax {line: usize, reason: String} = "line {line}: {reason}",
+    UnknownSetting {origin: String, name: String} = "{origin}: unknown setting '{name}'",
+    InvalidValue {key: String, origin: String, reason: String} = "{key} from {origin}: {reason}",
+}
+
+// The subset of TOML that config files use: `[section]` headers, and keys set to
+// strings, numbers, booleans or single line arrays of them
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<ConfigValue>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigEntry {
+    pub section: String, // Empty for keys before the first section
+    pub key: String,
+    pub value: ConfigValue,
+    pub line: usize,
+}
+
+impl ConfigValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ConfigValue::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    // A single value counts as an array of one, so `5 = "W"` and `5 = ["W"]` mean the same
+    pub fn as_array(&self) -> Vec<&ConfigValue> {
+        match self {
+            ConfigValue::Array(values) => values.iter().collect(),
+            value => vec![value],
+        }
+    }
+}
+
+// Writes the value back out the way a config file would give it
+pub fn format_config_value(value: &ConfigValue) -> String {
+    match value {
+        ConfigValue::String(text) => format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\t', "\\t")),
+        ConfigValue::Integer(number) => number.to_string(),
+        ConfigValue::Float(number) => format!("{:?}", number),
+        ConfigValue::Boolean(value) => value.to_string(),
+        ConfigValue::Array(values) => format!("[{}]", values.iter().map(format_config_value).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+pub fn parse_config(text: &str) -> Result<Vec<ConfigEntry>, ConfigError> {
+    let mut entries = Vec::new();
+    let mut section = String::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            match line.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+                Some(name) if !name.trim().is_empty() => section = name.trim().to_owned(),
+                _ => return Err(syntax_error(line_number, "expected a section name such as [keys]")),
+            }
+
+            continue;
+        }
+
+        let separator = line.find('=').ok_or_else(|| syntax_error(line_number, "expected key = value"))?;
+        let key = parse_key(line[..separator].trim()).ok_or_else(|| syntax_error(line_number, "invalid key"))?;
+        let mut rest = line[separator + 1..].trim();
+        let value = parse_value(&mut rest).map_err(|error| syntax_error(line_number, &error))?;
+        if !rest.trim().is_empty() {
+            return Err(syntax_error(line_number, &format!("unexpected '{}' after the value", rest.trim())));
+        }
+
+        entries.push(ConfigEntry {section: section.clone(), key, value, line: line_number});
+    }
+
+    Ok(entries)
+}
+
+fn syntax_error(line: usize, reason: &str) -> ConfigError {
+    ConfigError::Syntax {line, reason: reason.to_owned()}
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut is_in_string = false;
+    let mut previous = ' ';
+    for (index, character) in line.char_indices() {
+        match character {
+            '"' if previous != '\\' => is_in_string = !is_in_string,
+            '#' if !is_in_string => return &line[..index],
+            _ => (),
+        }
+
+        previous = character;
+    }
+
+    line
+}
+
+fn parse_key(text: &str) -> Option<String> {
+    if let Some(quoted) = text.strip_prefix('"').and_then(|x| x.strip_suffix('"')) {
+        return Some(quoted.to_owned());
+    }
+
+    match !text.is_empty() && text.chars().all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '-' || x == '.') {
+        true => Some(text.to_owned()),
+        false => None,
+    }
+}
+
+// Reads one value from the start of the text, leaving whatever follows it
+fn parse_value(text: &mut &str) -> Result<ConfigValue, String> {
+    if let Some(rest) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut characters = rest.char_indices();
+        while let Some((index, character)) = characters.next() {
+            match character {
+                '"' => {
+                    *text = &rest[index + 1..];
+                    return Ok(ConfigValue::String(value));
+                }
+
+                '\\' => match characters.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, escaped)) => value.push(escaped),
+                    None => break,
+                },
+
+                _ => value.push(character),
+            }
+        }
+
+        return Err("string is missing its closing quote".to_owned());
+    }
+
+    if let Some(rest) = text.strip_prefix('[') {
+        let mut values = Vec::new();
+        *text = rest.trim_start();
+        loop {
+            if let Some(rest) = text.strip_prefix(']') {
+                *text = rest;
+                return Ok(ConfigValue::Array(values));
+            }
+
+            values.push(parse_value(text)?);
+            *text = text.trim_start();
+            match text.strip_prefix(',') {
+                Some(rest) => *text = rest.trim_start(),
+                None if text.starts_with(']') => (),
+                None => return Err("expected , or ] in array".to_owned()),
+            }
+        }
+    }
+
+    let end = text.find([',', ']', ' ', '\t']).unwrap_or(text.len());
+    let word = &text[..end];
+    *text = &text[end..];
+    match word {
+        "true" => Ok(ConfigValue::Boolean(true)),
+        "false" => Ok(ConfigValue::Boolean(false)),
+        _ => {
+            let number = word.replace('_', "");
+            if let Some(hex) = number.strip_prefix("0x") {
+                return i64::from_str_radix(hex, 16).map(ConfigValue::Integer).map_err(|_| format!("'{}' is not a number", word));
+            }
+
+            number.parse::<i64>().map(ConfigValue::Integer)
+                .or_else(|_| number.parse::<f64>().map(ConfigValue::Float))
+                .map_err(|_| format!("'{}' is not a value, strings need quotes", word))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn syntax_error_line(text: &str) -> usize {
+        match parse_config(text).unwrap_err() {
+            ConfigError::Syntax {line, ..} => line,
+            x => panic!("Expected Syntax, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn keys_are_read_into_their_sections() {
+        let entries = parse_config("platform = \"schip\"\n\n[window]\nscale = 2.5\n[audio]\nvolume = 0x10\n").unwrap();
+        assert_eq!(entries.len(), 3, "Incorrect number of entries");
+        assert_eq!(entries[0], ConfigEntry {section: String::new(), key: "platform".to_owned(), value: ConfigValue::String("schip".to_owned()), line: 1}, "Incorrect first entry");
+        assert_eq!(entries[1], ConfigEntry {section: "window".to_owned(), key: "scale".to_owned(), value: ConfigValue::Float(2.5), line: 4}, "Incorrect second entry");
+        assert_eq!(entries[2], ConfigEntry {section: "audio".to_owned(), key: "volume".to_owned(), value: ConfigValue::Integer(16), line: 6}, "Incorrect third entry");
+    }
+
+    #[test]
+    fn comments_inside_strings_are_kept() {
+        let entries = parse_config("# A comment\nkeymap = \"keys # 2.toml\" # Another comment\ntitle = \"say \\\"#hi\\\"\"").unwrap();
+        assert_eq!(entries[0].value, ConfigValue::String("keys # 2.toml".to_owned()), "Incorrect string with a #");
+        assert_eq!(entries[1].value, ConfigValue::String("say \"#hi\"".to_owned()), "Incorrect string with escaped quotes");
+    }
+
+    #[test]
+    fn arrays_can_mix_values() {
+        let entries = parse_config("5 = [\"W\", \"Up\" , 3, true,[]] # Comment").unwrap();
+        let expected = ConfigValue::Array(vec![
+            ConfigValue::String("W".to_owned()),
+            ConfigValue::String("Up".to_owned()),
+            ConfigValue::Integer(3),
+            ConfigValue::Boolean(true),
+            ConfigValue::Array(Vec::new()),
+        ]);
+
+        assert_eq!(entries[0].value, expected, "Incorrect array");
+        assert_eq!(ConfigValue::Integer(3).as_array(), vec![&ConfigValue::Integer(3)], "A single value should be an array of one");
+    }
+
+    #[test]
+    fn formatted_values_can_be_read_back() {
+        let value = ConfigValue::Array(vec![ConfigValue::String("a \"b\"\n".to_owned()), ConfigValue::Float(1.0), ConfigValue::Integer(-2)]);
+        let entries = parse_config(&format!("x = {}", format_config_value(&value))).unwrap();
+        assert_eq!(entries[0].value, value, "Incorrect value after formatting");
+    }
+
+    #[test]
+    fn errors_give_the_line() {
+        assert_eq!(syntax_error_line("a = 1\n[]\n"), 2, "Expected empty section on line 2");
+        assert_eq!(syntax_error_line("a = 1\n\nb\n"), 3, "Expected missing = on line 3");
+        assert_eq!(syntax_error_line("a = \"abc # not a comment"), 1, "Expected unterminated string on line 1");
+        assert_eq!(syntax_error_line("[keys]\n1 = [\"A\" \"B\"]"), 2, "Expected missing comma on line 2");
+        assert_eq!(syntax_error_line("a = 1\nb = schip"), 2, "Expected unquoted string on line 2");
+        assert_eq!(syntax_error_line("a = 1 2"), 1, "Expected trailing text on line 1");
+        assert_eq!(syntax_error_line("a b = 1"), 1, "Expected invalid key on line 1");
+    }
+}
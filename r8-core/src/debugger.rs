@@ -0,0 +1,537 @@
+use std::fmt;
+use custom_error::custom_error;
+use crate::{Hardware, Instruction, Register};
+
+custom_error!{pub DebuggerError
+    InvalidNumber {text: String} = "'{text}' is not a valid number",
+    InvalidOperand {text: String} = "'{text}' is not a register or number",
+    InvalidCondition {text: String} = "'{text}' is not a valid condition, expected something like 'V3 == 0x10'",
+    InvalidRange {text: String} = "'{text}' is not a valid address range, expected something like '0x300-0x30f'",
+    UnknownInstruction {name: String} = "'{name}' is not a kind of instruction, expected something like 'Call' or 'DrawSprite'",
+}
+
+// Every `Instruction` variant, which breakpoints and trace filters can name
+const INSTRUCTION_NAMES: [&str; 46] = [
+    "AddFromRegister", "AddFromValue", "And", "Call", "ClearDisplay", "DisableHighResolution", "DrawSprite",
+    "EnableHighResolution", "Exit", "JumpToAddress", "JumpToMachineCode", "LoadAddressIntoIRegister", "LoadBcdValue",
+    "LoadBigSpriteLocation", "LoadFromFlags", "LoadFromKeyPress", "LoadFromMemory", "LoadFromRegister", "LoadFromValue",
+    "LoadIntoFlags", "LoadIntoMemory", "LoadLongAddressIntoIRegister", "LoadAudioPattern", "LoadRegisterRange",
+    "LoadSpriteLocation", "Or", "Return", "SaveRegisterRange", "ScrollDown", "ScrollLeft", "ScrollRight", "ScrollUp",
+    "SelectPlanes", "SetPitch", "SetRandom", "ShiftLeft", "ShiftRight", "SkipIfEqual", "SkipIfKeyNotPressed",
+    "SkipIfKeyPressed", "SkipIfNotEqual", "SkipIfRegistersEqual", "SkipIfRegistersNotEqual", "Subtract", "Unknown", "Xor",
+];
+
+// Something whose value can be compared in a conditional breakpoint
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Operand {
+    Register(u8),
+    I,
+    DelayTimer,
+    SoundTimer,
+    ProgramCounter,
+    Value(u16),
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Condition {
+    pub left: Operand,
+    pub comparison: Comparison,
+    pub right: Operand,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Breakpoint {
+    Address(u16),
+    Condition(Condition),
+    Opcode(String), // Name of an `Instruction` variant, such as `Call` or `DrawSprite`
+}
+
+// Inclusive range of memory addresses to watch for writes
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum BreakReason {
+    Breakpoint(Breakpoint),
+    MemoryWrite {watchpoint: Watchpoint, start: u16, end: u16},
+}
+
+// Decides whether execution should pause before the next instruction runs.  Frontends call
+// `check()` before every instruction and stop running if it returns a reason.
+#[derive(Default, Debug, Clone)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    resuming: bool,
+    true_conditions: Vec<Condition>, // Conditions that were true at the last check
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|x| x != breakpoint);
+        self.breakpoints.len() != count
+    }
+
+    // Adds a breakpoint at the address if there isn't one, otherwise removes it.  Returns true if
+    // the breakpoint was added.
+    pub fn toggle_address_breakpoint(&mut self, address: u16) -> bool {
+        let breakpoint = Breakpoint::Address(address);
+        match self.remove_breakpoint(&breakpoint) {
+            true => false,
+            false => {
+                self.breakpoints.push(breakpoint);
+                true
+            }
+        }
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|x| x != watchpoint);
+        self.watchpoints.len() != count
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+
+    // Lets the next instruction run without being checked, so continuing from a breakpoint
+    // doesn't immediately stop on the same breakpoint again
+    pub fn resume(&mut self) {
+        self.resuming = true;
+    }
+
+    pub fn check(&mut self, hardware: &Hardware) -> Option<BreakReason> {
+        // Conditions only break when they become true, otherwise continuing would stop again on
+        // the next instruction for as long as the condition held
+        let true_conditions = self.breakpoints.iter()
+            .filter_map(|x| match x {
+                Breakpoint::Condition(condition) if condition.evaluate(hardware) => Some(condition.clone()),
+                _ => None,
+            })
+            .collect();
+
+        let previously_true_conditions = std::mem::replace(&mut self.true_conditions, true_conditions);
+        if self.resuming {
+            self.resuming = false;
+            return None;
+        }
+
+        let instruction = hardware.get_current_instruction();
+        for breakpoint in &self.breakpoints {
+            let is_hit = match breakpoint {
+                Breakpoint::Address(address) => hardware.program_counter == *address,
+                Breakpoint::Condition(condition) => {
+                    self.true_conditions.contains(condition) && !previously_true_conditions.contains(condition)
+                }
+
+                Breakpoint::Opcode(name) => instruction_name(&instruction).eq_ignore_ascii_case(name),
+            };
+
+            if is_hit {
+                return Some(BreakReason::Breakpoint(breakpoint.clone()));
+            }
+        }
+
+        if let Some((start, end)) = memory_written_by(&instruction, hardware) {
+            for watchpoint in &self.watchpoints {
+                if start <= watchpoint.end && end >= watchpoint.start {
+                    return Some(BreakReason::MemoryWrite {watchpoint: *watchpoint, start, end});
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl Condition {
+    // Parses conditions such as `V3 == 0x10`, `I >= 0x300` or `DT != 0`
+    pub fn parse(text: &str) -> Result<Condition, DebuggerError> {
+        let invalid = || DebuggerError::InvalidCondition {text: text.to_owned()};
+
+        // Two character operators need to be checked first so `<=` isn't read as `<`
+        let operators = [
+            ("==", Comparison::Equal),
+            ("!=", Comparison::NotEqual),
+            ("<=", Comparison::LessThanOrEqual),
+            (">=", Comparison::GreaterThanOrEqual),
+            ("<", Comparison::LessThan),
+            (">", Comparison::GreaterThan),
+        ];
+
+        let (index, operator, comparison) = operators.iter()
+            .filter_map(|(operator, comparison)| text.find(operator).map(|index| (index, *operator, *comparison)))
+            .next()
+            .ok_or_else(invalid)?;
+
+        let left = text[..index].trim();
+        let right = text[index + operator.len()..].trim();
+        if left.is_empty() || right.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Condition {
+            left: Operand::parse(left)?,
+            comparison,
+            right: Operand::parse(right)?,
+        })
+    }
+
+    pub fn evaluate(&self, hardware: &Hardware) -> bool {
+        let left = self.left.value(hardware);
+        let right = self.right.value(hardware);
+        match self.comparison {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::LessThan => left < right,
+            Comparison::LessThanOrEqual => left <= right,
+            Comparison::GreaterThan => left > right,
+            Comparison::GreaterThanOrEqual => left >= right,
+        }
+    }
+}
+
+impl Operand {
+    pub fn parse(text: &str) -> Result<Operand, DebuggerError> {
+        let upper = text.trim().to_uppercase();
+        match upper.as_ref() {
+            "I" => Ok(Operand::I),
+            "DT" => Ok(Operand::DelayTimer),
+            "ST" => Ok(Operand::SoundTimer),
+            "PC" => Ok(Operand::ProgramCounter),
+            _ if upper.starts_with('V') && upper.len() == 2 => {
+                u8::from_str_radix(&upper[1..], 16)
+                    .map(Operand::Register)
+                    .map_err(|_| DebuggerError::InvalidOperand {text: text.to_owned()})
+            }
+
+            _ => parse_address(text).map(Operand::Value).map_err(|_| DebuggerError::InvalidOperand {text: text.to_owned()}),
+        }
+    }
+
+    pub fn value(&self, hardware: &Hardware) -> u16 {
+        match self {
+            Operand::Register(number) => hardware.gen_registers[*number as usize] as u16,
+            Operand::I => hardware.i_register,
+            Operand::DelayTimer => hardware.delay_timer as u16,
+            Operand::SoundTimer => hardware.sound_timer as u16,
+            Operand::ProgramCounter => hardware.program_counter,
+            Operand::Value(value) => *value,
+        }
+    }
+}
+
+impl Breakpoint {
+    // Checks the name so a typo gives an error instead of a breakpoint that never hits
+    pub fn parse_opcode(name: &str) -> Result<Breakpoint, DebuggerError> {
+        parse_instruction_name(name).map(Breakpoint::Opcode)
+    }
+}
+
+impl Watchpoint {
+    // Parses either a single address or an inclusive range such as `0x300-0x30f`
+    pub fn parse(text: &str) -> Result<Watchpoint, DebuggerError> {
+        let invalid = || DebuggerError::InvalidRange {text: text.to_owned()};
+        let (start, end) = match text.find('-') {
+            Some(index) => (&text[..index], &text[index + 1..]),
+            None => (text, text),
+        };
+
+        let start = parse_address(start).map_err(|_| invalid())?;
+        let end = parse_address(end).map_err(|_| invalid())?;
+        match start <= end {
+            true => Ok(Watchpoint {start, end}),
+            false => Err(invalid()),
+        }
+    }
+}
+
+// Accepts hex with a `0x` prefix, otherwise decimal
+pub fn parse_address(text: &str) -> Result<u16, DebuggerError> {
+    let text = text.trim();
+    let result = match text.starts_with("0x") || text.starts_with("0X") {
+        true => u16::from_str_radix(&text[2..], 16),
+        false => text.parse(),
+    };
+
+    result.map_err(|_| DebuggerError::InvalidNumber {text: text.to_owned()})
+}
+
+// Matches any capitalisation, returning the variant's name as written in `Instruction`
+pub fn parse_instruction_name(name: &str) -> Result<String, DebuggerError> {
+    INSTRUCTION_NAMES.iter()
+        .find(|x| x.eq_ignore_ascii_case(name.trim()))
+        .map(|x| x.to_string())
+        .ok_or_else(|| DebuggerError::UnknownInstruction {name: name.trim().to_owned()})
+}
+
+// Name of the instruction's variant, such as `DrawSprite`
+pub(crate) fn instruction_name(instruction: &Instruction) -> String {
+    let debug = format!("{:?}", instruction);
+    debug.split(|c: char| !c.is_alphanumeric()).next().unwrap_or_default().to_owned()
+}
+
+// Inclusive range of memory the instruction will write to if it is executed next
+fn memory_written_by(instruction: &Instruction, hardware: &Hardware) -> Option<(u16, u16)> {
+    let start = hardware.i_register;
+    let length = match instruction {
+        Instruction::LoadBcdValue {..} => 3,
+        Instruction::LoadIntoMemory {last_register: Register::General(last)} => *last as u16 + 1,
+        Instruction::SaveRegisterRange {first_register: Register::General(first), last_register: Register::General(last)} => {
+            (*first as i16 - *last as i16).unsigned_abs() + 1
+        }
+
+        _ => return None,
+    };
+
+    Some((start, start.saturating_add(length - 1)))
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Register(number) => write!(f, "V{:X}", number),
+            Operand::I => write!(f, "I"),
+            Operand::DelayTimer => write!(f, "DT"),
+            Operand::SoundTimer => write!(f, "ST"),
+            Operand::ProgramCounter => write!(f, "PC"),
+            Operand::Value(value) => write!(f, "{:#x}", value),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operator = match self.comparison {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::LessThan => "<",
+            Comparison::LessThanOrEqual => "<=",
+            Comparison::GreaterThan => ">",
+            Comparison::GreaterThanOrEqual => ">=",
+        };
+
+        write!(f, "{} {} {}", self.left, operator, self.right)
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Address(address) => write!(f, "at {:#x}", address),
+            Breakpoint::Condition(condition) => write!(f, "if {}", condition),
+            Breakpoint::Opcode(name) => write!(f, "on {}", name),
+        }
+    }
+}
+
+impl fmt::Display for BreakReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BreakReason::Breakpoint(Breakpoint::Address(address)) => write!(f, "breakpoint at {:#x}", address),
+            BreakReason::Breakpoint(Breakpoint::Condition(condition)) => write!(f, "condition {} is true", condition),
+            BreakReason::Breakpoint(Breakpoint::Opcode(name)) => write!(f, "{} instruction", name),
+            BreakReason::MemoryWrite {watchpoint, start, end} => {
+                write!(f, "write to {:#x}-{:#x} inside watched range {:#x}-{:#x}", start, end, watchpoint.start, watchpoint.end)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hardware_with_program(bytes: &[u8]) -> Hardware {
+        let mut hardware = Hardware::new();
+        hardware.memory[0x200..0x200 + bytes.len()].copy_from_slice(bytes);
+        hardware.program_counter = 0x200;
+        hardware
+    }
+
+    #[test]
+    fn can_parse_conditions() {
+        let condition = Condition::parse("V3 == 0x10").unwrap();
+        assert_eq!(condition, Condition {left: Operand::Register(3), comparison: Comparison::Equal, right: Operand::Value(0x10)});
+
+        let condition = Condition::parse("i>=768").unwrap();
+        assert_eq!(condition, Condition {left: Operand::I, comparison: Comparison::GreaterThanOrEqual, right: Operand::Value(768)});
+
+        let condition = Condition::parse("DT < vA").unwrap();
+        assert_eq!(condition, Condition {left: Operand::DelayTimer, comparison: Comparison::LessThan, right: Operand::Register(0xa)});
+    }
+
+    #[test]
+    fn invalid_conditions_are_errors() {
+        match Condition::parse("V3 = 1").unwrap_err() {
+            DebuggerError::InvalidCondition {..} => (),
+            x => panic!("Expected InvalidCondition, instead got {:?}", x),
+        }
+
+        match Condition::parse("VG == 1").unwrap_err() {
+            DebuggerError::InvalidOperand {..} => (),
+            x => panic!("Expected InvalidOperand, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn can_parse_watchpoints() {
+        assert_eq!(Watchpoint::parse("0x300-0x30f").unwrap(), Watchpoint {start: 0x300, end: 0x30f}, "Incorrect range");
+        assert_eq!(Watchpoint::parse("0x300").unwrap(), Watchpoint {start: 0x300, end: 0x300}, "Incorrect single address");
+
+        match Watchpoint::parse("0x30f-0x300").unwrap_err() {
+            DebuggerError::InvalidRange {..} => (),
+            x => panic!("Expected InvalidRange, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn breaks_at_address() {
+        let mut hardware = hardware_with_program(&[0x00, 0xe0, 0x00, 0xe0]);
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(Breakpoint::Address(0x202));
+
+        assert_eq!(debugger.check(&hardware), None, "Should not break before the address");
+
+        hardware.program_counter = 0x202;
+        assert_eq!(debugger.check(&hardware), Some(BreakReason::Breakpoint(Breakpoint::Address(0x202))), "Incorrect break reason");
+    }
+
+    #[test]
+    fn resume_skips_one_check() {
+        let hardware = hardware_with_program(&[0x00, 0xe0]);
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(Breakpoint::Address(0x200));
+
+        debugger.resume();
+        assert_eq!(debugger.check(&hardware), None, "Should not break right after resuming");
+        assert!(debugger.check(&hardware).is_some(), "Should break on the following check");
+    }
+
+    #[test]
+    fn breaks_on_condition() {
+        let mut hardware = hardware_with_program(&[0x00, 0xe0]);
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(Breakpoint::Condition(Condition::parse("V3 == 0x10").unwrap()));
+
+        assert_eq!(debugger.check(&hardware), None, "Should not break while the condition is false");
+
+        hardware.gen_registers[3] = 0x10;
+        assert!(debugger.check(&hardware).is_some(), "Should break once the condition is true");
+
+        debugger.resume();
+        assert_eq!(debugger.check(&hardware), None, "Should not break right after resuming");
+        assert_eq!(debugger.check(&hardware), None, "Should not break again while the condition stays true");
+
+        hardware.gen_registers[3] = 0;
+        assert_eq!(debugger.check(&hardware), None, "Should not break once the condition is false");
+
+        hardware.gen_registers[3] = 0x10;
+        assert!(debugger.check(&hardware).is_some(), "Should break when the condition becomes true again");
+    }
+
+    #[test]
+    fn opcode_breakpoints_need_a_known_instruction() {
+        assert_eq!(Breakpoint::parse_opcode("drawsprite").unwrap(), Breakpoint::Opcode("DrawSprite".to_owned()), "Incorrect breakpoint");
+        match Breakpoint::parse_opcode("DrawSprit").unwrap_err() {
+            DebuggerError::UnknownInstruction {..} => (),
+            x => panic!("Expected UnknownInstruction, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn every_instruction_has_a_known_name() {
+        for opcode in 0..=0xffff_u16 {
+            let name = instruction_name(&crate::get_instruction((opcode >> 8) as u8, opcode as u8));
+            assert!(INSTRUCTION_NAMES.contains(&name.as_str()), "{} is missing from the instruction names", name);
+        }
+    }
+
+    #[test]
+    fn breaks_on_opcode_class() {
+        // 200: CLS / 202: CALL 300
+        let mut hardware = hardware_with_program(&[0x00, 0xe0, 0x23, 0x00]);
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(Breakpoint::Opcode("call".to_owned()));
+
+        assert_eq!(debugger.check(&hardware), None, "Should not break on other instructions");
+
+        hardware.program_counter = 0x202;
+        assert!(debugger.check(&hardware).is_some(), "Should break on the call");
+    }
+
+    #[test]
+    fn breaks_before_write_to_watched_memory() {
+        // 200: LD [I], V3
+        let mut hardware = hardware_with_program(&[0xf3, 0x55]);
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint {start: 0x303, end: 0x310});
+
+        hardware.i_register = 0x2f0;
+        assert_eq!(debugger.check(&hardware), None, "Should not break on writes outside the range");
+
+        hardware.i_register = 0x300;
+        let expected = BreakReason::MemoryWrite {watchpoint: Watchpoint {start: 0x303, end: 0x310}, start: 0x300, end: 0x303};
+        assert_eq!(debugger.check(&hardware), Some(expected), "Incorrect break reason");
+    }
+
+    #[test]
+    fn reads_do_not_trigger_watchpoints() {
+        // 200: LD V3, [I]
+        let mut hardware = hardware_with_program(&[0xf3, 0x65]);
+        hardware.i_register = 0x300;
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint {start: 0x300, end: 0x310});
+
+        assert_eq!(debugger.check(&hardware), None, "Reads should not trigger watchpoints");
+    }
+
+    #[test]
+    fn can_toggle_address_breakpoint() {
+        let mut debugger = Debugger::new();
+        assert!(debugger.toggle_address_breakpoint(0x200), "Expected breakpoint to be added");
+        assert_eq!(debugger.breakpoints().len(), 1, "Incorrect breakpoint count");
+        assert!(!debugger.toggle_address_breakpoint(0x200), "Expected breakpoint to be removed");
+        assert_eq!(debugger.breakpoints().len(), 0, "Incorrect breakpoint count");
+    }
+}
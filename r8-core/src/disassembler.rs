@@ -0,0 +1,310 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use crate::{Instruction, get_instruction_at, serialize_instruction_bytes};
+
+const PROGRAM_START_ADDRESS: usize = 0x200;
+const DATA_BYTES_PER_LINE: usize = 8;
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum ListingEntry {
+    Instruction { address: u16, instruction: Instruction, bytes: Vec<u8> },
+    Data { address: u16, bytes: Vec<u8> },
+}
+
+#[derive(Debug, Clone)]
+pub struct Listing {
+    pub entries: Vec<ListingEntry>,
+    pub labels: BTreeMap<u16, String>,
+}
+
+impl ListingEntry {
+    pub fn address(&self) -> u16 {
+        match self {
+            ListingEntry::Instruction {address, ..} => *address,
+            ListingEntry::Data {address, ..} => *address,
+        }
+    }
+}
+
+impl Listing {
+    pub fn label_for(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(|x| x.as_ref())
+    }
+
+    fn address_operand(&self, address: u16) -> String {
+        match self.label_for(address) {
+            Some(label) => label.to_owned(),
+            None => format!("{:x}", address),
+        }
+    }
+
+    fn format_instruction(&self, instruction: &Instruction) -> String {
+        match instruction {
+            Instruction::Call {address} => format!("CALL {}", self.address_operand(*address)),
+            Instruction::JumpToAddress {address, add_register_0: true} => format!("JP v0, {}", self.address_operand(*address)),
+            Instruction::JumpToAddress {address, add_register_0: false} => format!("JP {}", self.address_operand(*address)),
+            Instruction::LoadAddressIntoIRegister {address} => format!("LD I, {}", self.address_operand(*address)),
+            Instruction::LoadLongAddressIntoIRegister {address} => format!("LDL I, {}", self.address_operand(*address)),
+            x => format!("{}", x),
+        }
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in &self.entries {
+            if let Some(label) = self.label_for(entry.address()) {
+                writeln!(f, "{}:", label)?;
+            }
+
+            match entry {
+                ListingEntry::Instruction {instruction, bytes, ..} => {
+                    // Some opcodes have bits the decoder ignores (e.g. the last nibble of 5xy0), so
+                    // they are written out as raw words to keep the listing byte for byte identical
+                    if serialize_instruction_bytes(instruction.clone()).ok().as_ref() == Some(bytes) {
+                        writeln!(f, "    {}", self.format_instruction(instruction))?;
+                    } else {
+                        let words = bytes.chunks(2).map(|x| format!("0x{:0>2x}{:0>2x}", x[0], x[1])).collect::<Vec<String>>();
+                        writeln!(f, "    dw {} ; {}", words.join(", "), instruction)?;
+                    }
+                }
+
+                ListingEntry::Data {bytes, ..} => {
+                    let values = bytes.iter().map(|x| format!("0x{:0>2x}", x)).collect::<Vec<String>>();
+                    writeln!(f, "    db {}", values.join(", "))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn disassemble(rom: &[u8]) -> Listing {
+    let end = PROGRAM_START_ADDRESS + rom.len();
+    let mut claimed = vec![false; rom.len()];
+    let mut instruction_starts = BTreeSet::new();
+    let mut call_targets = BTreeSet::new();
+    let mut jump_targets = BTreeSet::new();
+    let mut data_targets = BTreeSet::new();
+
+    // Follow every path execution can take from the entry point.  Anything never reached is
+    // considered to be sprite or other data.
+    let mut pending = vec![PROGRAM_START_ADDRESS];
+    while let Some(address) = pending.pop() {
+        if address < PROGRAM_START_ADDRESS || address + 2 > end {
+            continue;
+        }
+
+        let offset = address - PROGRAM_START_ADDRESS;
+        let instruction = get_instruction_at(rom, offset);
+        let size = instruction.size() as usize;
+        if address + size > end || claimed[offset..offset + size].iter().any(|x| *x) {
+            continue;
+        }
+
+        match instruction {
+            Instruction::Unknown {..} | Instruction::JumpToMachineCode {..} => continue,
+            _ => (),
+        }
+
+        for byte in &mut claimed[offset..offset + size] {
+            *byte = true;
+        }
+
+        instruction_starts.insert(address);
+
+        match instruction {
+            Instruction::JumpToAddress {address: target, add_register_0: false} => {
+                jump_targets.insert(target as usize);
+                pending.push(target as usize);
+            }
+
+            // The final address isn't known until V0 is, so there is nothing to follow
+            Instruction::JumpToAddress {add_register_0: true, ..} => (),
+
+            Instruction::Call {address: target} => {
+                call_targets.insert(target as usize);
+                pending.push(target as usize);
+                pending.push(address + 2);
+            }
+
+            Instruction::Return | Instruction::Exit => (),
+
+            Instruction::SkipIfEqual {..} |
+            Instruction::SkipIfNotEqual {..} |
+            Instruction::SkipIfRegistersEqual {..} |
+            Instruction::SkipIfRegistersNotEqual {..} |
+            Instruction::SkipIfKeyPressed {..} |
+            Instruction::SkipIfKeyNotPressed {..} => {
+                // The skipped instruction may be a four byte long load
+                let skipped_size = get_instruction_at(rom, offset + 2).size() as usize;
+                pending.push(address + 2);
+                pending.push(address + 2 + skipped_size);
+            }
+
+            Instruction::LoadAddressIntoIRegister {address: target} |
+            Instruction::LoadLongAddressIntoIRegister {address: target} => {
+                data_targets.insert(target as usize);
+                pending.push(address + size);
+            }
+
+            _ => pending.push(address + size),
+        }
+    }
+
+    // Labels can only be placed at the start of an instruction or on a data byte
+    let mut labels = BTreeMap::new();
+    let targets = [(&data_targets, "data"), (&jump_targets, "label"), (&call_targets, "sub")];
+    for (addresses, prefix) in targets.iter() {
+        for address in addresses.iter() {
+            if *address < PROGRAM_START_ADDRESS || *address >= end {
+                continue;
+            }
+
+            if instruction_starts.contains(address) || !claimed[*address - PROGRAM_START_ADDRESS] {
+                labels.insert(*address as u16, format!("{}_{:0>3x}", prefix, address));
+            }
+        }
+    }
+
+    let mut entries = Vec::new();
+    let mut address = PROGRAM_START_ADDRESS;
+    while address < end {
+        let offset = address - PROGRAM_START_ADDRESS;
+        if instruction_starts.contains(&address) {
+            let instruction = get_instruction_at(rom, offset);
+            let size = instruction.size() as usize;
+            let bytes = rom[offset..offset + size].to_vec();
+            entries.push(ListingEntry::Instruction {address: address as u16, instruction, bytes});
+            address += size;
+            continue;
+        }
+
+        let mut bytes = vec![rom[offset]];
+        while bytes.len() < DATA_BYTES_PER_LINE {
+            let next = address + bytes.len();
+            if next >= end || claimed[next - PROGRAM_START_ADDRESS] || labels.contains_key(&(next as u16)) {
+                break;
+            }
+
+            bytes.push(rom[next - PROGRAM_START_ADDRESS]);
+        }
+
+        let length = bytes.len();
+        entries.push(ListingEntry::Data {address: address as u16, bytes});
+        address += length;
+    }
+
+    Listing {entries, labels}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble, Register};
+
+    #[test]
+    fn reachable_instructions_are_code_and_the_rest_is_data() {
+        // 200: LD I, 206 / 202: DRW V0, V0, 2 / 204: JP 204 / 206: sprite data
+        let rom = [0xa2, 0x06, 0xd0, 0x02, 0x12, 0x04, 0xf0, 0x90];
+        let listing = disassemble(&rom);
+
+        let expected = vec![
+            ListingEntry::Instruction {address: 0x200, instruction: Instruction::LoadAddressIntoIRegister {address: 0x206}, bytes: vec![0xa2, 0x06]},
+            ListingEntry::Instruction {
+                address: 0x202,
+                instruction: Instruction::DrawSprite {x_register: Register::General(0), y_register: Register::General(0), height: 2},
+                bytes: vec![0xd0, 0x02],
+            },
+            ListingEntry::Instruction {address: 0x204, instruction: Instruction::JumpToAddress {address: 0x204, add_register_0: false}, bytes: vec![0x12, 0x04]},
+            ListingEntry::Data {address: 0x206, bytes: vec![0xf0, 0x90]},
+        ];
+
+        assert_eq!(listing.entries, expected);
+    }
+
+    #[test]
+    fn labels_are_generated_for_jump_call_and_data_targets() {
+        // 200: CALL 206 / 202: JP 202 / 204: data / 206: LD I, 204 / 208: RET
+        let rom = [0x22, 0x06, 0x12, 0x02, 0x3c, 0x3c, 0xa2, 0x04, 0x00, 0xee];
+        let listing = disassemble(&rom);
+
+        assert_eq!(listing.label_for(0x202), Some("label_202"), "Incorrect jump label");
+        assert_eq!(listing.label_for(0x204), Some("data_204"), "Incorrect data label");
+        assert_eq!(listing.label_for(0x206), Some("sub_206"), "Incorrect call label");
+        assert_eq!(listing.label_for(0x200), None, "Unexpected label at entry point");
+    }
+
+    #[test]
+    fn both_paths_of_a_skip_are_followed() {
+        // 200: SE V0, 0 / 202: JP 206 / 204: JP 208 / 206: JP 206 / 208: JP 208
+        let rom = [0x30, 0x00, 0x12, 0x06, 0x12, 0x08, 0x12, 0x06, 0x12, 0x08];
+        let listing = disassemble(&rom);
+
+        let instructions = listing.entries.iter()
+            .filter(|x| matches!(x, ListingEntry::Instruction {..}))
+            .count();
+
+        assert_eq!(instructions, 5, "Expected every instruction to be reached");
+    }
+
+    #[test]
+    fn listing_text_uses_labels() {
+        let rom = [0x22, 0x04, 0x12, 0x02, 0x00, 0xee];
+        let listing = disassemble(&rom);
+
+        let expected = "    CALL sub_204\nlabel_202:\n    JP label_202\nsub_204:\n    RET\n";
+        assert_eq!(format!("{}", listing), expected);
+    }
+
+    #[test]
+    fn opcodes_with_ignored_bits_are_kept_as_words() {
+        // 5xy1 decodes as SE Vx, Vy but would serialize back as 5xy0
+        let rom = [0x51, 0x21, 0x12, 0x02];
+        let listing = disassemble(&rom);
+
+        assert_eq!(format!("{}", listing), "    dw 0x5121 ; SE V1, V2\nlabel_202:\n    JP label_202\n");
+    }
+
+    #[test]
+    fn long_load_is_a_single_four_byte_instruction() {
+        // 200: SE V0, 0 / 202: LDL I, 20a / 206: CLS / 208: JP 208 / 20a: data
+        let rom = [0x30, 0x00, 0xf0, 0x00, 0x02, 0x0a, 0x00, 0xe0, 0x12, 0x08, 0xaa];
+        let listing = disassemble(&rom);
+
+        let expected = "    SE V0, 0\n    LDL I, data_20a\n    CLS\nlabel_208:\n    JP label_208\ndata_20a:\n    db 0xaa\n";
+        assert_eq!(format!("{}", listing), expected);
+    }
+
+    #[test]
+    fn listing_reassembles_to_the_original_rom() {
+        let rom = [
+            0x00, 0xe0, 0xa2, 0x2a, 0x60, 0x0c, 0x61, 0x08, 0xd0, 0x1f, 0x70, 0x09, 0xa2, 0x39, 0xd0, 0x1f,
+            0xa2, 0x48, 0x70, 0x08, 0xd0, 0x1f, 0x22, 0x1c, 0x12, 0x18, 0x00, 0xee, 0xff, 0x00, 0xff, 0x00,
+            0x3c, 0x00, 0x3c, 0x00, 0x3c, 0x00, 0x3c, 0x00, 0xff, 0x00, 0xff, 0xff, 0x00, 0xff, 0x00, 0x38,
+            0x00, 0x3f, 0x00, 0x3f, 0x00, 0x38, 0x00, 0xff, 0x00, 0xff, 0x80, 0x00, 0xe0, 0x00, 0xe0, 0x00,
+            0x80, 0x00, 0x80, 0x00, 0xe0, 0x00, 0xe0, 0x00, 0x80, 0xf8, 0x00, 0xfc, 0x00, 0x3e, 0x00, 0x3f,
+        ];
+
+        let listing = disassemble(&rom);
+        let bytes = assemble(&format!("{}", listing)).unwrap();
+        assert_eq!(&bytes[..], &rom[..]);
+    }
+
+    #[test]
+    fn arbitrary_bytes_reassemble_to_the_original_rom() {
+        let mut seed: u32 = 12345;
+        for _ in 0..20 {
+            let mut rom = Vec::new();
+            for _ in 0..513 {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                rom.push((seed >> 16) as u8);
+            }
+
+            let listing = disassemble(&rom);
+            let bytes = assemble(&format!("{}", listing)).unwrap();
+            assert_eq!(bytes, rom);
+        }
+    }
+}
@@ -1,6 +1,11 @@
+use std::ops::Range;
 use custom_error::custom_error;
-use crate::{Hardware, Instruction, Register};
-use crate::hardware::{STACK_SIZE, MEMORY_SIZE, FRAMEBUFFER_HEIGHT, FRAMEBUFFER_WIDTH};
+use crate::{Hardware, Instruction, Platform, Register, get_instruction_at};
+use crate::hardware::{STACK_SIZE, FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT, AUDIO_PATTERN_SIZE, Framebuffer};
+
+const HORIZONTAL_SCROLL_PIXELS: usize = 4;
+const PLANE_COUNT: usize = 2;
+const ALL_PLANES: u8 = 0b11;
 
 custom_error!{pub ExecutionError
     InvalidRegisterForInstruction {instruction:Instruction} = "Invalid register was used for instruction: {instruction}",
@@ -9,9 +14,15 @@ custom_error!{pub ExecutionError
     InvalidCallOrJumpAddress {address:u16} = "Call performed to invalid address {address}",
     EmptyStack = "Return was called with an empty stack",
     InvalidFontDigit {digit: u8} = "Font digit of {digit} is invalid, only 0-f is allowed",
+    MemoryOutOfBounds {address: usize, instruction: Instruction} = "Instruction '{instruction}' accessed address {address}, which is outside of memory",
+    ProgramCounterOutOfBounds {address: u16} = "Program counter {address} ran past the end of memory",
 }
 
 pub fn execute_instruction(instruction: Instruction, hardware: &mut Hardware) -> Result<(), ExecutionError> {
+    if !is_supported(&instruction, hardware.platform) {
+        return Err(ExecutionError::UnhandleableInstruction {instruction});
+    }
+
     match instruction {
         Instruction::AddFromRegister {register1: Register::General(reg1_num), register2: Register::General(reg2_num)} => {
             let reg1_value = hardware.gen_registers[reg1_num as usize];
@@ -20,22 +31,23 @@ pub fn execute_instruction(instruction: Instruction, hardware: &mut Hardware) ->
 
             hardware.gen_registers[reg1_num as usize] = reg1_value.wrapping_add(reg2_value);
             hardware.gen_registers[0xf] = if will_wrap { 1 } else { 0};
-            hardware.program_counter += 2;
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::AddFromRegister {register1: Register::I, register2: Register::General(reg2_num)} => {
             hardware.i_register = hardware.i_register.wrapping_add(hardware.gen_registers[reg2_num as usize] as u16);
-            hardware.program_counter += 2;
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::AddFromValue {register: Register::General(reg_num), value} => {
             hardware.gen_registers[reg_num as usize] = hardware.gen_registers[reg_num as usize].wrapping_add(value);
-            hardware.program_counter += 2;
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::And {register1: Register::General(reg_num1), register2: Register::General(reg_num2)} => {
             hardware.gen_registers[reg_num1 as usize] = hardware.gen_registers[reg_num1 as usize] & hardware.gen_registers[reg_num2 as usize];
-            hardware.program_counter += 2;
+            reset_flag_after_logic(hardware);
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::Call {address} => {
@@ -49,65 +61,96 @@ pub fn execute_instruction(instruction: Instruction, hardware: &mut Hardware) ->
         }
 
         Instruction::ClearDisplay => {
-            for x in 0..hardware.framebuffer.len() {
-                for y in 0..hardware.framebuffer[x].len() {
-                    hardware.framebuffer[x][y] = 0;
-                }
-            }
+            clear_planes(hardware, hardware.selected_planes);
+            advance_program_counter(hardware, 2)?;
+        }
 
-            hardware.program_counter += 2;
+        Instruction::DisableHighResolution => {
+            hardware.high_resolution = false;
+            clear_planes(hardware, ALL_PLANES);
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::DrawSprite {x_register: Register::General(x_reg_num), y_register: Register::General(y_reg_num), height} => {
-            let first_row = hardware.gen_registers[y_reg_num as usize] as usize;
-            let first_pixel = hardware.gen_registers[x_reg_num as usize] as usize;
-            let shift_amount = first_pixel % 8;
-
-            let left_column_set = first_pixel / 8;
+            // Stay on the same instruction until the display is ready for the next frame
+            if hardware.quirks.display_wait {
+                if !hardware.vertical_blank {
+                    return Ok(());
+                }
 
-            // According to the Cowgod spec, if the right column set would be out of bounds it
-            // wraps to the other side on the same row
-            let right_column_set = (left_column_set + 1) % (FRAMEBUFFER_WIDTH / 8);
+                hardware.vertical_blank = false;
+            }
 
-            let mut collisions_found = false;
-            for x in 0..height as usize {
-                let sprite_byte = hardware.memory[hardware.i_register as usize + x];
-                let left_byte = sprite_byte >> shift_amount;
+            let clip = hardware.quirks.clip_sprites;
+            let display_height = hardware.display_height();
+            let first_row = hardware.gen_registers[y_reg_num as usize] as usize % display_height;
+            let first_pixel = hardware.gen_registers[x_reg_num as usize] as usize % hardware.display_width();
 
-                // According to Cowgod spec, if we've gone past the screen in height then wrap to the top
-                let row = (first_row + x) % FRAMEBUFFER_HEIGHT;
+            // The SUPER-CHIP draws a 16x16 sprite, stored as two bytes per row, when no height is given
+            let (rows, bytes_per_row) = match (height, hardware.platform) {
+                (0, Platform::SuperChip) | (0, Platform::XoChip) => (16, 2),
+                _ => (height as usize, 1),
+            };
 
-                // Detect if the xor will reset any already on pixels
-                if hardware.framebuffer[row][left_column_set] & left_byte > 0 {
-                    collisions_found = true;
+            // When multiple planes are selected the sprite data for each plane follows the previous one
+            let sprite_length = planes_in(hardware.selected_planes).count() * rows * bytes_per_row;
+            let mut sprite_address = memory_range(hardware, hardware.i_register, sprite_length, &instruction)?.start;
+            let mut colliding_rows = vec![false; rows];
+            for plane in planes_in(hardware.selected_planes) {
+                for (y, is_row_colliding) in colliding_rows.iter_mut().enumerate() {
+                    // According to Cowgod spec, if we've gone past the screen in height then wrap to the top
+                    let row = (first_row + y) % display_height;
+
+                    for x in 0..bytes_per_row {
+                        let sprite_byte = hardware.memory[sprite_address];
+                        sprite_address += 1;
+
+                        if clip && first_row + y >= display_height {
+                            continue;
+                        }
+
+                        if draw_sprite_byte(hardware, plane, row, first_pixel + (x * 8), sprite_byte, clip) {
+                            *is_row_colliding = true;
+                        }
+                    }
                 }
+            }
 
-                // Update framebuffer
-                hardware.framebuffer[row][left_column_set] ^= left_byte;
-
-                // If we are affecting pixels across column set boundaries, repeat for the next byte
-                if shift_amount > 0 {
-                    let right_byte = sprite_byte << 8 - shift_amount;
+            // SUPER-CHIP 1.1 counts the rows that collided or were cut off by the bottom edge in high
+            // resolution mode, instead of just flagging a collision
+            let colliding_row_count = colliding_rows.iter().filter(|x| **x).count() as u8;
+            let clipped_row_count = match clip {
+                true => (first_row + rows).saturating_sub(display_height) as u8,
+                false => 0,
+            };
 
-                    if hardware.framebuffer[row][right_column_set] & right_byte > 0 {
-                        collisions_found = true;
-                    }
+            advance_program_counter(hardware, 2)?;
+            hardware.gen_registers[0xf] = match (hardware.platform, hardware.high_resolution) {
+                (Platform::SuperChip, true) => colliding_row_count + clipped_row_count,
+                _ => (colliding_row_count > 0) as u8,
+            };
+        }
 
-                    hardware.framebuffer[row][right_column_set] ^= right_byte;
-                }
-            }
+        Instruction::EnableHighResolution => {
+            hardware.high_resolution = true;
+            clear_planes(hardware, ALL_PLANES);
+            advance_program_counter(hardware, 2)?;
+        }
 
-            hardware.program_counter += 2;
-            hardware.gen_registers[0xf] = if collisions_found { 1 } else { 0 };
+        Instruction::Exit => {
+            // Leave the program counter alone so nothing else runs even if the frontend ignores `halted`
+            hardware.halted = true;
         }
 
         Instruction::JumpToAddress {address, add_register_0} => {
+            // With the jump quirk the highest nibble of the address also picks the register to add
+            let offset_register = if hardware.quirks.jump_uses_vx { (address >> 8) as usize & 0xf } else { 0 };
             let final_address = match add_register_0 {
-                true => address + hardware.gen_registers[0] as u16,
+                true => address + hardware.gen_registers[offset_register] as u16,
                 false => address
             };
 
-            if final_address < 512 || final_address > MEMORY_SIZE as u16 {
+            if final_address < 512 || final_address as usize >= hardware.memory_size() {
                 return Err(ExecutionError::InvalidCallOrJumpAddress {address: final_address});
             }
 
@@ -116,38 +159,69 @@ pub fn execute_instruction(instruction: Instruction, hardware: &mut Hardware) ->
 
         Instruction::LoadAddressIntoIRegister {address} => {
             hardware.i_register = address;
-            hardware.program_counter += 2;
+            advance_program_counter(hardware, 2)?;
+        }
+
+        Instruction::LoadAudioPattern => {
+            let range = memory_range(hardware, hardware.i_register, AUDIO_PATTERN_SIZE, &instruction)?;
+            hardware.audio_pattern.copy_from_slice(&hardware.memory[range]);
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::LoadBcdValue {source: Register::General(reg_num)} => {
-            let start_address = hardware.i_register as usize;
+            let start_address = memory_range(hardware, hardware.i_register, 3, &instruction)?.start;
             let source_value = hardware.gen_registers[reg_num as usize];
 
             hardware.memory[start_address] = (source_value / 100) % 10;
             hardware.memory[start_address + 1] = (source_value / 10) % 10;
             hardware.memory[start_address + 2] = source_value % 10;
-            hardware.program_counter += 2;
+            advance_program_counter(hardware, 2)?;
+        }
+
+        Instruction::LoadBigSpriteLocation {sprite_digit: Register::General(reg_num)} => {
+            let digit = hardware.gen_registers[reg_num as usize];
+            if digit > 0xf {
+                return Err(ExecutionError::InvalidFontDigit {digit});
+            }
+
+            hardware.i_register = hardware.big_font_addresses[&digit];
+            advance_program_counter(hardware, 2)?;
+        }
+
+        Instruction::LoadFromFlags {last_register: Register::General(reg_num)} => {
+            if reg_num as usize >= hardware.flag_register_count() {
+                return Err(ExecutionError::InvalidRegisterForInstruction {instruction});
+            }
+
+            for index in 0..=reg_num as usize {
+                hardware.gen_registers[index] = hardware.flag_registers[index];
+            }
+
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::LoadFromKeyPress {destination: Register::General(reg_num)} => {
             // According to specs I have found this instruction does not recognize a key if it's
             // currently down.  So it will wait (stay on the same program counter for our purposes)
             // until the user releases the key, at which point for one execution
-            // `hardware.key_released_since_last_instruction` should have the key that was just released.
+            // `hardware.keys_released` should have the key that was just released.  If several were
+            // released at once the lowest numbered key wins.
 
-            if let Some(key_num) = hardware.key_released_since_last_instruction {
-                hardware.gen_registers[reg_num as usize] = key_num;
-                hardware.program_counter += 2;
+            if hardware.keys_released != 0 {
+                hardware.gen_registers[reg_num as usize] = hardware.keys_released.trailing_zeros() as u8;
+                advance_program_counter(hardware, 2)?;
             }
         }
 
         Instruction::LoadFromMemory {last_register: Register::General(reg_num)} => {
-            for index in 0..=reg_num {
-                hardware.gen_registers[index as usize] = hardware.memory[hardware.i_register as usize + index as usize];
+            let range = memory_range(hardware, hardware.i_register, reg_num as usize + 1, &instruction)?;
+            hardware.gen_registers[..range.len()].copy_from_slice(&hardware.memory[range]);
+
+            if hardware.quirks.load_store_increments_i {
+                hardware.i_register = hardware.i_register.wrapping_add(reg_num as u16 + 1);
             }
 
-            hardware.i_register = hardware.i_register + reg_num as u16 + 1;
-            hardware.program_counter += 2;
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::LoadFromRegister {destination, source} => {
@@ -165,21 +239,50 @@ pub fn execute_instruction(instruction: Instruction, hardware: &mut Hardware) ->
                 _ => return Err(ExecutionError::InvalidRegisterForInstruction {instruction: Instruction::LoadFromRegister {destination, source}}),
             }
 
-            hardware.program_counter += 2;
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::LoadFromValue {destination: Register::General(reg_num), value} => {
             hardware.gen_registers[reg_num as usize] = value;
-            hardware.program_counter += 2;
+            advance_program_counter(hardware, 2)?;
+        }
+
+        Instruction::LoadIntoFlags {last_register: Register::General(reg_num)} => {
+            if reg_num as usize >= hardware.flag_register_count() {
+                return Err(ExecutionError::InvalidRegisterForInstruction {instruction});
+            }
+
+            for index in 0..=reg_num as usize {
+                hardware.flag_registers[index] = hardware.gen_registers[index];
+            }
+
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::LoadIntoMemory {last_register: Register::General(reg_num)} => {
-            for index in 0..=reg_num {
-                hardware.memory[hardware.i_register as usize + index as usize] = hardware.gen_registers[index as usize];
+            let range = memory_range(hardware, hardware.i_register, reg_num as usize + 1, &instruction)?;
+            let length = range.len();
+            hardware.memory[range].copy_from_slice(&hardware.gen_registers[..length]);
+
+            if hardware.quirks.load_store_increments_i {
+                hardware.i_register = hardware.i_register.wrapping_add(reg_num as u16 + 1);
             }
 
-            hardware.i_register = hardware.i_register + reg_num as u16 + 1;
-            hardware.program_counter += 2;
+            advance_program_counter(hardware, 2)?;
+        }
+
+        Instruction::LoadLongAddressIntoIRegister {address} => {
+            hardware.i_register = address;
+            advance_program_counter(hardware, 4)?;
+        }
+
+        Instruction::LoadRegisterRange {first_register: Register::General(first_num), last_register: Register::General(last_num)} => {
+            let start_address = memory_range(hardware, hardware.i_register, register_range(first_num, last_num).count(), &instruction)?.start;
+            for (offset, index) in register_range(first_num, last_num).enumerate() {
+                hardware.gen_registers[index] = hardware.memory[start_address + offset];
+            }
+
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::LoadSpriteLocation {sprite_digit: Register::General(reg_num)} => {
@@ -189,12 +292,13 @@ pub fn execute_instruction(instruction: Instruction, hardware: &mut Hardware) ->
             }
 
             hardware.i_register = hardware.font_addresses[&digit];
-            hardware.program_counter += 2;
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::Or {register1: Register::General(reg_num1), register2: Register::General(reg_num2)} => {
             hardware.gen_registers[reg_num1 as usize] = hardware.gen_registers[reg_num1 as usize] | hardware.gen_registers[reg_num2 as usize];
-            hardware.program_counter += 2;
+            reset_flag_after_logic(hardware);
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::Return => {
@@ -202,77 +306,137 @@ pub fn execute_instruction(instruction: Instruction, hardware: &mut Hardware) ->
                 return Err(ExecutionError::EmptyStack);
             }
 
-            hardware.program_counter = hardware.stack[hardware.stack_pointer - 1] + 2;
+            hardware.program_counter = hardware.stack[hardware.stack_pointer - 1];
             hardware.stack_pointer = hardware.stack_pointer - 1;
+            advance_program_counter(hardware, 2)?;
+        }
+
+        Instruction::SaveRegisterRange {first_register: Register::General(first_num), last_register: Register::General(last_num)} => {
+            let start_address = memory_range(hardware, hardware.i_register, register_range(first_num, last_num).count(), &instruction)?.start;
+            for (offset, index) in register_range(first_num, last_num).enumerate() {
+                hardware.memory[start_address + offset] = hardware.gen_registers[index];
+            }
+
+            advance_program_counter(hardware, 2)?;
+        }
+
+        Instruction::ScrollDown {rows} => {
+            let height = hardware.display_height();
+            let rows = rows as usize;
+            for plane in planes_in(hardware.selected_planes) {
+                let framebuffer = plane_mut(hardware, plane);
+                for row in (0..height).rev() {
+                    framebuffer[row] = if row >= rows { framebuffer[row - rows] } else { [0; FRAMEBUFFER_WIDTH / 8] };
+                }
+            }
+
+            advance_program_counter(hardware, 2)?;
+        }
+
+        Instruction::ScrollLeft => {
+            scroll_horizontally(hardware, HORIZONTAL_SCROLL_PIXELS, true);
+            advance_program_counter(hardware, 2)?;
+        }
+
+        Instruction::ScrollRight => {
+            scroll_horizontally(hardware, HORIZONTAL_SCROLL_PIXELS, false);
+            advance_program_counter(hardware, 2)?;
+        }
+
+        Instruction::ScrollUp {rows} => {
+            let height = hardware.display_height();
+            let rows = rows as usize;
+            for plane in planes_in(hardware.selected_planes) {
+                let framebuffer = plane_mut(hardware, plane);
+                for row in 0..height {
+                    framebuffer[row] = if row + rows < height { framebuffer[row + rows] } else { [0; FRAMEBUFFER_WIDTH / 8] };
+                }
+            }
+
+            advance_program_counter(hardware, 2)?;
+        }
+
+        Instruction::SelectPlanes {planes} => {
+            hardware.selected_planes = planes & ALL_PLANES;
+            advance_program_counter(hardware, 2)?;
+        }
+
+        Instruction::SetPitch {register: Register::General(reg_num)} => {
+            hardware.pitch = hardware.gen_registers[reg_num as usize];
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::SetRandom {register: Register::General(reg_num), and_value} => {
-            hardware.gen_registers[reg_num as usize] = rand::random::<u8>() & and_value;
-            hardware.program_counter += 2;
+            hardware.gen_registers[reg_num as usize] = hardware.random.next_byte() & and_value;
+            advance_program_counter(hardware, 2)?;
         }
 
-        Instruction::ShiftLeft {register: Register::General(reg_num)} => {
-            hardware.gen_registers[reg_num as usize] = hardware.gen_registers[reg_num as usize] << 1;
-            hardware.program_counter += 2;
+        Instruction::ShiftLeft {register: Register::General(reg_num), source: Register::General(source_num)} => {
+            let value = shift_source_value(hardware, reg_num, source_num);
+            hardware.gen_registers[reg_num as usize] = value << 1;
+            hardware.gen_registers[0xf] = value >> 7;
+            advance_program_counter(hardware, 2)?;
         }
 
-        Instruction::ShiftRight {register: Register::General(reg_num)} => {
-            hardware.gen_registers[reg_num as usize] = hardware.gen_registers[reg_num as usize] >> 1;
-            hardware.program_counter += 2;
+        Instruction::ShiftRight {register: Register::General(reg_num), source: Register::General(source_num)} => {
+            let value = shift_source_value(hardware, reg_num, source_num);
+            hardware.gen_registers[reg_num as usize] = value >> 1;
+            hardware.gen_registers[0xf] = value & 0x1;
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::SkipIfEqual {register: Register::General(reg_num), value} => {
             let increment = match hardware.gen_registers[reg_num as usize] == value {
-                true => 4,
+                true => skip_size(hardware),
                 false => 2,
             };
 
-            hardware.program_counter += increment;
+            advance_program_counter(hardware, increment)?;
         }
 
         Instruction::SkipIfKeyPressed {register: Register::General(reg_num)} => {
-            let increment = match hardware.current_key_down {
-                Some(x) if x == hardware.gen_registers[reg_num as usize] => 4,
-                _ => 2,
+            let increment = match hardware.is_key_down(hardware.gen_registers[reg_num as usize]) {
+                true => skip_size(hardware),
+                false => 2,
             };
 
-            hardware.program_counter += increment;
+            advance_program_counter(hardware, increment)?;
         }
 
         Instruction::SkipIfKeyNotPressed {register: Register::General(reg_num)} => {
-            let increment = match hardware.current_key_down {
-                Some(x) if x == hardware.gen_registers[reg_num as usize] => 2,
-                _ => 4,
+            let increment = match hardware.is_key_down(hardware.gen_registers[reg_num as usize]) {
+                true => 2,
+                false => skip_size(hardware),
             };
 
-            hardware.program_counter += increment;
+            advance_program_counter(hardware, increment)?;
         }
 
         Instruction::SkipIfNotEqual {register: Register::General(reg_num), value} => {
             let increment = match hardware.gen_registers[reg_num as usize] == value {
                 true => 2,
-                false => 4,
+                false => skip_size(hardware),
             };
 
-            hardware.program_counter += increment;
+            advance_program_counter(hardware, increment)?;
         }
 
         Instruction::SkipIfRegistersEqual {register1: Register::General(reg_num1), register2: Register::General(reg_num2)} => {
             let increment = match hardware.gen_registers[reg_num1 as usize] == hardware.gen_registers[reg_num2 as usize]  {
-                true => 4,
+                true => skip_size(hardware),
                 false => 2,
             };
 
-            hardware.program_counter += increment;
+            advance_program_counter(hardware, increment)?;
         }
 
         Instruction::SkipIfRegistersNotEqual {register1: Register::General(reg_num1), register2: Register::General(reg_num2)} => {
             let increment = match hardware.gen_registers[reg_num1 as usize] == hardware.gen_registers[reg_num2 as usize]  {
                 true => 2,
-                false => 4,
+                false => skip_size(hardware),
             };
 
-            hardware.program_counter += increment;
+            advance_program_counter(hardware, increment)?;
         }
 
         Instruction::Subtract {minuend: Register::General(minuend_reg), subtrahend: Register::General(subtrahend_reg), stored_in: Register::General(stored_in_reg)} => {
@@ -280,12 +444,13 @@ pub fn execute_instruction(instruction: Instruction, hardware: &mut Hardware) ->
             let difference = hardware.gen_registers[minuend_reg as usize].wrapping_sub(hardware.gen_registers[subtrahend_reg as usize]);
             hardware.gen_registers[stored_in_reg as usize] = difference;
             hardware.gen_registers[0xf] = if will_underflow { 0 } else { 1 };
-            hardware.program_counter += 2;
+            advance_program_counter(hardware, 2)?;
         }
 
         Instruction::Xor {register1: Register::General(reg_num1), register2: Register::General(reg_num2)} => {
             hardware.gen_registers[reg_num1 as usize] = hardware.gen_registers[reg_num1 as usize] ^ hardware.gen_registers[reg_num2 as usize];
-            hardware.program_counter += 2;
+            reset_flag_after_logic(hardware);
+            advance_program_counter(hardware, 2)?;
         }
 
         _ => return Err(ExecutionError::UnhandleableInstruction{instruction})
@@ -294,10 +459,160 @@ pub fn execute_instruction(instruction: Instruction, hardware: &mut Hardware) ->
     Ok(())
 }
 
+fn is_supported(instruction: &Instruction, platform: Platform) -> bool {
+    match instruction {
+        Instruction::DisableHighResolution |
+        Instruction::EnableHighResolution |
+        Instruction::Exit |
+        Instruction::LoadBigSpriteLocation {..} |
+        Instruction::LoadFromFlags {..} |
+        Instruction::LoadIntoFlags {..} |
+        Instruction::ScrollDown {..} |
+        Instruction::ScrollLeft |
+        Instruction::ScrollRight => platform != Platform::Chip8,
+
+        Instruction::LoadAudioPattern |
+        Instruction::LoadLongAddressIntoIRegister {..} |
+        Instruction::LoadRegisterRange {..} |
+        Instruction::SaveRegisterRange {..} |
+        Instruction::ScrollUp {..} |
+        Instruction::SelectPlanes {..} |
+        Instruction::SetPitch {..} => platform == Platform::XoChip,
+
+        _ => true,
+    }
+}
+
+fn reset_flag_after_logic(hardware: &mut Hardware) {
+    if hardware.quirks.logic_resets_vf {
+        hardware.gen_registers[0xf] = 0;
+    }
+}
+
+fn shift_source_value(hardware: &Hardware, register: u8, source: u8) -> u8 {
+    match hardware.quirks.shift_uses_vy {
+        true => hardware.gen_registers[source as usize],
+        false => hardware.gen_registers[register as usize],
+    }
+}
+
+// Skips have to jump over the whole next instruction, which is four bytes for XO-CHIP's long load
+fn skip_size(hardware: &Hardware) -> u16 {
+    match hardware.platform {
+        Platform::XoChip => 2 + get_instruction_at(&hardware.memory, hardware.program_counter as usize + 2).size(),
+        _ => 4,
+    }
+}
+
+// Moving past the last instruction in memory has to fail rather than wrap the program counter,
+// since XO-CHIP's 64KB of memory ends exactly where the counter would overflow
+fn advance_program_counter(hardware: &mut Hardware, increment: u16) -> Result<(), ExecutionError> {
+    let address = hardware.program_counter as usize + increment as usize;
+    if address >= hardware.memory_size() {
+        return Err(ExecutionError::ProgramCounterOutOfBounds {address: hardware.program_counter});
+    }
+
+    hardware.program_counter = address as u16;
+    Ok(())
+}
+
+// Checks that the instruction can access `length` bytes starting at `start` before any of them
+// are touched, so a bad access fails without leaving memory partly written
+fn memory_range(hardware: &Hardware, start: u16, length: usize, instruction: &Instruction) -> Result<Range<usize>, ExecutionError> {
+    let start = start as usize;
+    let end = start + length;
+    match end > hardware.memory_size() {
+        true => Err(ExecutionError::MemoryOutOfBounds {address: start.max(hardware.memory_size()), instruction: instruction.clone()}),
+        false => Ok(start..end),
+    }
+}
+
+// Registers are visited in the order given, so a range can be saved or loaded backwards
+fn register_range(first: u8, last: u8) -> Box<dyn Iterator<Item = usize>> {
+    match first <= last {
+        true => Box::new(first as usize..=last as usize),
+        false => Box::new((last as usize..=first as usize).rev()),
+    }
+}
+
+fn planes_in(mask: u8) -> impl Iterator<Item = usize> {
+    (0..PLANE_COUNT).filter(move |x| mask & (1 << x) > 0)
+}
+
+fn plane_mut(hardware: &mut Hardware, plane: usize) -> &mut Framebuffer {
+    match plane {
+        0 => &mut hardware.framebuffer,
+        _ => &mut hardware.second_framebuffer,
+    }
+}
+
+fn clear_planes(hardware: &mut Hardware, planes: u8) {
+    for plane in planes_in(planes) {
+        *plane_mut(hardware, plane) = [[0; FRAMEBUFFER_WIDTH / 8]; FRAMEBUFFER_HEIGHT];
+    }
+}
+
+// Returns if any pixels that were already on were turned off
+fn draw_sprite_byte(hardware: &mut Hardware, plane: usize, row: usize, first_pixel: usize, sprite_byte: u8, clip: bool) -> bool {
+    let column_sets = hardware.display_width() / 8;
+    if clip && first_pixel >= hardware.display_width() {
+        return false;
+    }
+
+    let shift_amount = first_pixel % 8;
+    let left_column_set = (first_pixel / 8) % column_sets;
+
+    // According to the Cowgod spec, if the right column set would be out of bounds it
+    // wraps to the other side on the same row, unless sprites are being clipped
+    let right_column_set = (left_column_set + 1) % column_sets;
+    let draw_right_column_set = !clip || left_column_set + 1 < column_sets;
+
+    let framebuffer = plane_mut(hardware, plane);
+    let mut collision_found = false;
+    let left_byte = sprite_byte >> shift_amount;
+
+    // Detect if the xor will reset any already on pixels
+    if framebuffer[row][left_column_set] & left_byte > 0 {
+        collision_found = true;
+    }
+
+    // Update framebuffer
+    framebuffer[row][left_column_set] ^= left_byte;
+
+    // If we are affecting pixels across column set boundaries, repeat for the next byte
+    if shift_amount > 0 && draw_right_column_set {
+        let right_byte = sprite_byte << (8 - shift_amount);
+
+        if framebuffer[row][right_column_set] & right_byte > 0 {
+            collision_found = true;
+        }
+
+        framebuffer[row][right_column_set] ^= right_byte;
+    }
+
+    collision_found
+}
+
+fn scroll_horizontally(hardware: &mut Hardware, pixels: usize, to_left: bool) {
+    // Each framebuffer row is 128 bits wide, so it can be shifted as a single number.  The mask
+    // keeps pixels from moving outside of the visible area in low resolution mode.
+    let visible_mask = !0_u128 << (FRAMEBUFFER_WIDTH - hardware.display_width());
+    let height = hardware.display_height();
+    for plane in planes_in(hardware.selected_planes) {
+        let framebuffer = plane_mut(hardware, plane);
+        for row in framebuffer.iter_mut().take(height) {
+            let pixels_in_row = u128::from_be_bytes(*row);
+            let shifted = if to_left { pixels_in_row << pixels } else { pixels_in_row >> pixels };
+            *row = (shifted & visible_mask).to_be_bytes();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ::{Hardware, Register};
+    use ::{Hardware, Register, Random, Quirks};
+    use crate::hardware::MEMORY_SIZE;
 
     #[test]
     fn can_add_value_to_general_register() {
@@ -535,8 +850,7 @@ mod tests {
         let mut hardware = Hardware::new();
         hardware.program_counter = 1000;
         hardware.gen_registers[4] = 10;
-        hardware.current_key_down = Some(0x4);
-        hardware.key_released_since_last_instruction = None;
+        hardware.press_key(0x4);
 
         let instruction = Instruction::LoadFromKeyPress {destination: Register::General(4)};
         execute_instruction(instruction, &mut hardware).unwrap();
@@ -549,8 +863,8 @@ mod tests {
         let mut hardware = Hardware::new();
         hardware.program_counter = 1000;
         hardware.gen_registers[4] = 10;
-        hardware.current_key_down = None;
-        hardware.key_released_since_last_instruction = Some(0x5);
+        hardware.press_key(0x5);
+        hardware.release_key(0x5);
 
         let instruction = Instruction::LoadFromKeyPress {destination: Register::General(4)};
         execute_instruction(instruction, &mut hardware).unwrap();
@@ -558,6 +872,92 @@ mod tests {
         assert_eq!(hardware.gen_registers[4], 5, "Incorrect value in register");
     }
 
+    #[test]
+    fn bcd_value_past_end_of_memory_is_an_error() {
+        let mut hardware = Hardware::new();
+        hardware.i_register = MEMORY_SIZE as u16 - 2;
+
+        let instruction = Instruction::LoadBcdValue {source: Register::General(0)};
+        match execute_instruction(instruction, &mut hardware).unwrap_err() {
+            ExecutionError::MemoryOutOfBounds {address: MEMORY_SIZE, ..} => (),
+            x => panic!("Expected MemoryOutOfBounds, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn storing_registers_past_end_of_memory_writes_nothing() {
+        let mut hardware = Hardware::new();
+        hardware.program_counter = 1000;
+        hardware.i_register = MEMORY_SIZE as u16 - 2;
+        hardware.gen_registers[0] = 0xaa;
+
+        let instruction = Instruction::LoadIntoMemory {last_register: Register::General(3)};
+        match execute_instruction(instruction, &mut hardware).unwrap_err() {
+            ExecutionError::MemoryOutOfBounds {..} => (),
+            x => panic!("Expected MemoryOutOfBounds, instead got {:?}", x),
+        }
+
+        assert_eq!(hardware.memory[MEMORY_SIZE - 2], 0, "Memory should not be partly written");
+        assert_eq!(hardware.program_counter, 1000, "Program counter should not move");
+    }
+
+    #[test]
+    fn loading_registers_past_end_of_memory_is_an_error() {
+        let mut hardware = Hardware::new();
+        hardware.i_register = 0xffff;
+
+        let instruction = Instruction::LoadFromMemory {last_register: Register::General(0)};
+        match execute_instruction(instruction, &mut hardware).unwrap_err() {
+            ExecutionError::MemoryOutOfBounds {address: 0xffff, ..} => (),
+            x => panic!("Expected MemoryOutOfBounds, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn can_use_last_byte_of_xo_chip_memory() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::XoChip;
+        hardware.i_register = 0xffff;
+        hardware.gen_registers[0] = 0x12;
+
+        execute_instruction(Instruction::LoadIntoMemory {last_register: Register::General(0)}, &mut hardware).unwrap();
+        assert_eq!(hardware.memory[0xffff], 0x12, "Incorrect last byte");
+    }
+
+    #[test]
+    fn moving_past_end_of_xo_chip_memory_is_an_error() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::XoChip;
+        hardware.program_counter = 0xfffe;
+
+        let instruction = Instruction::AddFromValue {register: Register::General(0), value: 1};
+        match execute_instruction(instruction, &mut hardware).unwrap_err() {
+            ExecutionError::ProgramCounterOutOfBounds {address: 0xfffe} => (),
+            x => panic!("Expected ProgramCounterOutOfBounds, instead got {:?}", x),
+        }
+
+        // A call from the last instruction can't return past it either
+        hardware.stack[0] = 0xfffe;
+        hardware.stack_pointer = 1;
+        hardware.program_counter = 0x300;
+        match execute_instruction(Instruction::Return, &mut hardware).unwrap_err() {
+            ExecutionError::ProgramCounterOutOfBounds {address: 0xfffe} => (),
+            x => panic!("Expected ProgramCounterOutOfBounds, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn drawing_sprite_past_end_of_memory_is_an_error() {
+        let mut hardware = Hardware::new();
+        hardware.i_register = MEMORY_SIZE as u16 - 4;
+
+        let instruction = Instruction::DrawSprite {x_register: Register::General(0), y_register: Register::General(1), height: 5};
+        match execute_instruction(instruction, &mut hardware).unwrap_err() {
+            ExecutionError::MemoryOutOfBounds {address: MEMORY_SIZE, ..} => (),
+            x => panic!("Expected MemoryOutOfBounds, instead got {:?}", x),
+        }
+    }
+
     #[test]
     fn can_load_bcd_value_into_memory() {
         let mut hardware = Hardware::new();
@@ -780,7 +1180,7 @@ mod tests {
         let mut hardware = Hardware::new();
         hardware.program_counter = 1000;
         hardware.gen_registers[5] = 10;
-        hardware.current_key_down = Some(10);
+        hardware.press_key(10);
 
         let instruction = Instruction::SkipIfKeyPressed {
             register: Register::General(5),
@@ -795,7 +1195,7 @@ mod tests {
         let mut hardware = Hardware::new();
         hardware.program_counter = 1000;
         hardware.gen_registers[5] = 10;
-        hardware.current_key_down = Some(11);
+        hardware.press_key(11);
 
         let instruction = Instruction::SkipIfKeyPressed {
             register: Register::General(5),
@@ -810,7 +1210,7 @@ mod tests {
         let mut hardware = Hardware::new();
         hardware.program_counter = 1000;
         hardware.gen_registers[5] = 10;
-        hardware.current_key_down = Some(11);
+        hardware.press_key(11);
 
         let instruction = Instruction::SkipIfKeyNotPressed {
             register: Register::General(5),
@@ -825,7 +1225,7 @@ mod tests {
         let mut hardware = Hardware::new();
         hardware.program_counter = 1000;
         hardware.gen_registers[5] = 10;
-        hardware.current_key_down = Some(10);
+        hardware.press_key(10);
 
         let instruction = Instruction::SkipIfKeyNotPressed {
             register: Register::General(5),
@@ -897,6 +1297,7 @@ mod tests {
 
         let instruction = Instruction::ShiftRight {
             register: Register::General(3),
+            source: Register::General(3),
         };
 
         execute_instruction(instruction, &mut hardware).unwrap();
@@ -912,6 +1313,7 @@ mod tests {
 
         let instruction = Instruction::ShiftLeft {
             register: Register::General(3),
+            source: Register::General(3),
         };
 
         execute_instruction(instruction, &mut hardware).unwrap();
@@ -922,6 +1324,7 @@ mod tests {
     #[test]
     fn can_get_random_number() {
         let mut hardware = Hardware::new();
+        hardware.random = Random::new(2);
         hardware.program_counter = 1000;
         hardware.gen_registers[3] = 100;
 
@@ -946,6 +1349,33 @@ mod tests {
         assert_ne!(value1, value2, "Values 1 and 2 were the same (possibly not random??)");
     }
 
+    #[test]
+    fn random_numbers_repeat_for_same_seed_and_clones() {
+        let instruction = Instruction::SetRandom {register: Register::General(3), and_value: 0xff};
+        let mut hardware = Hardware::new();
+        hardware.random = Random::new(42);
+        let mut clone = hardware.clone();
+        let mut reseeded = Hardware::new();
+        reseeded.random = Random::new(42);
+
+        for _ in 0..20 {
+            execute_instruction(instruction.clone(), &mut hardware).unwrap();
+            execute_instruction(instruction.clone(), &mut clone).unwrap();
+            execute_instruction(instruction.clone(), &mut reseeded).unwrap();
+            assert_eq!(clone.gen_registers[3], hardware.gen_registers[3], "Clone generated a different value");
+            assert_eq!(reseeded.gen_registers[3], hardware.gen_registers[3], "Same seed generated a different value");
+        }
+    }
+
+    #[test]
+    fn random_number_is_masked_by_and_value() {
+        let mut hardware = Hardware::new();
+        for _ in 0..50 {
+            execute_instruction(Instruction::SetRandom {register: Register::General(3), and_value: 0x0f}, &mut hardware).unwrap();
+            assert_eq!(hardware.gen_registers[3] & 0xf0, 0, "Random value was not masked");
+        }
+    }
+
     #[test]
     fn can_subtract_register_without_underflow() {
         let mut hardware = Hardware::new();
@@ -1222,4 +1652,455 @@ mod tests {
         assert_eq!(hardware.gen_registers[3], 25, "Incorrect VX value");
         assert_eq!(hardware.sound_timer, 25, "Incorrect delay timer value");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn super_chip_instructions_are_unhandled_on_chip8() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::Chip8;
+
+        let instruction = Instruction::EnableHighResolution;
+        match execute_instruction(instruction, &mut hardware).unwrap_err() {
+            ExecutionError::UnhandleableInstruction {instruction: _} => (),
+            x => panic!("Expected UnhandleableInstruction, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn can_enable_and_disable_high_resolution_mode() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::SuperChip;
+        hardware.program_counter = 1000;
+        hardware.framebuffer[0][0] = 0xff;
+
+        execute_instruction(Instruction::EnableHighResolution, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1002, "Incorrect program counter");
+        assert!(hardware.high_resolution, "Expected high resolution mode");
+        assert_eq!(hardware.framebuffer[0][0], 0, "Expected framebuffer to be cleared");
+
+        hardware.framebuffer[0][0] = 0xff;
+        execute_instruction(Instruction::DisableHighResolution, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1004, "Incorrect program counter");
+        assert!(!hardware.high_resolution, "Expected low resolution mode");
+        assert_eq!(hardware.framebuffer[0][0], 0, "Expected framebuffer to be cleared");
+    }
+
+    #[test]
+    fn exit_halts_without_moving_program_counter() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::SuperChip;
+        hardware.program_counter = 1000;
+
+        execute_instruction(Instruction::Exit, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1000, "Incorrect program counter");
+        assert!(hardware.halted, "Expected hardware to be halted");
+    }
+
+    #[test]
+    fn can_load_big_digit_sprite_location() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::SuperChip;
+        hardware.program_counter = 1000;
+        hardware.gen_registers[4] = 0x7;
+
+        let instruction = Instruction::LoadBigSpriteLocation {sprite_digit: Register::General(4)};
+        execute_instruction(instruction, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1002, "Incorrect program counter");
+        assert_eq!(hardware.i_register, hardware.big_font_addresses[&0x7], "Incorrect sprite address");
+    }
+
+    #[test]
+    fn can_save_and_restore_flag_registers() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::SuperChip;
+        hardware.program_counter = 1000;
+        hardware.gen_registers[0] = 10;
+        hardware.gen_registers[1] = 11;
+        hardware.gen_registers[2] = 12;
+
+        execute_instruction(Instruction::LoadIntoFlags {last_register: Register::General(1)}, &mut hardware).unwrap();
+        assert_eq!(hardware.flag_registers[0], 10, "Incorrect flag 0 value");
+        assert_eq!(hardware.flag_registers[1], 11, "Incorrect flag 1 value");
+        assert_eq!(hardware.flag_registers[2], 0, "Incorrect flag 2 value");
+
+        hardware.gen_registers[0] = 0;
+        hardware.gen_registers[1] = 0;
+        execute_instruction(Instruction::LoadFromFlags {last_register: Register::General(1)}, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1004, "Incorrect program counter");
+        assert_eq!(hardware.gen_registers[0], 10, "Incorrect V0 value");
+        assert_eq!(hardware.gen_registers[1], 11, "Incorrect V1 value");
+        assert_eq!(hardware.gen_registers[2], 12, "Incorrect V2 value");
+    }
+
+    #[test]
+    fn cannot_save_more_than_eight_flag_registers() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::SuperChip;
+
+        let instruction = Instruction::LoadIntoFlags {last_register: Register::General(8)};
+        match execute_instruction(instruction, &mut hardware).unwrap_err() {
+            ExecutionError::InvalidRegisterForInstruction {instruction: _} => (),
+            x => panic!("Expected InvalidRegisterForInstruction, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn can_scroll_display_down() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::SuperChip;
+        hardware.program_counter = 1000;
+        hardware.framebuffer[0][1] = 0xaa;
+        hardware.framebuffer[30][2] = 0xbb;
+
+        execute_instruction(Instruction::ScrollDown {rows: 3}, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1002, "Incorrect program counter");
+        assert_eq!(hardware.framebuffer[0][1], 0, "Incorrect framebuffer value at row 0 column byte 1");
+        assert_eq!(hardware.framebuffer[3][1], 0xaa, "Incorrect framebuffer value at row 3 column byte 1");
+        assert_eq!(hardware.framebuffer[33][2], 0, "Pixels should not scroll outside of the low resolution area");
+    }
+
+    #[test]
+    fn can_scroll_display_right() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::SuperChip;
+        hardware.program_counter = 1000;
+        hardware.framebuffer[5][0] = 0b11000011;
+        hardware.framebuffer[5][7] = 0b00001111;
+
+        execute_instruction(Instruction::ScrollRight, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1002, "Incorrect program counter");
+        assert_eq!(hardware.framebuffer[5][0], 0b00001100, "Incorrect framebuffer value at row 5 column byte 0");
+        assert_eq!(hardware.framebuffer[5][1], 0b00110000, "Incorrect framebuffer value at row 5 column byte 1");
+        assert_eq!(hardware.framebuffer[5][7], 0b00000000, "Incorrect framebuffer value at row 5 column byte 7");
+        assert_eq!(hardware.framebuffer[5][8], 0, "Pixels should not scroll outside of the low resolution area");
+    }
+
+    #[test]
+    fn can_scroll_display_left() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::SuperChip;
+        hardware.high_resolution = true;
+        hardware.program_counter = 1000;
+        hardware.framebuffer[5][0] = 0b11000011;
+        hardware.framebuffer[5][15] = 0b00001111;
+
+        execute_instruction(Instruction::ScrollLeft, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1002, "Incorrect program counter");
+        assert_eq!(hardware.framebuffer[5][0], 0b00110000, "Incorrect framebuffer value at row 5 column byte 0");
+        assert_eq!(hardware.framebuffer[5][14], 0b00000000, "Incorrect framebuffer value at row 5 column byte 14");
+        assert_eq!(hardware.framebuffer[5][15], 0b11110000, "Incorrect framebuffer value at row 5 column byte 15");
+    }
+
+    #[test]
+    fn can_draw_16_x_16_sprite_in_super_chip_mode() {
+        const SPRITE_START_ADDRESS: usize = 1046;
+
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::SuperChip;
+        hardware.high_resolution = true;
+        hardware.program_counter = 1000;
+        hardware.i_register = SPRITE_START_ADDRESS as u16;
+        hardware.gen_registers[4] = 120;
+        hardware.gen_registers[3] = 60;
+        for x in 0..32 {
+            hardware.memory[SPRITE_START_ADDRESS + x] = x as u8 + 1;
+        }
+
+        let instruction = Instruction::DrawSprite {
+            x_register: Register::General(4),
+            y_register: Register::General(3),
+            height: 0,
+        };
+
+        execute_instruction(instruction, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1002, "Incorrect program counter");
+        assert_eq!(hardware.gen_registers[0xf], 0, "Incorrect VF value");
+        assert_eq!(hardware.framebuffer[60][15], 1, "Incorrect framebuffer value at row 60 column byte 15");
+        assert_eq!(hardware.framebuffer[60][0], 2, "Incorrect framebuffer value at row 60 column byte 0");
+        assert_eq!(hardware.framebuffer[63][15], 7, "Incorrect framebuffer value at row 63 column byte 15");
+        assert_eq!(hardware.framebuffer[0][15], 9, "Incorrect framebuffer value at row 0 column byte 15");
+        assert_eq!(hardware.framebuffer[11][0], 32, "Incorrect framebuffer value at row 11 column byte 0");
+    }
+
+    #[test]
+    fn super_chip_high_resolution_draw_counts_colliding_and_clipped_rows() {
+        const SPRITE_START_ADDRESS: usize = 1046;
+
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::SuperChip;
+        hardware.quirks = Quirks::super_chip();
+        hardware.high_resolution = true;
+        hardware.program_counter = 1000;
+        hardware.i_register = SPRITE_START_ADDRESS as u16;
+        hardware.gen_registers[3] = 60;
+        for x in 0..6 {
+            hardware.memory[SPRITE_START_ADDRESS + x] = 0xff;
+        }
+
+        // Rows 60 and 62 already have pixels set, and the last two of the six rows fall off the bottom
+        hardware.framebuffer[60][0] = 0x01;
+        hardware.framebuffer[62][0] = 0x80;
+
+        let instruction = Instruction::DrawSprite {x_register: Register::General(4), y_register: Register::General(3), height: 6};
+        execute_instruction(instruction.clone(), &mut hardware).unwrap();
+        assert_eq!(hardware.gen_registers[0xf], 4, "Incorrect VF value");
+
+        // Low resolution mode only flags that something collided
+        hardware.high_resolution = false;
+        hardware.gen_registers[3] = 0;
+        execute_instruction(instruction.clone(), &mut hardware).unwrap();
+        execute_instruction(instruction, &mut hardware).unwrap();
+        assert_eq!(hardware.gen_registers[0xf], 1, "Incorrect low resolution VF value");
+    }
+
+    #[test]
+    fn xo_chip_instructions_are_unhandled_on_super_chip() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::SuperChip;
+
+        let instruction = Instruction::SelectPlanes {planes: 2};
+        match execute_instruction(instruction, &mut hardware).unwrap_err() {
+            ExecutionError::UnhandleableInstruction {instruction: _} => (),
+            x => panic!("Expected UnhandleableInstruction, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn can_load_long_address_into_i_register() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::XoChip;
+        hardware.program_counter = 1000;
+
+        execute_instruction(Instruction::LoadLongAddressIntoIRegister {address: 0xbeef}, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1004, "Incorrect program counter");
+        assert_eq!(hardware.i_register, 0xbeef, "Incorrect I register value");
+    }
+
+    #[test]
+    fn skip_jumps_over_long_load_on_xo_chip() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::XoChip;
+        hardware.program_counter = 1000;
+        hardware.memory[1002] = 0xf0;
+        hardware.memory[1003] = 0x00;
+
+        let instruction = Instruction::SkipIfEqual {register: Register::General(0), value: 0};
+        execute_instruction(instruction, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1006, "Incorrect program counter");
+    }
+
+    #[test]
+    fn can_jump_above_4k_on_xo_chip() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::XoChip;
+
+        let instruction = Instruction::JumpToAddress {address: 0x1200, add_register_0: false};
+        execute_instruction(instruction, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 0x1200, "Incorrect program counter");
+    }
+
+    #[test]
+    fn can_save_and_load_register_range() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::XoChip;
+        hardware.program_counter = 1000;
+        hardware.i_register = 0x900;
+        hardware.gen_registers[2] = 12;
+        hardware.gen_registers[3] = 13;
+        hardware.gen_registers[4] = 14;
+
+        let instruction = Instruction::SaveRegisterRange {first_register: Register::General(2), last_register: Register::General(4)};
+        execute_instruction(instruction, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1002, "Incorrect program counter");
+        assert_eq!(hardware.i_register, 0x900, "I register should not change");
+        assert_eq!(&hardware.memory[0x900..0x903], &[12, 13, 14], "Incorrect memory values");
+
+        let instruction = Instruction::LoadRegisterRange {first_register: Register::General(7), last_register: Register::General(5)};
+        execute_instruction(instruction, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1004, "Incorrect program counter");
+        assert_eq!(hardware.gen_registers[7], 12, "Incorrect V7 value");
+        assert_eq!(hardware.gen_registers[6], 13, "Incorrect V6 value");
+        assert_eq!(hardware.gen_registers[5], 14, "Incorrect V5 value");
+    }
+
+    #[test]
+    fn can_save_sixteen_flag_registers_on_xo_chip() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::XoChip;
+        hardware.gen_registers[0xf] = 0x55;
+
+        execute_instruction(Instruction::LoadIntoFlags {last_register: Register::General(0xf)}, &mut hardware).unwrap();
+        assert_eq!(hardware.flag_registers[0xf], 0x55, "Incorrect flag 15 value");
+    }
+
+    #[test]
+    fn can_load_audio_pattern_and_pitch() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::XoChip;
+        hardware.program_counter = 1000;
+        hardware.i_register = 0x900;
+        hardware.gen_registers[3] = 112;
+        for x in 0..16 {
+            hardware.memory[0x900 + x] = x as u8 * 3;
+        }
+
+        execute_instruction(Instruction::LoadAudioPattern, &mut hardware).unwrap();
+        execute_instruction(Instruction::SetPitch {register: Register::General(3)}, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1004, "Incorrect program counter");
+        assert_eq!(hardware.audio_pattern[15], 45, "Incorrect audio pattern value");
+        assert_eq!(hardware.pitch, 112, "Incorrect pitch");
+    }
+
+    #[test]
+    fn sprites_are_drawn_to_each_selected_plane() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::XoChip;
+        hardware.program_counter = 1000;
+        hardware.i_register = 0x900;
+        hardware.memory[0x900] = 0b1100_0000;
+        hardware.memory[0x901] = 0b1010_0000;
+
+        execute_instruction(Instruction::SelectPlanes {planes: 3}, &mut hardware).unwrap();
+        let instruction = Instruction::DrawSprite {x_register: Register::General(0), y_register: Register::General(0), height: 1};
+        execute_instruction(instruction, &mut hardware).unwrap();
+
+        assert_eq!(hardware.program_counter, 1004, "Incorrect program counter");
+        assert_eq!(hardware.pixel(0, 0), 3, "Incorrect planes at pixel 0");
+        assert_eq!(hardware.pixel(1, 0), 1, "Incorrect planes at pixel 1");
+        assert_eq!(hardware.pixel(2, 0), 2, "Incorrect planes at pixel 2");
+    }
+
+    #[test]
+    fn clear_and_scroll_only_affect_selected_planes() {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::XoChip;
+        hardware.framebuffer[5][0] = 0xff;
+        hardware.second_framebuffer[5][0] = 0xff;
+        hardware.second_framebuffer[9][0] = 0xaa;
+
+        execute_instruction(Instruction::SelectPlanes {planes: 2}, &mut hardware).unwrap();
+        execute_instruction(Instruction::ScrollUp {rows: 4}, &mut hardware).unwrap();
+        assert_eq!(hardware.framebuffer[5][0], 0xff, "First plane should not scroll");
+        assert_eq!(hardware.second_framebuffer[5][0], 0xaa, "Incorrect second plane value after scroll");
+        assert_eq!(hardware.second_framebuffer[1][0], 0xff, "Incorrect second plane value after scroll");
+
+        execute_instruction(Instruction::ClearDisplay, &mut hardware).unwrap();
+        assert_eq!(hardware.framebuffer[5][0], 0xff, "First plane should not be cleared");
+        assert_eq!(hardware.second_framebuffer[1][0], 0, "Second plane should be cleared");
+    }
+
+    #[test]
+    fn shift_sets_vf_to_the_bit_shifted_out() {
+        let mut hardware = Hardware::new();
+        hardware.gen_registers[3] = 0b1000_0001;
+
+        execute_instruction(Instruction::ShiftLeft {register: Register::General(3), source: Register::General(3)}, &mut hardware).unwrap();
+        assert_eq!(hardware.gen_registers[3], 0b0000_0010, "Incorrect v3 value");
+        assert_eq!(hardware.gen_registers[0xf], 1, "Incorrect VF value after shift left");
+
+        execute_instruction(Instruction::ShiftRight {register: Register::General(3), source: Register::General(3)}, &mut hardware).unwrap();
+        assert_eq!(hardware.gen_registers[3], 0b0000_0001, "Incorrect v3 value");
+        assert_eq!(hardware.gen_registers[0xf], 0, "Incorrect VF value after shift right");
+    }
+
+    #[test]
+    fn shift_quirk_shifts_vy_into_vx() {
+        let mut hardware = Hardware::new();
+        hardware.quirks.shift_uses_vy = true;
+        hardware.gen_registers[3] = 0xff;
+        hardware.gen_registers[5] = 0b0000_0101;
+
+        execute_instruction(Instruction::ShiftRight {register: Register::General(3), source: Register::General(5)}, &mut hardware).unwrap();
+        assert_eq!(hardware.gen_registers[3], 0b0000_0010, "Incorrect v3 value");
+        assert_eq!(hardware.gen_registers[5], 0b0000_0101, "Incorrect v5 value");
+        assert_eq!(hardware.gen_registers[0xf], 1, "Incorrect VF value");
+    }
+
+    #[test]
+    fn load_store_quirk_leaves_i_unchanged() {
+        let mut hardware = Hardware::new();
+        hardware.quirks.load_store_increments_i = false;
+        hardware.i_register = 0x900;
+
+        execute_instruction(Instruction::LoadIntoMemory {last_register: Register::General(3)}, &mut hardware).unwrap();
+        assert_eq!(hardware.i_register, 0x900, "Incorrect I value after store");
+
+        execute_instruction(Instruction::LoadFromMemory {last_register: Register::General(3)}, &mut hardware).unwrap();
+        assert_eq!(hardware.i_register, 0x900, "Incorrect I value after load");
+    }
+
+    #[test]
+    fn jump_quirk_adds_vx() {
+        let mut hardware = Hardware::new();
+        hardware.quirks.jump_uses_vx = true;
+        hardware.gen_registers[0] = 1;
+        hardware.gen_registers[3] = 10;
+
+        execute_instruction(Instruction::JumpToAddress {address: 0x320, add_register_0: true}, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 0x32a, "Incorrect program counter");
+    }
+
+    #[test]
+    fn logic_quirk_resets_vf() {
+        let mut hardware = Hardware::new();
+        hardware.quirks.logic_resets_vf = true;
+        hardware.gen_registers[0xf] = 1;
+
+        execute_instruction(Instruction::Or {register1: Register::General(1), register2: Register::General(2)}, &mut hardware).unwrap();
+        assert_eq!(hardware.gen_registers[0xf], 0, "Incorrect VF value");
+    }
+
+    #[test]
+    fn clip_quirk_cuts_sprites_off_at_screen_edges() {
+        const SPRITE_START_ADDRESS: usize = 1046;
+
+        let mut hardware = Hardware::new();
+        hardware.quirks.clip_sprites = true;
+        hardware.i_register = SPRITE_START_ADDRESS as u16;
+        hardware.gen_registers[0] = 60;
+        hardware.gen_registers[1] = 30;
+        hardware.memory[SPRITE_START_ADDRESS] = 0xff;
+        hardware.memory[SPRITE_START_ADDRESS + 1] = 0xff;
+        hardware.memory[SPRITE_START_ADDRESS + 2] = 0xff;
+
+        let instruction = Instruction::DrawSprite {x_register: Register::General(0), y_register: Register::General(1), height: 3};
+        execute_instruction(instruction, &mut hardware).unwrap();
+        assert_eq!(hardware.framebuffer[30][7], 0b0000_1111, "Incorrect framebuffer value at row 30 column byte 7");
+        assert_eq!(hardware.framebuffer[31][7], 0b0000_1111, "Incorrect framebuffer value at row 31 column byte 7");
+        assert_eq!(hardware.framebuffer[30][0], 0, "Sprite should not wrap horizontally");
+        assert_eq!(hardware.framebuffer[0][7], 0, "Sprite should not wrap vertically");
+    }
+
+    #[test]
+    fn display_wait_quirk_draws_once_per_vertical_blank() {
+        let mut hardware = Hardware::new();
+        hardware.quirks.display_wait = true;
+        hardware.program_counter = 1000;
+
+        let instruction = Instruction::DrawSprite {x_register: Register::General(0), y_register: Register::General(0), height: 1};
+        execute_instruction(instruction.clone(), &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1000, "Draw should wait for a vertical blank");
+
+        hardware.simulate_timer_tick();
+        execute_instruction(instruction.clone(), &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1002, "Draw should happen after a vertical blank");
+
+        execute_instruction(instruction, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1002, "Second draw should wait for the next vertical blank");
+    }
+
+    #[test]
+    fn skip_if_key_pressed_checks_every_held_key() {
+        let mut hardware = Hardware::new();
+        hardware.program_counter = 1000;
+        hardware.gen_registers[5] = 10;
+        hardware.press_key(3);
+        hardware.press_key(10);
+
+        let instruction = Instruction::SkipIfKeyPressed {
+            register: Register::General(5),
+        };
+
+        execute_instruction(instruction, &mut hardware).unwrap();
+        assert_eq!(hardware.program_counter, 1004, "Incorrect program counter");
+    }
+}
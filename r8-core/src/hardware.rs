@@ -1,14 +1,33 @@
 use std::collections::HashMap;
+use crate::{Instruction, Quirks, Random};
+use crate::parser::get_instruction_at;
 
 const FONT_MEMORY_START_ADDRESS: u16 = 0x0;
+const BIG_FONT_MEMORY_START_ADDRESS: u16 = 0x50;
 pub const STACK_SIZE: usize = 16;
-pub const MEMORY_SIZE: usize = 0xFFF;
-pub const FRAMEBUFFER_WIDTH: usize = 64;
-pub const FRAMEBUFFER_HEIGHT: usize = 32;
+pub const MEMORY_SIZE: usize = 0x1000;
+pub const XO_CHIP_MEMORY_SIZE: usize = 0x10000;
+pub const FRAMEBUFFER_WIDTH: usize = 128;
+pub const FRAMEBUFFER_HEIGHT: usize = 64;
+pub const LOW_RESOLUTION_WIDTH: usize = 64;
+pub const LOW_RESOLUTION_HEIGHT: usize = 32;
+pub const SUPER_CHIP_FLAG_REGISTER_COUNT: usize = 8;
+pub const FLAG_REGISTER_COUNT: usize = 16;
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+pub const DEFAULT_PITCH: u8 = 64;
+
+pub type Framebuffer = [[u8; FRAMEBUFFER_WIDTH / 8]; FRAMEBUFFER_HEIGHT];
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
 
 #[derive(Clone)]
 pub struct Hardware {
-    pub memory: [u8; MEMORY_SIZE],
+    pub memory: Vec<u8>,
     pub gen_registers: [u8; 16],
     pub i_register: u16,
     pub sound_timer: u8,
@@ -16,16 +35,28 @@ pub struct Hardware {
     pub program_counter: u16,
     pub stack: [u16; STACK_SIZE],
     pub stack_pointer: usize,
-    pub current_key_down: Option<u8>,
-    pub key_released_since_last_instruction: Option<u8>,
-    pub framebuffer: [[u8; FRAMEBUFFER_WIDTH / 8]; FRAMEBUFFER_HEIGHT], // up to 64x32 pixel resolution,
+    pub keys_down: u16, // bit n is set while key n is held down
+    pub keys_released: u16, // bit n is set if key n was released since the last instruction
+    pub framebuffer: Framebuffer, // up to 128x64 pixel resolution,
+    pub second_framebuffer: Framebuffer, // XO-CHIP's second bitplane
+    pub selected_planes: u8,
     pub font_addresses: HashMap<u8, u16>,
+    pub big_font_addresses: HashMap<u8, u16>,
+    pub platform: Platform,
+    pub high_resolution: bool,
+    pub flag_registers: [u8; FLAG_REGISTER_COUNT],
+    pub halted: bool,
+    pub audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pub pitch: u8,
+    pub quirks: Quirks,
+    pub vertical_blank: bool, // set by each timer tick, consumed by sprite draws when waiting on the display
+    pub random: Random,
 }
 
 impl Hardware {
     pub fn new() -> Hardware {
         let mut hardware = Hardware {
-            memory: [0_u8; MEMORY_SIZE],
+            memory: vec![0_u8; XO_CHIP_MEMORY_SIZE],
             gen_registers: [0_u8; 16],
             i_register: 0,
             sound_timer: 0,
@@ -33,23 +64,125 @@ impl Hardware {
             program_counter: 512, // First accessible memory location
             stack: [0; STACK_SIZE],
             stack_pointer: 0,
-            current_key_down: None,
-            key_released_since_last_instruction: None,
+            keys_down: 0,
+            keys_released: 0,
             framebuffer: [[0; FRAMEBUFFER_WIDTH / 8]; FRAMEBUFFER_HEIGHT],
+            second_framebuffer: [[0; FRAMEBUFFER_WIDTH / 8]; FRAMEBUFFER_HEIGHT],
+            selected_planes: 1,
             font_addresses: HashMap::new(),
+            big_font_addresses: HashMap::new(),
+            platform: Platform::Chip8,
+            high_resolution: false,
+            flag_registers: [0; FLAG_REGISTER_COUNT],
+            halted: false,
+            audio_pattern: [0; AUDIO_PATTERN_SIZE],
+            pitch: DEFAULT_PITCH,
+            quirks: Quirks::default(),
+            vertical_blank: false,
+            random: Random::new(Random::generate_seed()),
         };
 
         hardware.load_fonts();
+        hardware.load_big_fonts();
         hardware
     }
 
+    // Memory is always allocated for the largest platform, but only XO-CHIP programs can address all of it
+    pub fn memory_size(&self) -> usize {
+        match self.platform {
+            Platform::XoChip => XO_CHIP_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+
+    // XO-CHIP doubles the number of flag registers the SUPER-CHIP provided
+    pub fn flag_register_count(&self) -> usize {
+        match self.platform {
+            Platform::XoChip => FLAG_REGISTER_COUNT,
+            _ => SUPER_CHIP_FLAG_REGISTER_COUNT,
+        }
+    }
+
+    // Only the top left portion of the framebuffer is used while in low resolution mode
+    pub fn display_width(&self) -> usize {
+        if self.high_resolution { FRAMEBUFFER_WIDTH } else { LOW_RESOLUTION_WIDTH }
+    }
+
+    pub fn display_height(&self) -> usize {
+        if self.high_resolution { FRAMEBUFFER_HEIGHT } else { LOW_RESOLUTION_HEIGHT }
+    }
+
+    // Returns which bitplanes the pixel is on in, with bit 0 being the first plane
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        let mask = 0b1000_0000 >> (x % 8);
+        let first_plane = if self.framebuffer[y][x / 8] & mask > 0 { 1 } else { 0 };
+        let second_plane = if self.second_framebuffer[y][x / 8] & mask > 0 { 2 } else { 0 };
+
+        first_plane | second_plane
+    }
+
     pub fn simulate_timer_tick(&mut self) {
         self.delay_timer = if self.delay_timer > 0 { self.delay_timer - 1 } else { 0 };
         self.sound_timer = if self.sound_timer > 0 { self.sound_timer - 1 } else { 0 };
+        self.vertical_blank = true;
+    }
+
+    pub fn press_key(&mut self, key: u8) {
+        self.keys_down |= 1 << (key & 0xf);
+    }
+
+    pub fn release_key(&mut self, key: u8) {
+        let mask = 1 << (key & 0xf);
+        if self.keys_down & mask > 0 {
+            self.keys_down &= !mask;
+            self.keys_released |= mask;
+        }
+    }
+
+    pub fn is_key_down(&self, key: u8) -> bool {
+        key <= 0xf && self.keys_down & (1 << key) > 0
     }
 
+    // Releases only count for the instruction executed right after them
+    pub fn clear_key_edges(&mut self) {
+        self.keys_released = 0;
+    }
+
+    // Multi-line dump of the registers, timers and stack for debuggers and crash reports
+    pub fn describe_registers(&self) -> String {
+        let mut lines = Vec::new();
+        for (row, values) in self.gen_registers.chunks(8).enumerate() {
+            let registers = values.iter().enumerate()
+                .map(|(index, value)| format!("V{:X}={:02x}", row * 8 + index, value))
+                .collect::<Vec<String>>();
+
+            lines.push(registers.join(" "));
+        }
+
+        lines.push(format!("I={:04x} PC={:04x} DT={:02x} ST={:02x} SP={}",
+                           self.i_register, self.program_counter, self.delay_timer, self.sound_timer, self.stack_pointer));
+
+        let stack = self.stack[..self.stack_pointer.min(STACK_SIZE)].iter()
+            .map(|x| format!("{:04x}", x))
+            .collect::<Vec<String>>();
+
+        lines.push(format!("Stack: [{}]", stack.join(", ")));
+        lines.join("\n")
+    }
+
+    // Chip-8 only has a single tone, which sounds for as long as the sound timer is counting down
+    pub fn is_buzzer_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    // Bytes past the end of memory read as zero
     pub fn get_current_instruction_bytes(&self) -> (u8, u8) {
-        (self.memory[self.program_counter as usize], self.memory[self.program_counter as usize + 1])
+        let address = self.program_counter as usize;
+        (self.memory.get(address).cloned().unwrap_or(0), self.memory.get(address + 1).cloned().unwrap_or(0))
+    }
+
+    pub fn get_current_instruction(&self) -> Instruction {
+        get_instruction_at(&self.memory, self.program_counter as usize)
     }
 
     fn load_fonts(&mut self) {
@@ -83,6 +216,39 @@ impl Hardware {
             }
         }
     }
+
+    // 8x10 digits used by the SUPER-CHIP's high resolution mode
+    fn load_big_fonts(&mut self) {
+        let zero = [0x3c, 0x7e, 0xe7, 0xc3, 0xc3, 0xc3, 0xc3, 0xe7, 0x7e, 0x3c];
+        let one = [0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3c];
+        let two = [0x3e, 0x7f, 0xc3, 0x06, 0x0c, 0x18, 0x30, 0x60, 0xff, 0xff];
+        let three = [0x3c, 0x7e, 0xc3, 0x03, 0x0e, 0x0e, 0x03, 0xc3, 0x7e, 0x3c];
+        let four = [0x06, 0x0e, 0x1e, 0x36, 0x66, 0xc6, 0xff, 0xff, 0x06, 0x06];
+        let five = [0xff, 0xff, 0xc0, 0xc0, 0xfc, 0xfe, 0x03, 0xc3, 0x7e, 0x3c];
+        let six = [0x3e, 0x7c, 0xe0, 0xc0, 0xfc, 0xfe, 0xc3, 0xc3, 0x7e, 0x3c];
+        let seven = [0xff, 0xff, 0x03, 0x06, 0x0c, 0x18, 0x30, 0x60, 0x60, 0x60];
+        let eight = [0x3c, 0x7e, 0xc3, 0xc3, 0x7e, 0x7e, 0xc3, 0xc3, 0x7e, 0x3c];
+        let nine = [0x3c, 0x7e, 0xc3, 0xc3, 0x7f, 0x3f, 0x03, 0x03, 0x3e, 0x7c];
+        let a = [0x3c, 0x7e, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xc3, 0xc3];
+        let b = [0xfc, 0xfe, 0xc3, 0xc3, 0xfe, 0xfe, 0xc3, 0xc3, 0xfe, 0xfc];
+        let c = [0x3c, 0x7e, 0xc3, 0xc0, 0xc0, 0xc0, 0xc0, 0xc3, 0x7e, 0x3c];
+        let d = [0xfc, 0xfe, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xfe, 0xfc];
+        let e = [0xff, 0xff, 0xc0, 0xc0, 0xfc, 0xfc, 0xc0, 0xc0, 0xff, 0xff];
+        let f = [0xff, 0xff, 0xc0, 0xc0, 0xfc, 0xfc, 0xc0, 0xc0, 0xc0, 0xc0];
+
+        let fonts = [
+            zero, one, two, three, four, five, six, seven, eight, nine, a, b, c, d, e, f
+        ];
+
+        let mut current_address = BIG_FONT_MEMORY_START_ADDRESS;
+        for (digit, font) in fonts.iter().enumerate() {
+            self.big_font_addresses.insert(digit as u8, current_address);
+            for byte in font.iter() {
+                self.memory[current_address as usize] = *byte;
+                current_address += 1;
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -264,4 +430,123 @@ mod tests {
         assert_eq!(hardware.memory[start_address + 3], 0x80, "Incorrect byte 4 value");
         assert_eq!(hardware.memory[start_address + 4], 0x80, "Incorrect byte 5 value");
     }
+
+    #[test]
+    fn big_sprite_font_0_loaded_at_hardware_creation() {
+        let hardware = Hardware::new();
+        let start_address = hardware.big_font_addresses[&0x0] as usize;
+        assert_eq!(hardware.memory[start_address + 0], 0x3c, "Incorrect byte 1 value");
+        assert_eq!(hardware.memory[start_address + 1], 0x7e, "Incorrect byte 2 value");
+        assert_eq!(hardware.memory[start_address + 2], 0xe7, "Incorrect byte 3 value");
+        assert_eq!(hardware.memory[start_address + 8], 0x7e, "Incorrect byte 9 value");
+        assert_eq!(hardware.memory[start_address + 9], 0x3c, "Incorrect byte 10 value");
+    }
+
+    #[test]
+    fn big_sprite_fonts_do_not_overlap_small_fonts() {
+        let hardware = Hardware::new();
+        let small_font_end = hardware.font_addresses[&0xf] + 5;
+        assert!(hardware.big_font_addresses[&0x0] >= small_font_end, "Big font overlaps the small font");
+        assert!(hardware.big_font_addresses[&0xf] + 10 <= 0x200, "Big font overlaps program memory");
+    }
+
+    #[test]
+    fn multiple_keys_can_be_held_at_once() {
+        let mut hardware = Hardware::new();
+        hardware.press_key(0x1);
+        hardware.press_key(0xf);
+
+        assert!(hardware.is_key_down(0x1), "Expected key 1 to be down");
+        assert!(hardware.is_key_down(0xf), "Expected key f to be down");
+        assert!(!hardware.is_key_down(0x2), "Expected key 2 to be up");
+    }
+
+    #[test]
+    fn releasing_a_held_key_records_a_release_edge() {
+        let mut hardware = Hardware::new();
+        hardware.press_key(0x3);
+        hardware.press_key(0x4);
+        hardware.release_key(0x3);
+
+        assert!(!hardware.is_key_down(0x3), "Expected key 3 to be up");
+        assert!(hardware.is_key_down(0x4), "Expected key 4 to still be down");
+        assert_eq!(hardware.keys_released, 1 << 3, "Incorrect released keys");
+
+        hardware.clear_key_edges();
+        assert_eq!(hardware.keys_released, 0, "Expected release edges to be cleared");
+    }
+
+    #[test]
+    fn releasing_a_key_that_was_not_held_is_ignored() {
+        let mut hardware = Hardware::new();
+        hardware.release_key(0x3);
+        assert_eq!(hardware.keys_released, 0, "Expected no released keys");
+    }
+
+    #[test]
+    fn buzzer_is_active_until_sound_timer_runs_out() {
+        let mut hardware = Hardware::new();
+        hardware.sound_timer = 1;
+        assert!(hardware.is_buzzer_active(), "Expected buzzer to be active");
+
+        hardware.simulate_timer_tick();
+        assert!(!hardware.is_buzzer_active(), "Expected buzzer to be inactive");
+    }
+
+    #[test]
+    fn memory_size_depends_on_platform() {
+        let mut hardware = Hardware::new();
+        assert_eq!(hardware.memory_size(), MEMORY_SIZE, "Incorrect CHIP-8 memory size");
+
+        hardware.platform = Platform::XoChip;
+        assert_eq!(hardware.memory_size(), 0x10000, "Incorrect XO-CHIP memory size");
+    }
+
+    #[test]
+    fn pixel_combines_both_bitplanes() {
+        let mut hardware = Hardware::new();
+        hardware.framebuffer[3][1] = 0b0100_0000;
+        hardware.second_framebuffer[3][1] = 0b0110_0000;
+
+        assert_eq!(hardware.pixel(8, 3), 0, "Incorrect value for unset pixel");
+        assert_eq!(hardware.pixel(9, 3), 3, "Incorrect value for pixel on both planes");
+        assert_eq!(hardware.pixel(10, 3), 2, "Incorrect value for pixel on second plane");
+    }
+
+    #[test]
+    fn can_get_long_instruction_at_program_counter() {
+        let mut hardware = Hardware::new();
+        hardware.program_counter = 0x300;
+        hardware.memory[0x300] = 0xf0;
+        hardware.memory[0x301] = 0x00;
+        hardware.memory[0x302] = 0xab;
+        hardware.memory[0x303] = 0xcd;
+
+        let instruction = hardware.get_current_instruction();
+        assert_eq!(instruction, Instruction::LoadLongAddressIntoIRegister {address: 0xabcd});
+    }
+
+    #[test]
+    fn display_size_depends_on_resolution_mode() {
+        let mut hardware = Hardware::new();
+        assert_eq!((hardware.display_width(), hardware.display_height()), (64, 32), "Incorrect low resolution size");
+
+        hardware.high_resolution = true;
+        assert_eq!((hardware.display_width(), hardware.display_height()), (128, 64), "Incorrect high resolution size");
+    }
+
+    #[test]
+    fn can_describe_registers() {
+        let mut hardware = Hardware::new();
+        hardware.gen_registers[0xa] = 0x5c;
+        hardware.i_register = 0x300;
+        hardware.stack[0] = 0x202;
+        hardware.stack_pointer = 1;
+
+        let expected = "V0=00 V1=00 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00\n\
+                        V8=00 V9=00 VA=5c VB=00 VC=00 VD=00 VE=00 VF=00\n\
+                        I=0300 PC=0200 DT=00 ST=00 SP=1\n\
+                        Stack: [0202]";
+        assert_eq!(hardware.describe_registers(), expected, "Incorrect register description");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,324 @@
+use std::collections::VecDeque;
+use crate::{Hardware, Platform, Quirks, Random};
+use crate::hardware::{Framebuffer, FRAMEBUFFER_WIDTH, STACK_SIZE, FLAG_REGISTER_COUNT, AUDIO_PATTERN_SIZE};
+
+// Bytes compared at a time when looking for changes, so unchanged memory is skipped quickly
+const DIFF_CHUNK_SIZE: usize = 64;
+
+// Everything in the hardware except memory and the framebuffers, which are too big to copy on
+// every instruction and are stored as the bytes that changed instead
+#[derive(Clone)]
+struct CpuState {
+    gen_registers: [u8; 16],
+    i_register: u16,
+    sound_timer: u8,
+    delay_timer: u8,
+    program_counter: u16,
+    stack: [u16; STACK_SIZE],
+    stack_pointer: usize,
+    keys_down: u16,
+    keys_released: u16,
+    selected_planes: u8,
+    platform: Platform,
+    high_resolution: bool,
+    flag_registers: [u8; FLAG_REGISTER_COUNT],
+    halted: bool,
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pitch: u8,
+    quirks: Quirks,
+    vertical_blank: bool,
+    random: Random,
+}
+
+struct Snapshot {
+    cpu: CpuState,
+    memory: Vec<u8>,
+    framebuffers: Vec<u8>,
+}
+
+// How to get from a recorded state back to the one recorded before it
+struct Delta {
+    cpu: CpuState,
+    memory: Vec<(u32, u8)>,
+    framebuffers: Vec<(u16, u8)>,
+}
+
+// Bounded rewind buffer.  Only the most recently recorded state is kept in full, and each older
+// state is stored as the bytes that differ from the state after it, so an instruction that
+// writes nothing to memory costs little more than a copy of the registers.  Once full, recording
+// a new state drops the oldest one.
+pub struct History {
+    capacity: usize,
+    deltas: VecDeque<Delta>,
+    latest: Option<Snapshot>,
+}
+
+impl History {
+    // Capacity is the number of steps that can be rewound
+    pub fn new(capacity: usize) -> Self {
+        History {
+            capacity,
+            deltas: VecDeque::new(),
+            latest: None,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // Number of steps that can currently be rewound
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+        self.latest = None;
+    }
+
+    // Forgets all history and starts recording from the given state, such as after loading a ROM
+    pub fn reset(&mut self, hardware: &Hardware) {
+        self.clear();
+        self.record(hardware);
+    }
+
+    // Records the hardware's state, which should be done after every instruction.  The first
+    // recording only sets the starting point.
+    pub fn record(&mut self, hardware: &Hardware) {
+        let latest = match self.latest {
+            Some(ref mut latest) => latest,
+            None => {
+                self.latest = Some(Snapshot::capture(hardware));
+                return;
+            }
+        };
+
+        if self.capacity == 0 {
+            *latest = Snapshot::capture(hardware);
+            return;
+        }
+
+        let framebuffers = flatten_framebuffers(hardware);
+        let delta = Delta {
+            cpu: std::mem::replace(&mut latest.cpu, CpuState::capture(hardware)),
+            memory: update_and_diff(&mut latest.memory, &hardware.memory).into_iter()
+                .map(|(index, value)| (index as u32, value))
+                .collect(),
+            framebuffers: update_and_diff(&mut latest.framebuffers, &framebuffers).into_iter()
+                .map(|(index, value)| (index as u16, value))
+                .collect(),
+        };
+
+        if self.deltas.len() >= self.capacity {
+            self.deltas.pop_front();
+        }
+
+        self.deltas.push_back(delta);
+    }
+
+    // Moves the hardware back to the state recorded before the latest one.  Returns false if
+    // there is nothing left to rewind.
+    pub fn rewind(&mut self, hardware: &mut Hardware) -> bool {
+        let (delta, latest) = match (self.deltas.pop_back(), self.latest.as_mut()) {
+            (Some(delta), Some(latest)) => (delta, latest),
+            _ => return false,
+        };
+
+        latest.cpu = delta.cpu;
+        for (index, value) in delta.memory {
+            latest.memory[index as usize] = value;
+        }
+
+        for (index, value) in delta.framebuffers {
+            latest.framebuffers[index as usize] = value;
+        }
+
+        latest.restore(hardware);
+        true
+    }
+}
+
+impl Snapshot {
+    fn capture(hardware: &Hardware) -> Self {
+        Snapshot {
+            cpu: CpuState::capture(hardware),
+            memory: hardware.memory.clone(),
+            framebuffers: flatten_framebuffers(hardware),
+        }
+    }
+
+    fn restore(&self, hardware: &mut Hardware) {
+        self.cpu.restore(hardware);
+        hardware.memory.copy_from_slice(&self.memory);
+
+        let (first, second) = self.framebuffers.split_at(self.framebuffers.len() / 2);
+        unflatten_framebuffer(first, &mut hardware.framebuffer);
+        unflatten_framebuffer(second, &mut hardware.second_framebuffer);
+    }
+}
+
+impl CpuState {
+    fn capture(hardware: &Hardware) -> Self {
+        CpuState {
+            gen_registers: hardware.gen_registers,
+            i_register: hardware.i_register,
+            sound_timer: hardware.sound_timer,
+            delay_timer: hardware.delay_timer,
+            program_counter: hardware.program_counter,
+            stack: hardware.stack,
+            stack_pointer: hardware.stack_pointer,
+            keys_down: hardware.keys_down,
+            keys_released: hardware.keys_released,
+            selected_planes: hardware.selected_planes,
+            platform: hardware.platform,
+            high_resolution: hardware.high_resolution,
+            flag_registers: hardware.flag_registers,
+            halted: hardware.halted,
+            audio_pattern: hardware.audio_pattern,
+            pitch: hardware.pitch,
+            quirks: hardware.quirks,
+            vertical_blank: hardware.vertical_blank,
+            random: hardware.random,
+        }
+    }
+
+    fn restore(&self, hardware: &mut Hardware) {
+        hardware.gen_registers = self.gen_registers;
+        hardware.i_register = self.i_register;
+        hardware.sound_timer = self.sound_timer;
+        hardware.delay_timer = self.delay_timer;
+        hardware.program_counter = self.program_counter;
+        hardware.stack = self.stack;
+        hardware.stack_pointer = self.stack_pointer;
+        hardware.keys_down = self.keys_down;
+        hardware.keys_released = self.keys_released;
+        hardware.selected_planes = self.selected_planes;
+        hardware.platform = self.platform;
+        hardware.high_resolution = self.high_resolution;
+        hardware.flag_registers = self.flag_registers;
+        hardware.halted = self.halted;
+        hardware.audio_pattern = self.audio_pattern;
+        hardware.pitch = self.pitch;
+        hardware.quirks = self.quirks;
+        hardware.vertical_blank = self.vertical_blank;
+        hardware.random = self.random;
+    }
+}
+
+// Copies the current bytes over the old ones, returning the index and old value of each byte
+// that changed
+fn update_and_diff(old: &mut [u8], current: &[u8]) -> Vec<(usize, u8)> {
+    let mut changes = Vec::new();
+    let chunks = old.chunks_mut(DIFF_CHUNK_SIZE).zip(current.chunks(DIFF_CHUNK_SIZE));
+    for (chunk_index, (old_chunk, current_chunk)) in chunks.enumerate() {
+        if old_chunk == current_chunk {
+            continue;
+        }
+
+        for (offset, (old_byte, current_byte)) in old_chunk.iter_mut().zip(current_chunk).enumerate() {
+            if old_byte != current_byte {
+                changes.push((chunk_index * DIFF_CHUNK_SIZE + offset, *old_byte));
+                *old_byte = *current_byte;
+            }
+        }
+    }
+
+    changes
+}
+
+fn flatten_framebuffers(hardware: &Hardware) -> Vec<u8> {
+    hardware.framebuffer.iter()
+        .chain(hardware.second_framebuffer.iter())
+        .flat_map(|row| row.iter().cloned())
+        .collect()
+}
+
+fn unflatten_framebuffer(bytes: &[u8], framebuffer: &mut Framebuffer) {
+    for (row, chunk) in framebuffer.iter_mut().zip(bytes.chunks(FRAMEBUFFER_WIDTH / 8)) {
+        row.copy_from_slice(chunk);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Instruction, Register, execute_instruction};
+
+    fn run(hardware: &mut Hardware, history: &mut History, instruction: Instruction) {
+        execute_instruction(instruction, hardware).unwrap();
+        history.record(hardware);
+    }
+
+    #[test]
+    fn rewinds_registers_memory_and_framebuffer() {
+        let mut hardware = Hardware::new();
+        let mut history = History::new(10);
+        history.reset(&hardware);
+        let original = hardware.clone();
+
+        hardware.gen_registers[0] = 0x42;
+        hardware.i_register = 0x300;
+        run(&mut hardware, &mut history, Instruction::LoadIntoMemory {last_register: Register::General(0)});
+        run(&mut hardware, &mut history, Instruction::LoadSpriteLocation {sprite_digit: Register::General(1)});
+        run(&mut hardware, &mut history, Instruction::DrawSprite {x_register: Register::General(1), y_register: Register::General(1), height: 5});
+        assert_eq!(history.len(), 3, "Incorrect history length");
+
+        assert!(history.rewind(&mut hardware), "Expected rewind to succeed");
+        assert_eq!(hardware.framebuffer[0][0], 0, "Sprite should have been undrawn");
+        assert_eq!(hardware.i_register, 0, "I should be back to the font location");
+
+        assert!(history.rewind(&mut hardware), "Expected rewind to succeed");
+        assert!(history.rewind(&mut hardware), "Expected rewind to succeed");
+        assert_eq!(hardware.memory, original.memory, "Memory should be back to the original");
+        assert_eq!(hardware.program_counter, original.program_counter, "Incorrect program counter");
+        assert_eq!(hardware.gen_registers, original.gen_registers, "Incorrect registers");
+        assert!(!history.rewind(&mut hardware), "Should not be able to rewind past the start");
+    }
+
+    #[test]
+    fn oldest_steps_are_dropped_once_full() {
+        let mut hardware = Hardware::new();
+        let mut history = History::new(3);
+        history.reset(&hardware);
+
+        for _ in 0..5 {
+            run(&mut hardware, &mut history, Instruction::AddFromValue {register: Register::General(0), value: 1});
+        }
+
+        assert_eq!(history.len(), 3, "Incorrect history length");
+        while history.rewind(&mut hardware) {}
+        assert_eq!(hardware.gen_registers[0], 2, "Should only rewind back to the oldest kept step");
+    }
+
+    #[test]
+    fn rewinding_restores_random_state() {
+        let mut hardware = Hardware::new();
+        let mut history = History::new(10);
+        history.reset(&hardware);
+
+        let instruction = Instruction::SetRandom {register: Register::General(0), and_value: 0xff};
+        run(&mut hardware, &mut history, instruction.clone());
+        run(&mut hardware, &mut history, instruction.clone());
+        let value = hardware.gen_registers[0];
+
+        history.rewind(&mut hardware);
+        run(&mut hardware, &mut history, instruction);
+        assert_eq!(hardware.gen_registers[0], value, "Replaying after a rewind should generate the same value");
+    }
+
+    #[test]
+    fn zero_capacity_records_nothing() {
+        let mut hardware = Hardware::new();
+        let mut history = History::new(0);
+        history.reset(&hardware);
+        run(&mut hardware, &mut history, Instruction::ClearDisplay);
+
+        assert!(history.is_empty(), "Expected no history");
+        assert!(!history.rewind(&mut hardware), "Expected nothing to rewind");
+    }
+}
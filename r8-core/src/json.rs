@@ -0,0 +1,278 @@
+use custom_error::custom_error;
+
+custom_error!{pub JsonError
+    Syntax {line: usize, reason: String} = "line {line}: {reason}",
+}
+
+// Enough JSON for the community CHIP-8 database and JSON save states
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Boolean(bool),
+    Number(String), // As written, so 64 bit values aren't rounded by going through a float
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>), // In file order
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+
+    // None for numbers that are negative or have a fraction or exponent
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(values) => values,
+            _ => &[],
+        }
+    }
+
+    pub fn fields(&self) -> &[(String, Json)] {
+        match self {
+            Json::Object(fields) => fields,
+            _ => &[],
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut parser = Parser {characters: text.chars().collect(), position: 0};
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.position == parser.characters.len() {
+            true => Ok(value),
+            false => Err(parser.error("unexpected text after the end")),
+        }
+    }
+}
+
+struct Parser {
+    characters: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Json::String),
+            Some('t') => self.literal("true", Json::Boolean(true)),
+            Some('f') => self.literal("false", Json::Boolean(false)),
+            Some('n') => self.literal("null", Json::Null),
+            Some(x) if x == '-' || x.is_ascii_digit() => self.number(),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        let mut fields = Vec::new();
+        self.position += 1;
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(Json::Object(fields));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.take() {
+                Some(',') => continue,
+                Some('}') => return Ok(Json::Object(fields)),
+                _ => return Err(self.error("expected , or } in object")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        let mut values = Vec::new();
+        self.position += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(Json::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.take() {
+                Some(',') => continue,
+                Some(']') => return Ok(Json::Array(values)),
+                _ => return Err(self.error("expected , or ] in array")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.take() {
+                Some('"') => return Ok(text),
+                Some('\\') => match self.take() {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some('r') => text.push('\r'),
+                    Some('b') => text.push('\u{8}'),
+                    Some('f') => text.push('\u{c}'),
+                    Some('u') => {
+                        let digits = self.characters.get(self.position..self.position + 4)
+                            .map(|x| x.iter().collect::<String>())
+                            .ok_or_else(|| self.error("incomplete unicode escape"))?;
+
+                        self.position += 4;
+                        let code = u32::from_str_radix(&digits, 16).map_err(|_| self.error("invalid unicode escape"))?;
+                        text.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+
+                    Some(x) => text.push(x),
+                    None => break,
+                },
+
+                Some(x) => text.push(x),
+                None => break,
+            }
+        }
+
+        Err(self.error("string is missing its closing quote"))
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.position;
+        while self.peek().is_some_and(|x| x.is_ascii_digit() || "+-.eE".contains(x)) {
+            self.position += 1;
+        }
+
+        let text = self.characters[start..self.position].iter().collect::<String>();
+        match text.parse::<f64>() {
+            Ok(_) => Ok(Json::Number(text)),
+            Err(_) => Err(self.error("invalid number")),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, JsonError> {
+        for expected in word.chars() {
+            if self.take() != Some(expected) {
+                return Err(self.error("expected a value"));
+            }
+        }
+
+        Ok(value)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        match self.take() == Some(expected) {
+            true => Ok(()),
+            false => Err(self.error(&format!("expected '{}'", expected))),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.characters.get(self.position).cloned()
+    }
+
+    fn take(&mut self) -> Option<char> {
+        let character = self.peek();
+        self.position += 1;
+        character
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    // Reports the line the parser stopped on, since the database files are large
+    fn error(&self, message: &str) -> JsonError {
+        let end = self.position.min(self.characters.len());
+        let line = self.characters[..end].iter().filter(|x| **x == '\n').count() + 1;
+        JsonError::Syntax {line, reason: message.to_owned()}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn syntax_error_line(text: &str) -> usize {
+        match Json::parse(text).unwrap_err() {
+            JsonError::Syntax {line, ..} => line,
+        }
+    }
+
+    #[test]
+    fn escapes_are_decoded() {
+        let json = Json::parse(r#""a\"b\\c\/d\n\téA""#).unwrap();
+        assert_eq!(json.as_str(), Some("a\"b\\c/d\n\té\u{41}"), "Incorrect string");
+    }
+
+    #[test]
+    fn nested_values_can_be_read() {
+        let json = Json::parse(r#"{"a": [1, {"b": [true, null]}, "c"], "d": {}, "e": -2.5e1}"#).unwrap();
+        let array = json.get("a").unwrap().as_array();
+        assert_eq!(array.len(), 3, "Incorrect array length");
+        assert_eq!(array[0].as_u64(), Some(1), "Incorrect number");
+        assert_eq!(array[1].get("b"), Some(&Json::Array(vec![Json::Boolean(true), Json::Null])), "Incorrect nested array");
+        assert_eq!(array[2].as_str(), Some("c"), "Incorrect string");
+        assert_eq!(json.get("d"), Some(&Json::Object(Vec::new())), "Incorrect empty object");
+        assert_eq!(json.get("e").and_then(Json::as_f64), Some(-25.0), "Incorrect float");
+        assert_eq!(json.fields().iter().map(|(x, _)| x.as_str()).collect::<Vec<_>>(), ["a", "d", "e"], "Fields should be in file order");
+    }
+
+    #[test]
+    fn large_integers_are_exact() {
+        let json = Json::parse("18446744073709551615").unwrap();
+        assert_eq!(json.as_u64(), Some(u64::MAX), "Incorrect number");
+        assert_eq!(Json::parse("-1").unwrap().as_u64(), None, "Negative numbers aren't u64s");
+        assert_eq!(Json::parse("1.5").unwrap().as_u64(), None, "Fractions aren't u64s");
+    }
+
+    #[test]
+    fn errors_give_the_line() {
+        assert_eq!(syntax_error_line("{\n  \"a\": 1,\n  \"b\" 2\n}"), 3, "Expected missing colon on line 3");
+        assert_eq!(syntax_error_line("[1, 2"), 1, "Expected unterminated array on line 1");
+        assert_eq!(syntax_error_line("\"abc"), 1, "Expected unterminated string on line 1");
+        assert_eq!(syntax_error_line("{}\n\nx"), 3, "Expected trailing text on line 3");
+        assert_eq!(syntax_error_line("[tru]"), 1, "Expected bad literal on line 1");
+        assert_eq!(syntax_error_line("[1.2.3]"), 1, "Expected bad number on line 1");
+    }
+}
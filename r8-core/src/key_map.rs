@@ -0,0 +1,272 @@
+use custom_error::custom_error;
+use crate::config::{ConfigEntry, KEY_MAP_SECTION};
+
+// How far a stick has to be pushed, out of 100, before it counts as pressing a key
+const AXIS_THRESHOLD: f32 = 50.0;
+
+custom_error!{pub KeyMapError
+    InvalidKey {origin: String, key: String} = "{origin}: '{key}' is not a CHIP-8 key, expected 0 to f",
+    InvalidBinding {origin: String, reason: String} = "{origin}: {reason}",
+}
+
+// Something on a keyboard or gamepad that can press a CHIP-8 key.  Buttons and axes work the same
+// on every connected gamepad.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input<K, A> {
+    Key(K),
+    Button(u32),
+    Axis(A, bool), // The axis and whether it's the positive direction
+}
+
+// Which CHIP-8 key each input presses, for any frontend's key and axis types.  It keeps track of
+// which inputs are held, so a CHIP-8 key bound to several of them is only released once the last
+// one lets go.
+pub struct KeyMap<K: 'static, A: 'static> {
+    key_names: &'static [(&'static str, K)], // The names used in key map files
+    axis_names: &'static [(&'static str, A)],
+    bindings: Vec<(Input<K, A>, u8)>,
+    held: Vec<(Input<K, A>, u8)>,
+}
+
+impl<K: Copy + PartialEq, A: Copy + PartialEq> KeyMap<K, A> {
+    pub fn new(key_names: &'static [(&'static str, K)], axis_names: &'static [(&'static str, A)], default_bindings: &[(u8, K)]) -> Self {
+        KeyMap {
+            key_names,
+            axis_names,
+            bindings: default_bindings.iter().map(|(value, key)| (Input::Key(*key), *value)).collect(),
+            held: Vec::new(),
+        }
+    }
+
+    // Applies the `[keys]` section, where each CHIP-8 key is given a list of bindings such as
+    // `5 = ["W", "Up", "Button0", "AxisY-"]` that replace all of that key's earlier bindings
+    pub fn apply(&mut self, entries: &[ConfigEntry]) -> Result<(), KeyMapError> {
+        for entry in entries.iter().filter(|x| x.section == KEY_MAP_SECTION) {
+            let origin = format!("line {}", entry.line);
+            let value = u8::from_str_radix(&entry.key, 16).ok().filter(|x| *x < 16)
+                .ok_or_else(|| KeyMapError::InvalidKey {origin: origin.clone(), key: entry.key.clone()})?;
+
+            self.bindings.retain(|(_, x)| *x != value);
+            for binding in entry.value.as_array() {
+                let name = binding.as_str()
+                    .ok_or_else(|| KeyMapError::InvalidBinding {origin: origin.clone(), reason: "key names need quotes".to_owned()})?;
+
+                let input = self.parse_input(name).map_err(|reason| KeyMapError::InvalidBinding {origin: origin.clone(), reason})?;
+                self.bind(input, value);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Adds to the existing bindings rather than replacing them
+    pub fn add_bindings(&mut self, origin: &str, bindings: &[(u8, String)]) -> Result<(), KeyMapError> {
+        for (value, name) in bindings {
+            let input = self.parse_input(name).map_err(|reason| KeyMapError::InvalidBinding {origin: origin.to_owned(), reason})?;
+            self.bind(input, *value);
+        }
+
+        Ok(())
+    }
+
+    pub fn binding(&self, input: Input<K, A>) -> Option<u8> {
+        self.bindings.iter().find(|(x, _)| *x == input).map(|(_, value)| *value)
+    }
+
+    // Returns the CHIP-8 key the input presses, unless it's unbound or another input already
+    // holds that key down
+    pub fn press(&mut self, input: Input<K, A>) -> Option<u8> {
+        let value = self.binding(input)?;
+        let was_pressed = self.is_held(value);
+        if !self.held.contains(&(input, value)) {
+            self.held.push((input, value));
+        }
+
+        match was_pressed {
+            true => None,
+            false => Some(value),
+        }
+    }
+
+    // Returns the CHIP-8 key the input releases, unless the input wasn't holding it or another
+    // input still is
+    pub fn release(&mut self, input: Input<K, A>) -> Option<u8> {
+        let value = self.binding(input)?;
+        let was_holding = self.held.contains(&(input, value));
+        self.held.retain(|x| *x != (input, value));
+
+        match was_holding && !self.is_held(value) {
+            true => Some(value),
+            false => None,
+        }
+    }
+
+    // Returns each CHIP-8 key the new position presses or releases, and whether it's now pressed
+    pub fn axis_moved(&mut self, axis: A, position: f32) -> Vec<(u8, bool)> {
+        let directions = [(true, position > AXIS_THRESHOLD), (false, position < -AXIS_THRESHOLD)];
+        let mut changes = Vec::new();
+        for (is_positive, is_pushed) in directions.iter() {
+            let input = Input::Axis(axis, *is_positive);
+            let change = match is_pushed {
+                true => self.press(input).map(|value| (value, true)),
+                false => self.release(input).map(|value| (value, false)),
+            };
+
+            changes.extend(change);
+        }
+
+        changes
+    }
+
+    fn is_held(&self, value: u8) -> bool {
+        self.held.iter().any(|(_, x)| *x == value)
+    }
+
+    // Each input presses a single CHIP-8 key, so binding it again moves it
+    fn bind(&mut self, input: Input<K, A>, value: u8) {
+        self.bindings.retain(|(x, _)| *x != input);
+        self.bindings.push((input, value));
+    }
+
+    fn parse_input(&self, name: &str) -> Result<Input<K, A>, String> {
+        let lowercase = name.to_lowercase();
+        if let Some(number) = lowercase.strip_prefix("button") {
+            return number.parse::<u32>()
+                .map(Input::Button)
+                .map_err(|_| format!("'{}' is not a gamepad button such as Button0", name));
+        }
+
+        if let Some(axis) = lowercase.strip_prefix("axis") {
+            let (axis, is_positive) = match (axis.strip_suffix('+'), axis.strip_suffix('-')) {
+                (Some(axis), _) => (axis, true),
+                (_, Some(axis)) => (axis, false),
+                _ => return Err(format!("'{}' needs a direction, such as AxisX+ or AxisX-", name)),
+            };
+
+            let axis_names = self.axis_names.iter().map(|(axis_name, _)| *axis_name).collect::<Vec<_>>();
+            return self.axis_names.iter().find(|(axis_name, _)| axis_name.eq_ignore_ascii_case(axis))
+                .map(|(_, axis)| Input::Axis(*axis, is_positive))
+                .ok_or_else(|| format!("'{}' is not a gamepad axis, expected {}", name, axis_names.join(", ")));
+        }
+
+        self.key_names.iter().find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+            .map(|(_, key)| Input::Key(*key))
+            .ok_or_else(|| format!("'{}' is not a key that can be bound", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Key {
+        W,
+        Up,
+        Num1,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Axis {
+        X,
+        PovY,
+    }
+
+    const KEY_NAMES: [(&str, Key); 3] = [("W", Key::W), ("Up", Key::Up), ("Num1", Key::Num1)];
+    const AXIS_NAMES: [(&str, Axis); 2] = [("X", Axis::X), ("PovY", Axis::PovY)];
+    const DEFAULT_BINDINGS: [(u8, Key); 2] = [(0x1, Key::Num1), (0x5, Key::W)];
+
+    fn key_map(text: &str) -> Result<KeyMap<Key, Axis>, KeyMapError> {
+        let mut key_map = KeyMap::new(&KEY_NAMES, &AXIS_NAMES, &DEFAULT_BINDINGS);
+        key_map.apply(&parse_config(text).unwrap())?;
+        Ok(key_map)
+    }
+
+    fn invalid_binding_reason(text: &str) -> String {
+        match key_map(text) {
+            Err(KeyMapError::InvalidBinding {reason, ..}) => reason,
+            x => panic!("Expected InvalidBinding, instead got {:?}", x.err()),
+        }
+    }
+
+    #[test]
+    fn key_names_ignore_case() {
+        let key_map = key_map("[keys]\n5 = [\"up\", \"NUM1\"]").unwrap();
+        assert_eq!(key_map.binding(Input::Key(Key::Up)), Some(0x5), "Incorrect binding for up");
+        assert_eq!(key_map.binding(Input::Key(Key::Num1)), Some(0x5), "Num1 should move to 5");
+        assert!(invalid_binding_reason("[keys]\n5 = \"Space\"").contains("not a key"), "Expected an unknown key error");
+    }
+
+    #[test]
+    fn listed_keys_lose_their_earlier_bindings() {
+        let key_map = key_map("[keys]\n5 = \"Up\"\n[other]\n1 = \"W\"").unwrap();
+        assert_eq!(key_map.binding(Input::Key(Key::W)), None, "W should be unbound from 5");
+        assert_eq!(key_map.binding(Input::Key(Key::Up)), Some(0x5), "Incorrect binding for up");
+        assert_eq!(key_map.binding(Input::Key(Key::Num1)), Some(0x1), "Keys outside the keys section should be ignored");
+
+        let key_map = self::key_map("[keys]\n5 = []").unwrap();
+        assert_eq!(key_map.binding(Input::Key(Key::W)), None, "An empty list should unbind the key");
+    }
+
+    #[test]
+    fn added_bindings_keep_earlier_ones() {
+        let mut key_map = key_map("").unwrap();
+        key_map.add_bindings("ROM database", &[(0x5, "Up".to_owned()), (0x8, "Button1".to_owned())]).unwrap();
+        assert_eq!(key_map.binding(Input::Key(Key::W)), Some(0x5), "W should still press 5");
+        assert_eq!(key_map.binding(Input::Key(Key::Up)), Some(0x5), "Incorrect binding for up");
+        assert_eq!(key_map.binding(Input::Button(1)), Some(0x8), "Incorrect binding for button 1");
+
+        match key_map.add_bindings("ROM database", &[(0x5, "Nope".to_owned())]).unwrap_err() {
+            KeyMapError::InvalidBinding {ref origin, ..} if origin == "ROM database" => (),
+            x => panic!("Expected InvalidBinding, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn buttons_and_axes_can_be_bound() {
+        let key_map = key_map("[keys]\n2 = [\"Button0\", \"button12\"]\n8 = \"AxisX-\"\n9 = \"axispovy+\"").unwrap();
+        assert_eq!(key_map.binding(Input::Button(0)), Some(0x2), "Incorrect binding for button 0");
+        assert_eq!(key_map.binding(Input::Button(12)), Some(0x2), "Incorrect binding for button 12");
+        assert_eq!(key_map.binding(Input::Axis(Axis::X, false)), Some(0x8), "Incorrect binding for X-");
+        assert_eq!(key_map.binding(Input::Axis(Axis::X, true)), None, "X+ should be unbound");
+        assert_eq!(key_map.binding(Input::Axis(Axis::PovY, true)), Some(0x9), "Incorrect binding for PovY+");
+
+        assert!(invalid_binding_reason("[keys]\n2 = \"ButtonA\"").contains("gamepad button"), "Expected a button error");
+        assert!(invalid_binding_reason("[keys]\n2 = \"AxisX\"").contains("direction"), "Expected a direction error");
+        assert!(invalid_binding_reason("[keys]\n2 = \"AxisQ+\"").contains("X, PovY"), "Expected an axis error listing the axes");
+        assert!(invalid_binding_reason("[keys]\n2 = 3").contains("quotes"), "Expected a quotes error");
+    }
+
+    #[test]
+    fn invalid_chip8_key_gives_its_line() {
+        match key_map("[keys]\n1 = \"W\"\n10 = \"Up\"").err() {
+            Some(KeyMapError::InvalidKey {ref origin, ref key}) if origin == "line 3" && key == "10" => (),
+            x => panic!("Expected InvalidKey on line 3, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn axis_presses_past_the_threshold() {
+        let mut key_map = key_map("[keys]\n4 = \"AxisX-\"\n6 = \"AxisX+\"").unwrap();
+        assert_eq!(key_map.axis_moved(Axis::X, 50.0), vec![], "Exactly at the threshold shouldn't press");
+        assert_eq!(key_map.axis_moved(Axis::X, 60.0), vec![(0x6, true)], "Past the threshold should press");
+        assert_eq!(key_map.axis_moved(Axis::X, 90.0), vec![], "Moving further shouldn't press again");
+        assert_eq!(key_map.axis_moved(Axis::X, -75.0), vec![(0x6, false), (0x4, true)], "Crossing over should swap keys");
+        assert_eq!(key_map.axis_moved(Axis::X, -10.0), vec![(0x4, false)], "Returning to the middle should release");
+        assert_eq!(key_map.axis_moved(Axis::PovY, 100.0), vec![], "Unbound axes shouldn't press anything");
+    }
+
+    #[test]
+    fn axis_does_not_release_a_key_held_on_the_keyboard() {
+        let mut key_map = key_map("[keys]\n5 = [\"W\", \"AxisX+\"]").unwrap();
+        assert_eq!(key_map.press(Input::Key(Key::W)), Some(0x5), "W should press 5");
+        assert_eq!(key_map.axis_moved(Axis::X, 5.0), vec![], "A resting axis shouldn't release 5");
+        assert_eq!(key_map.axis_moved(Axis::X, 80.0), vec![], "5 is already pressed");
+        assert_eq!(key_map.axis_moved(Axis::X, 0.0), vec![], "W still holds 5");
+        assert_eq!(key_map.press(Input::Key(Key::W)), None, "A repeated press shouldn't press again");
+        assert_eq!(key_map.release(Input::Key(Key::W)), Some(0x5), "Releasing W should release 5");
+        assert_eq!(key_map.release(Input::Key(Key::W)), None, "W was already released");
+        assert_eq!(key_map.release(Input::Key(Key::Up)), None, "Unbound keys release nothing");
+    }
+}
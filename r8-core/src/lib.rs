@@ -5,13 +5,52 @@ mod hardware;
 mod parser;
 mod execution;
 mod serializer;
+mod assembler;
+mod disassembler;
+mod quirks;
+mod audio;
+mod machine;
+mod save_state;
+mod random;
+mod debugger;
+mod history;
+mod trace;
+mod movie;
+mod scheduler;
+mod timing;
+mod palette;
+mod json;
+mod sha1;
+mod rom_database;
+mod config;
+mod preferences;
+mod key_map;
 
 use std::fmt;
 
-pub use hardware::Hardware;
-pub use parser::get_instruction;
-pub use execution::execute_instruction;
-pub use serializer::serialize_instruction;
+pub use hardware::{Hardware, Platform};
+pub use parser::{get_instruction, get_instruction_at};
+pub use execution::{execute_instruction, ExecutionError};
+pub use serializer::{serialize_instruction, serialize_instruction_bytes};
+pub use assembler::{assemble, AssemblyError};
+pub use disassembler::{disassemble, Listing, ListingEntry};
+pub use quirks::Quirks;
+pub use random::Random;
+pub use history::History;
+pub use trace::{Tracer, TraceFormat, TraceFilter, TraceRegisters, PendingTrace, TraceEntry, TraceError, read_trace};
+pub use debugger::{Debugger, DebuggerError, Breakpoint, Watchpoint, Condition, Comparison, Operand, BreakReason, parse_address, parse_instruction_name};
+pub use machine::{Machine, RomError};
+pub use save_state::{SaveStateError, SAVE_STATE_VERSION};
+pub use scheduler::{Scheduler, ScheduledEvent, TIMER_FREQUENCY};
+pub use timing::{TimingModel, VIP_CYCLES_PER_TICK};
+pub use movie::{Movie, MovieError, KeyEvent, MOVIE_VERSION, rom_checksum};
+pub use palette::{DEFAULT_PALETTE, parse_color, format_color};
+pub use json::JsonError;
+pub use rom_database::{RomInfo, find_rom_info};
+pub use config::{ConfigValue, ConfigEntry, ConfigError, KEY_MAP_SECTION, parse_config, format_config_value};
+pub use preferences::{Preferences, PreferenceLayer};
+pub use key_map::{KeyMap, KeyMapError, Input};
+pub use audio::{ToneGenerator, WavWriter, DEFAULT_SAMPLE_RATE, DEFAULT_TONE_FREQUENCY, DEFAULT_VOLUME};
 
 // Info sourced from http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#Fx33
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -29,22 +68,38 @@ pub enum Instruction {
     And { register1: Register, register2: Register },
     Call { address: u16 },
     ClearDisplay,
+    DisableHighResolution,
     DrawSprite { x_register: Register, y_register: Register, height: u8 },
+    EnableHighResolution,
+    Exit,
     JumpToAddress { address: u16, add_register_0: bool },
     JumpToMachineCode { address: u16 },
     LoadAddressIntoIRegister { address: u16 },
     LoadBcdValue { source: Register },
+    LoadBigSpriteLocation { sprite_digit: Register },
+    LoadFromFlags { last_register: Register },
     LoadFromKeyPress { destination: Register },
     LoadFromMemory { last_register: Register },
     LoadFromRegister { destination: Register, source: Register },
     LoadFromValue { destination: Register, value: u8 },
+    LoadIntoFlags { last_register: Register },
     LoadIntoMemory { last_register: Register },
+    LoadLongAddressIntoIRegister { address: u16 },
+    LoadAudioPattern,
+    LoadRegisterRange { first_register: Register, last_register: Register },
     LoadSpriteLocation { sprite_digit: Register },
     Or { register1: Register, register2: Register },
     Return,
+    SaveRegisterRange { first_register: Register, last_register: Register },
+    ScrollDown { rows: u8 },
+    ScrollLeft,
+    ScrollRight,
+    ScrollUp { rows: u8 },
+    SelectPlanes { planes: u8 },
+    SetPitch { register: Register },
     SetRandom { register: Register, and_value: u8 },
-    ShiftLeft { register: Register},
-    ShiftRight { register: Register },
+    ShiftLeft { register: Register, source: Register },
+    ShiftRight { register: Register, source: Register },
     SkipIfEqual { register: Register, value: u8 },
     SkipIfKeyNotPressed { register: Register },
     SkipIfKeyPressed { register: Register },
@@ -56,6 +111,16 @@ pub enum Instruction {
     Xor { register1: Register, register2: Register },
 }
 
+impl Instruction {
+    // Number of bytes the instruction takes up in memory
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LoadLongAddressIntoIRegister {..} => 4,
+            _ => 2,
+        }
+    }
+}
+
 impl fmt::Display for Register {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -75,6 +140,9 @@ impl fmt::Display for Instruction {
             Instruction::AddFromRegister {register1, register2} => write!(f, "ADD {}, {}", register1, register2),
             Instruction::Call {address} => write!(f, "CALL {:x}", address),
             Instruction::ClearDisplay => write!(f, "CLS"),
+            Instruction::DisableHighResolution => write!(f, "LOW"),
+            Instruction::EnableHighResolution => write!(f, "HIGH"),
+            Instruction::Exit => write!(f, "EXIT"),
             Instruction::JumpToAddress {address, add_register_0} => match add_register_0 {
                 true => write!(f, "JP v0, {:x}", address),
                 false => write!(f, "JP {:x}", address)
@@ -85,11 +153,24 @@ impl fmt::Display for Instruction {
             Instruction::LoadFromRegister {destination, source} => write!(f, "LD {}, {}", destination, source),
             Instruction::LoadFromKeyPress {destination} => write!(f, "LD {}, K", destination),
             Instruction::LoadSpriteLocation {sprite_digit} => write!(f, "LD F, {}", sprite_digit),
+            Instruction::LoadBigSpriteLocation {sprite_digit} => write!(f, "LD HF, {}", sprite_digit),
             Instruction::LoadBcdValue {source} => write!(f, "LD B, {}", source),
             Instruction::LoadIntoMemory {last_register} => write!(f, "LD [I], {}", last_register),
             Instruction::LoadFromMemory {last_register} => write!(f, "LD {}, [I]", last_register),
+            Instruction::LoadIntoFlags {last_register} => write!(f, "LD R, {}", last_register),
+            Instruction::LoadFromFlags {last_register} => write!(f, "LD {}, R", last_register),
             Instruction::LoadAddressIntoIRegister {address} => write!(f, "LD I, {:x}", address),
+            Instruction::LoadLongAddressIntoIRegister {address} => write!(f, "LDL I, {:x}", address),
+            Instruction::LoadAudioPattern => write!(f, "AUDIO"),
+            Instruction::LoadRegisterRange {first_register, last_register} => write!(f, "LOAD {}, {}", first_register, last_register),
+            Instruction::SaveRegisterRange {first_register, last_register} => write!(f, "SAVE {}, {}", first_register, last_register),
             Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollDown {rows} => write!(f, "SCD {:x}", rows),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollUp {rows} => write!(f, "SCU {:x}", rows),
+            Instruction::SelectPlanes {planes} => write!(f, "PLANE {:x}", planes),
+            Instruction::SetPitch {register} => write!(f, "PITCH {}", register),
             Instruction::SkipIfEqual {register, value} => write!(f, "SE {}, {:x}", register, value),
             Instruction::SkipIfNotEqual {register, value} => write!(f, "SNE {}, {:x}", register, value),
             Instruction::SkipIfRegistersEqual {register1, register2} => write!(f, "SE {}, {}", register1, register2),
@@ -105,8 +186,8 @@ impl fmt::Display for Instruction {
             Instruction::Or {register1, register2} => write!(f, "OR {}, {}", register1, register2),
             Instruction::And {register1, register2} => write!(f, "AND {}, {}", register1, register2),
             Instruction::Xor {register1, register2} => write!(f, "XOR {}, {}", register1, register2),
-            Instruction::ShiftRight {register} => write!(f, "SHR {}", register),
-            Instruction::ShiftLeft {register} => write!(f, "SHL {}", register),
+            Instruction::ShiftRight {register, source} => write!(f, "SHR {}, {}", register, source),
+            Instruction::ShiftLeft {register, source} => write!(f, "SHL {}, {}", register, source),
             Instruction::SetRandom {register, and_value} => write!(f, "RND {}, {:x}", register, and_value),
             Instruction::DrawSprite {x_register, y_register, height} => write!(f, "DRW {}, {}, {:x}", x_register, y_register, height),
         }
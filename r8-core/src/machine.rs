@@ -0,0 +1,307 @@
+use custom_error::custom_error;
+use crate::{Hardware, Instruction, Platform, Random, Scheduler, ScheduledEvent, TimingModel, execute_instruction};
+use crate::execution::ExecutionError;
+
+const PROGRAM_START_ADDRESS: usize = 0x200;
+
+custom_error!{pub RomError
+    RomTooLarge {size: usize, maximum: usize} = "ROM is {size} bytes but only {maximum} bytes fit in memory",
+}
+
+// Owns the hardware and drives it the way a frontend would, so tools and tests don't each have to
+// re-implement the fetch, execute and timer loop.  Timing is driven entirely by the caller, so the
+// same inputs always produce the same result.
+#[derive(Clone)]
+pub struct Machine {
+    pub hardware: Hardware,
+    pub timing: TimingModel,
+}
+
+impl Machine {
+    pub fn new(platform: Platform) -> Self {
+        let mut hardware = Hardware::new();
+        hardware.platform = platform;
+        hardware.quirks = platform.default_quirks();
+
+        Machine {hardware, timing: TimingModel::Fixed}
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), RomError> {
+        let maximum = self.hardware.memory_size() - PROGRAM_START_ADDRESS;
+        if rom.len() > maximum {
+            return Err(RomError::RomTooLarge {size: rom.len(), maximum});
+        }
+
+        for byte in &mut self.hardware.memory[PROGRAM_START_ADDRESS..] {
+            *byte = 0;
+        }
+
+        self.hardware.memory[PROGRAM_START_ADDRESS..PROGRAM_START_ADDRESS + rom.len()].copy_from_slice(rom);
+        self.hardware.program_counter = PROGRAM_START_ADDRESS as u16;
+        Ok(())
+    }
+
+    // Executes the instruction at the program counter, unless the program has exited
+    pub fn step(&mut self) -> Result<(), ExecutionError> {
+        if self.hardware.halted {
+            return Ok(());
+        }
+
+        // The whole instruction has to be in memory, and moving past it is checked when it runs
+        let instruction = self.hardware.get_current_instruction();
+        let address = self.hardware.program_counter;
+        if address as usize + instruction.size() as usize > self.hardware.memory_size() {
+            return Err(ExecutionError::ProgramCounterOutOfBounds {address});
+        }
+
+        let result = execute_instruction(instruction, &mut self.hardware);
+        self.hardware.clear_key_edges();
+
+        result
+    }
+
+    // Executes a fixed number of instructions followed by a single 60hz timer tick
+    pub fn run_frame(&mut self, cycles_per_frame: u32) -> Result<(), ExecutionError> {
+        for _ in 0..cycles_per_frame {
+            if self.hardware.halted {
+                break;
+            }
+
+            self.step()?;
+        }
+
+        self.hardware.simulate_timer_tick();
+        Ok(())
+    }
+
+    // How many of the scheduler's cycles the next instruction takes under the timing model
+    pub fn next_instruction_cycles(&self, scheduler: &Scheduler) -> u32 {
+        let instruction = self.hardware.get_current_instruction();
+        let is_waiting_for_display = match instruction {
+            Instruction::DrawSprite {..} => self.hardware.quirks.display_wait && !self.hardware.vertical_blank,
+            _ => false,
+        };
+
+        match self.timing {
+            TimingModel::CosmacVip if is_waiting_for_display => scheduler.cycles_left_in_tick().max(1),
+            _ => self.timing.instruction_cycles(&instruction, &self.hardware),
+        }
+    }
+
+    // Runs everything the scheduler has due, for frontends that don't need to act between
+    // instructions
+    pub fn run_scheduled(&mut self, scheduler: &mut Scheduler) -> Result<(), ExecutionError> {
+        while let Some(event) = scheduler.next_event() {
+            match event {
+                ScheduledEvent::Cycle => {
+                    let cycles = self.next_instruction_cycles(scheduler);
+                    self.step()?;
+                    scheduler.complete_cycles(cycles);
+                }
+
+                ScheduledEvent::TimerTick => {
+                    self.hardware.simulate_timer_tick();
+                    scheduler.complete_event();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Restarts the random number generator, so runs with the same seed and inputs match exactly
+    pub fn set_seed(&mut self, seed: u64) {
+        self.hardware.random = Random::new(seed);
+    }
+
+    // Sets which keys are held down, with bit n representing key n.  Keys that were down and no
+    // longer are count as released for the next instruction.
+    pub fn set_keys(&mut self, keys: u16) {
+        for key in 0..16_u8 {
+            match keys & (1 << key) > 0 {
+                true => self.hardware.press_key(key),
+                false => self.hardware.release_key(key),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_machine_uses_platform_quirks() {
+        let machine = Machine::new(Platform::SuperChip);
+        assert_eq!(machine.hardware.platform, Platform::SuperChip, "Incorrect platform");
+        assert_eq!(machine.hardware.quirks, Platform::SuperChip.default_quirks(), "Incorrect quirks");
+    }
+
+    #[test]
+    fn can_load_rom_into_program_memory() {
+        let mut machine = Machine::new(Platform::Chip8);
+        machine.hardware.memory[0x300] = 0xaa;
+        machine.load_rom(&[0x12, 0x34]).unwrap();
+
+        assert_eq!(&machine.hardware.memory[0x200..0x202], &[0x12, 0x34], "Incorrect program memory");
+        assert_eq!(machine.hardware.memory[0x300], 0, "Expected old program memory to be cleared");
+        assert_eq!(machine.hardware.program_counter, 0x200, "Incorrect program counter");
+    }
+
+    #[test]
+    fn rom_larger_than_memory_is_an_error() {
+        let mut machine = Machine::new(Platform::Chip8);
+        let rom = vec![0; 0x1000];
+        match machine.load_rom(&rom).unwrap_err() {
+            RomError::RomTooLarge {size: 0x1000, ..} => (),
+            x => panic!("Expected RomTooLarge, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn run_frame_executes_cycles_then_ticks_timers() {
+        // 200: ADD V0, 1 / 202: JP 200
+        let mut machine = Machine::new(Platform::Chip8);
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        machine.hardware.delay_timer = 5;
+
+        machine.run_frame(10).unwrap();
+        assert_eq!(machine.hardware.gen_registers[0], 5, "Incorrect V0 value");
+        assert_eq!(machine.hardware.delay_timer, 4, "Incorrect delay timer");
+    }
+
+    #[test]
+    fn run_scheduled_matches_run_frame() {
+        // 200: ADD V0, 1 / 202: JP 200
+        let rom = [0x70, 0x01, 0x12, 0x00];
+        let mut framed = Machine::new(Platform::Chip8);
+        let mut scheduled = Machine::new(Platform::Chip8);
+        for machine in [&mut framed, &mut scheduled].iter_mut() {
+            machine.load_rom(&rom).unwrap();
+            machine.hardware.delay_timer = 10;
+        }
+
+        let mut scheduler = Scheduler::new(7);
+        scheduler.advance(std::time::Duration::from_millis(50)); // Three ticks
+        scheduled.run_scheduled(&mut scheduler).unwrap();
+        for _ in 0..3 {
+            framed.run_frame(7).unwrap();
+        }
+
+        assert_eq!(scheduled.hardware.gen_registers[0], framed.hardware.gen_registers[0], "Incorrect V0 value");
+        assert_eq!(scheduled.hardware.delay_timer, 7, "Incorrect delay timer");
+    }
+
+    #[test]
+    fn vip_timing_draws_once_per_tick() {
+        // 200: DRW V0, V0, 5 / 202: ADD V1, 1 / 204: JP 200
+        let mut machine = Machine::new(Platform::Chip8);
+        machine.hardware.quirks = crate::Quirks::cosmac_vip();
+        machine.timing = TimingModel::CosmacVip;
+        machine.load_rom(&[0xd0, 0x05, 0x71, 0x01, 0x12, 0x00]).unwrap();
+
+        let mut scheduler = Scheduler::new(crate::VIP_CYCLES_PER_TICK);
+        scheduler.advance(std::time::Duration::from_millis(100)); // Six ticks, but only five can be due
+        machine.run_scheduled(&mut scheduler).unwrap();
+
+        // The first tick waits for the display, then each tick draws once
+        assert_eq!(machine.hardware.gen_registers[1], 4, "Incorrect number of draws");
+    }
+
+    #[test]
+    fn halted_machine_does_not_execute() {
+        let mut machine = Machine::new(Platform::SuperChip);
+        machine.load_rom(&[0x00, 0xfd, 0x70, 0x01]).unwrap();
+
+        machine.run_frame(10).unwrap();
+        assert!(machine.hardware.halted, "Expected machine to be halted");
+        assert_eq!(machine.hardware.gen_registers[0], 0, "Instructions after exit should not run");
+    }
+
+    #[test]
+    fn machines_with_same_seed_generate_same_numbers() {
+        // 200: RND V0, ff / 202: JP 200
+        let rom = [0xc0, 0xff, 0x12, 0x00];
+        let mut first = Machine::new(Platform::Chip8);
+        let mut second = Machine::new(Platform::Chip8);
+        for machine in [&mut first, &mut second].iter_mut() {
+            machine.load_rom(&rom).unwrap();
+            machine.set_seed(5);
+        }
+
+        for _ in 0..10 {
+            first.run_frame(2).unwrap();
+            second.run_frame(2).unwrap();
+            assert_eq!(first.hardware.gen_registers[0], second.hardware.gen_registers[0], "Machines diverged");
+        }
+    }
+
+    #[test]
+    fn running_off_the_end_of_memory_is_an_error() {
+        // FFE: ADD V0, 1
+        let mut machine = Machine::new(Platform::Chip8);
+        machine.hardware.memory[0xffe..0x1000].copy_from_slice(&[0x70, 0x01]);
+        machine.hardware.program_counter = 0xffe;
+        match machine.step().unwrap_err() {
+            ExecutionError::ProgramCounterOutOfBounds {address: 0xffe} => (),
+            x => panic!("Expected ProgramCounterOutOfBounds, instead got {:?}", x),
+        }
+
+        // FFFF: half of an instruction
+        let mut machine = Machine::new(Platform::XoChip);
+        machine.hardware.program_counter = 0xffff;
+        match machine.step().unwrap_err() {
+            ExecutionError::ProgramCounterOutOfBounds {address: 0xffff} => (),
+            x => panic!("Expected ProgramCounterOutOfBounds, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn can_jump_from_last_instruction_in_memory() {
+        // FFE: JP 200
+        let mut machine = Machine::new(Platform::Chip8);
+        machine.hardware.memory[0xffe..0x1000].copy_from_slice(&[0x12, 0x00]);
+        machine.hardware.program_counter = 0xffe;
+        machine.step().unwrap();
+        assert_eq!(machine.hardware.program_counter, 0x200, "Incorrect program counter");
+    }
+
+    #[test]
+    fn skipping_off_the_end_of_memory_is_an_error() {
+        // FFFC: SE V0, 0
+        let mut machine = Machine::new(Platform::XoChip);
+        machine.hardware.memory[0xfffc] = 0x30;
+        machine.hardware.program_counter = 0xfffc;
+        match machine.step().unwrap_err() {
+            ExecutionError::ProgramCounterOutOfBounds {address: 0xfffc} => (),
+            x => panic!("Expected ProgramCounterOutOfBounds, instead got {:?}", x),
+        }
+
+        // FFFA: SE V0, 0
+        // FFFC: LD I, 0x1234
+        let mut machine = Machine::new(Platform::XoChip);
+        machine.hardware.memory[0xfffa..].copy_from_slice(&[0x30, 0x00, 0xf0, 0x00, 0x12, 0x34]);
+        machine.hardware.program_counter = 0xfffa;
+        match machine.step().unwrap_err() {
+            ExecutionError::ProgramCounterOutOfBounds {address: 0xfffa} => (),
+            x => panic!("Expected ProgramCounterOutOfBounds, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn set_keys_releases_keys_no_longer_held() {
+        // 200: LD V3, K
+        let mut machine = Machine::new(Platform::Chip8);
+        machine.load_rom(&[0xf3, 0x0a]).unwrap();
+
+        machine.set_keys(0b0100_0000);
+        machine.step().unwrap();
+        assert_eq!(machine.hardware.program_counter, 0x200, "Should wait while the key is held");
+
+        machine.set_keys(0);
+        machine.step().unwrap();
+        assert_eq!(machine.hardware.program_counter, 0x202, "Should continue once the key is released");
+        assert_eq!(machine.hardware.gen_registers[3], 6, "Incorrect key value");
+        assert_eq!(machine.hardware.keys_released, 0, "Release edges should be cleared after a step");
+    }
+}
@@ -0,0 +1,379 @@
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use custom_error::custom_error;
+use crate::{Machine, Platform, Quirks, RomError, Scheduler, ScheduledEvent, TimingModel};
+use crate::execution::ExecutionError;
+
+pub const MOVIE_VERSION: u32 = 1;
+const MOVIE_HEADER: &str = "r8 movie";
+
+custom_error!{pub MovieError
+    Io {source: io::Error} = "Could not read or write movie: {source}",
+    NotAMovie = "File is not an r8 movie",
+    UnsupportedVersion {version: u32} = "Movie version {version} is not supported",
+    Malformed {line: usize, reason: String} = "Movie line {line} is malformed: {reason}",
+    RomMismatch = "Movie was recorded with a different ROM",
+    Rom {source: RomError} = "Could not load ROM: {source}",
+}
+
+// A key being pressed or released, and how far into the movie it happened
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct KeyEvent {
+    pub frame: u64,
+    pub instruction: u64, // How many of the frame's instructions had run
+    pub key: u8,
+    pub is_pressed: bool,
+}
+
+// Every key press and release and when it happened, along with everything else that affects how
+// a program runs.  Playing it back from a fresh machine with the same ROM repeats the recorded run
+// exactly, even when a key is tapped between two instructions or changed while paused mid-frame.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Movie {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub seed: u64,
+    pub cycles_per_frame: u32, // In the timing model's cycles
+    pub timing: TimingModel,
+    pub rom_checksum: u64,
+    pub length: u64, // In frames
+    key_events: Vec<KeyEvent>, // In the order they happened
+}
+
+impl Movie {
+    pub fn new(platform: Platform, quirks: Quirks, seed: u64, cycles_per_frame: u32, rom: &[u8]) -> Self {
+        Movie {
+            platform,
+            quirks,
+            seed,
+            cycles_per_frame,
+            timing: TimingModel::Fixed,
+            rom_checksum: rom_checksum(rom),
+            length: 0,
+            key_events: Vec::new(),
+        }
+    }
+
+    pub fn key_events(&self) -> &[KeyEvent] {
+        &self.key_events
+    }
+
+    // Records a key changing before the frame's next instruction runs
+    pub fn record_key(&mut self, frame: u64, instruction: u64, key: u8, is_pressed: bool) {
+        self.key_events.push(KeyEvent {frame, instruction, key: key & 0xf, is_pressed});
+        self.length = self.length.max(frame + 1);
+    }
+
+    // Should be called as each frame ends, so frames without any key changes are still played back
+    pub fn record_frame(&mut self, frame: u64) {
+        self.length = self.length.max(frame + 1);
+    }
+
+    // Forgets every recorded frame, for when recording starts over
+    pub fn clear(&mut self) {
+        self.key_events.clear();
+        self.length = 0;
+    }
+
+    // Presses and releases the keys recorded before the frame's next instruction, in the order they
+    // were recorded so a tap between two instructions still leaves a released key behind
+    pub fn apply_key_events(&self, machine: &mut Machine, frame: u64, instruction: u64) {
+        let first = self.key_events.partition_point(|x| (x.frame, x.instruction) < (frame, instruction));
+        let events = self.key_events[first..].iter().take_while(|x| (x.frame, x.instruction) == (frame, instruction));
+        for event in events {
+            match event.is_pressed {
+                true => machine.hardware.press_key(event.key),
+                false => machine.hardware.release_key(event.key),
+            }
+        }
+    }
+
+    // Sets up a machine the way it was when recording started
+    pub fn create_machine(&self, rom: &[u8]) -> Result<Machine, MovieError> {
+        if rom_checksum(rom) != self.rom_checksum {
+            return Err(MovieError::RomMismatch);
+        }
+
+        let mut machine = Machine::new(self.platform);
+        machine.hardware.quirks = self.quirks;
+        machine.timing = self.timing;
+        machine.set_seed(self.seed);
+        machine.load_rom(rom)?;
+        Ok(machine)
+    }
+
+    pub fn create_scheduler(&self) -> Scheduler {
+        Scheduler::new(self.cycles_per_frame)
+    }
+
+    // Runs one frame of the movie, pressing and releasing the recorded keys between the same
+    // instructions they were recorded between.  `scheduler` should come from `create_scheduler`
+    // and be kept for the whole movie.
+    pub fn play_frame(&self, machine: &mut Machine, scheduler: &mut Scheduler, frame: u64) -> Result<(), ExecutionError> {
+        let mut instruction = 0;
+        scheduler.add_tick();
+        while let Some(event) = scheduler.next_event() {
+            self.apply_key_events(machine, frame, instruction);
+            match event {
+                ScheduledEvent::Cycle => {
+                    let cycles = machine.next_instruction_cycles(scheduler);
+                    machine.step()?;
+                    scheduler.complete_cycles(cycles);
+                    instruction += 1;
+                }
+
+                ScheduledEvent::TimerTick => {
+                    machine.hardware.simulate_timer_tick();
+                    scheduler.complete_event();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self, writer: &mut impl Write) -> io::Result<()> {
+        let quirks = &self.quirks;
+        writeln!(writer, "{} {}", MOVIE_HEADER, MOVIE_VERSION)?;
+        writeln!(writer, "platform {:?}", self.platform)?;
+        writeln!(writer, "seed {}", self.seed)?;
+        writeln!(writer, "cycles_per_frame {}", self.cycles_per_frame)?;
+        writeln!(writer, "timing {:?}", self.timing)?;
+        writeln!(writer, "rom_checksum {:016x}", self.rom_checksum)?;
+        writeln!(writer, "length {}", self.length)?;
+        writeln!(writer, "quirks.shift_uses_vy {}", quirks.shift_uses_vy)?;
+        writeln!(writer, "quirks.load_store_increments_i {}", quirks.load_store_increments_i)?;
+        writeln!(writer, "quirks.jump_uses_vx {}", quirks.jump_uses_vx)?;
+        writeln!(writer, "quirks.clip_sprites {}", quirks.clip_sprites)?;
+        writeln!(writer, "quirks.logic_resets_vf {}", quirks.logic_resets_vf)?;
+        writeln!(writer, "quirks.display_wait {}", quirks.display_wait)?;
+
+        for event in &self.key_events {
+            let action = if event.is_pressed { "press" } else { "release" };
+            writeln!(writer, "{} {} {} {:x}", action, event.frame, event.instruction, event.key)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load(reader: &mut impl Read) -> Result<Movie, MovieError> {
+        let mut lines = Vec::new();
+        for line in BufReader::new(reader).lines() {
+            lines.push(line?);
+        }
+
+        match lines.first().and_then(|x| x.strip_prefix(MOVIE_HEADER)) {
+            Some(version) => match version.trim().parse::<u32>() {
+                Ok(MOVIE_VERSION) => (),
+                Ok(version) => return Err(MovieError::UnsupportedVersion {version}),
+                Err(_) => return Err(MovieError::NotAMovie),
+            },
+
+            None => return Err(MovieError::NotAMovie),
+        }
+
+        let mut movie = Movie::new(Platform::Chip8, Quirks::default(), 0, 1, &[]);
+        for (index, line) in lines.iter().enumerate().skip(1) {
+            let line_number = index + 1;
+            let malformed = |reason: &str| MovieError::Malformed {line: line_number, reason: reason.to_owned()};
+            let words = line.split_whitespace().collect::<Vec<&str>>();
+            let value = || words.get(1).cloned().ok_or_else(|| malformed("missing value"));
+            let boolean = || value()?.parse::<bool>().map_err(|_| malformed("expected true or false"));
+            let number = || value()?.parse::<u64>().map_err(|_| malformed("expected a number"));
+
+            match words.first().cloned() {
+                None => continue,
+                Some("platform") => movie.platform = match value()? {
+                    "Chip8" => Platform::Chip8,
+                    "SuperChip" => Platform::SuperChip,
+                    "XoChip" => Platform::XoChip,
+                    _ => return Err(malformed("unknown platform")),
+                },
+
+                Some("seed") => movie.seed = number()?,
+                Some("cycles_per_frame") => movie.cycles_per_frame = number()? as u32,
+                Some("timing") => movie.timing = match value()? {
+                    "Fixed" => TimingModel::Fixed,
+                    "CosmacVip" => TimingModel::CosmacVip,
+                    _ => return Err(malformed("unknown timing model")),
+                },
+
+                Some("rom_checksum") => {
+                    movie.rom_checksum = u64::from_str_radix(value()?, 16).map_err(|_| malformed("expected a hex checksum"))?;
+                }
+
+                Some("length") => movie.length = number()?,
+                Some("quirks.shift_uses_vy") => movie.quirks.shift_uses_vy = boolean()?,
+                Some("quirks.load_store_increments_i") => movie.quirks.load_store_increments_i = boolean()?,
+                Some("quirks.jump_uses_vx") => movie.quirks.jump_uses_vx = boolean()?,
+                Some("quirks.clip_sprites") => movie.quirks.clip_sprites = boolean()?,
+                Some("quirks.logic_resets_vf") => movie.quirks.logic_resets_vf = boolean()?,
+                Some("quirks.display_wait") => movie.quirks.display_wait = boolean()?,
+                Some(action @ "press") | Some(action @ "release") => {
+                    let position = |index: usize| words.get(index).and_then(|x| x.parse::<u64>().ok());
+                    let (frame, instruction) = match (position(1), position(2)) {
+                        (Some(frame), Some(instruction)) => (frame, instruction),
+                        _ => return Err(malformed("expected a frame and instruction number")),
+                    };
+
+                    let key = words.get(3).and_then(|x| u8::from_str_radix(x, 16).ok()).filter(|x| *x <= 0xf)
+                        .ok_or_else(|| malformed("expected a key from 0 to f"))?;
+
+                    if movie.key_events.last().is_some_and(|x| (x.frame, x.instruction) > (frame, instruction)) {
+                        return Err(malformed("key events must be in the order they happened"));
+                    }
+
+                    movie.key_events.push(KeyEvent {frame, instruction, key, is_pressed: action == "press"});
+                }
+
+                Some(_) => return Err(malformed("unknown setting")),
+            }
+        }
+
+        Ok(movie)
+    }
+}
+
+// FNV-1a, which is enough to notice a movie being played back with the wrong ROM
+pub fn rom_checksum(rom: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for byte in rom {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 200: LD V0, K / 202: RND V1, ff / 204: LD I, 300 / 206: LD [I], V1 / 208: JP 200
+    const ROM: [u8; 10] = [0xf0, 0x0a, 0xc1, 0xff, 0xa3, 0x00, 0xf1, 0x55, 0x12, 0x00];
+
+    #[test]
+    fn replay_matches_recording() {
+        let mut movie = Movie::new(Platform::Chip8, Quirks::cosmac_vip(), 1234, 7, &ROM);
+        let mut recorded = movie.create_machine(&ROM).unwrap();
+        for frame in 0..30 {
+            for instruction in 0..movie.cycles_per_frame as u64 {
+                // Taps part way through frames, some pressed and released between the same two instructions
+                let key = (frame % 16) as u8;
+                if frame % 5 == 0 && instruction == frame % 7 {
+                    recorded.hardware.press_key(key);
+                    movie.record_key(frame, instruction, key, true);
+                }
+
+                if frame % 5 == 0 && instruction == (frame + frame % 3) % 7 {
+                    recorded.hardware.release_key(key);
+                    movie.record_key(frame, instruction, key, false);
+                }
+
+                recorded.step().unwrap();
+            }
+
+            recorded.hardware.simulate_timer_tick();
+            movie.record_frame(frame);
+        }
+
+        let mut saved = Vec::new();
+        movie.save(&mut saved).unwrap();
+        let loaded = Movie::load(&mut saved.as_slice()).unwrap();
+        assert_eq!(loaded, movie, "Loaded movie should match the saved one");
+
+        let mut replayed = loaded.create_machine(&ROM).unwrap();
+        let mut scheduler = loaded.create_scheduler();
+        for frame in 0..loaded.length {
+            loaded.play_frame(&mut replayed, &mut scheduler, frame).unwrap();
+        }
+
+        assert_ne!(recorded.hardware.gen_registers[0], 0, "Expected a key press to be read");
+        assert_eq!(replayed.hardware.memory, recorded.hardware.memory, "Memory should match");
+        assert_eq!(replayed.hardware.gen_registers, recorded.hardware.gen_registers, "Registers should match");
+        assert_eq!(replayed.hardware.program_counter, recorded.hardware.program_counter, "Program counter should match");
+        assert_eq!(replayed.hardware.random, recorded.hardware.random, "Random state should match");
+    }
+
+    #[test]
+    fn tap_between_two_instructions_is_replayed() {
+        // 200: LD V0, K / 202: JP 202
+        let rom = [0xf0, 0x0a, 0x12, 0x02];
+        let mut movie = Movie::new(Platform::Chip8, Quirks::default(), 0, 5, &rom);
+        movie.record_key(0, 2, 7, true);
+        movie.record_key(0, 2, 7, false);
+        movie.record_frame(0);
+
+        let mut machine = movie.create_machine(&rom).unwrap();
+        movie.play_frame(&mut machine, &mut movie.create_scheduler(), 0).unwrap();
+        assert_eq!(machine.hardware.gen_registers[0], 7, "Expected the tapped key to be read");
+        assert_eq!(machine.hardware.program_counter, 0x202, "Expected the program to continue after the tap");
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let text = "r8 movie 2\nlength 4\n";
+        match Movie::load(&mut text.as_bytes()).unwrap_err() {
+            MovieError::UnsupportedVersion {version: 2} => (),
+            x => panic!("Expected UnsupportedVersion, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn timing_model_is_saved() {
+        let mut movie = Movie::new(Platform::Chip8, Quirks::cosmac_vip(), 0, crate::VIP_CYCLES_PER_TICK, &ROM);
+        movie.timing = TimingModel::CosmacVip;
+
+        let mut saved = Vec::new();
+        movie.save(&mut saved).unwrap();
+        let machine = Movie::load(&mut saved.as_slice()).unwrap().create_machine(&ROM);
+        assert!(machine.is_ok_and(|x| x.timing == TimingModel::CosmacVip), "Expected the machine to use VIP timing");
+    }
+
+    #[test]
+    fn recording_keys_extends_the_movie() {
+        let mut movie = Movie::new(Platform::Chip8, Quirks::default(), 0, 5, &ROM);
+        movie.record_frame(0);
+        movie.record_key(3, 1, 0xa, true);
+
+        let expected = KeyEvent {frame: 3, instruction: 1, key: 0xa, is_pressed: true};
+        assert_eq!(movie.key_events(), &[expected], "Incorrect key events");
+        assert_eq!(movie.length, 4, "Incorrect length");
+    }
+
+    #[test]
+    fn wrong_rom_is_rejected() {
+        let movie = Movie::new(Platform::Chip8, Quirks::default(), 0, 5, &ROM);
+        match movie.create_machine(&[0x00, 0xe0]) {
+            Err(MovieError::RomMismatch) => (),
+            Err(x) => panic!("Expected RomMismatch, instead got {:?}", x),
+            Ok(_) => panic!("Expected RomMismatch, instead the movie was loaded"),
+        }
+    }
+
+    #[test]
+    fn malformed_line_is_reported() {
+        let text = "r8 movie 1\nseed 5\npress ten 0 1\n";
+        match Movie::load(&mut text.as_bytes()).unwrap_err() {
+            MovieError::Malformed {line: 3, ..} => (),
+            x => panic!("Expected Malformed on line 3, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn out_of_order_key_events_are_malformed() {
+        let text = "r8 movie 1\npress 5 2 1\nrelease 5 1 1\n";
+        match Movie::load(&mut text.as_bytes()).unwrap_err() {
+            MovieError::Malformed {line: 3, ..} => (),
+            x => panic!("Expected Malformed on line 3, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn other_files_are_not_movies() {
+        match Movie::load(&mut "R8ST".as_bytes()).unwrap_err() {
+            MovieError::NotAMovie => (),
+            x => panic!("Expected NotAMovie, instead got {:?}", x),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+// Colors for the background, first bitplane, second bitplane and both bitplanes, indexed by which
+// bitplanes a pixel is on
+pub const DEFAULT_PALETTE: [[u8; 3]; 4] = [
+    [115, 130, 92],
+    [68, 68, 41],
+    [176, 84, 52],
+    [238, 204, 90],
+];
+
+// Reads a color such as #ffcc00
+pub fn parse_color(text: &str) -> Option<[u8; 3]> {
+    let digits = text.trim().strip_prefix('#')?;
+    if digits.len() != 6 || !digits.chars().all(|x| x.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let value = u32::from_str_radix(digits, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+pub fn format_color([red, green, blue]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", red, green, blue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_can_be_parsed_and_formatted() {
+        assert_eq!(parse_color("#ffcc00"), Some([255, 204, 0]), "Incorrect color");
+        assert_eq!(parse_color(" #73825C "), Some([115, 130, 92]), "Incorrect color");
+        assert_eq!(parse_color("ffcc00"), None, "Expected a # prefix");
+        assert_eq!(parse_color("#fc0"), None, "Expected six digits");
+        assert_eq!(parse_color("#+fcc00"), None, "Expected only hex digits");
+        assert_eq!(format_color([115, 130, 92]), "#73825c", "Incorrect text");
+    }
+}
@@ -1,5 +1,17 @@
 use super::{Instruction, Register};
 
+// Decodes the instruction starting at the address, including XO-CHIP's four byte long load
+pub fn get_instruction_at(memory: &[u8], address: usize) -> Instruction {
+    let byte1 = memory.get(address).cloned().unwrap_or(0);
+    let byte2 = memory.get(address + 1).cloned().unwrap_or(0);
+    if (byte1, byte2) == (0xf0, 0x00) && address + 3 < memory.len() {
+        let address = ((memory[address + 2] as u16) << 8) + memory[address + 3] as u16;
+        return Instruction::LoadLongAddressIntoIRegister {address};
+    }
+
+    get_instruction(byte1, byte2)
+}
+
 pub fn get_instruction(byte1: u8, byte2: u8) -> Instruction {
     let value1 = byte1 >> 4;
     let value2 = byte1 & 0x0F;
@@ -15,6 +27,34 @@ pub fn get_instruction(byte1: u8, byte2: u8) -> Instruction {
             Instruction::Return
         },
 
+        (0x0, 0x0, 0xc, _) => {
+            Instruction::ScrollDown {rows: value4}
+        },
+
+        (0x0, 0x0, 0xd, _) => {
+            Instruction::ScrollUp {rows: value4}
+        },
+
+        (0x0, 0x0, 0xf, 0xb) => {
+            Instruction::ScrollRight
+        },
+
+        (0x0, 0x0, 0xf, 0xc) => {
+            Instruction::ScrollLeft
+        },
+
+        (0x0, 0x0, 0xf, 0xd) => {
+            Instruction::Exit
+        },
+
+        (0x0, 0x0, 0xf, 0xe) => {
+            Instruction::DisableHighResolution
+        },
+
+        (0x0, 0x0, 0xf, 0xf) => {
+            Instruction::EnableHighResolution
+        },
+
         (0x0, _, _, _) => {
             let address: u16 = ((value2 as u16) * 16 * 16) + (byte2 as u16);
             Instruction::JumpToMachineCode {address}
@@ -44,6 +84,20 @@ pub fn get_instruction(byte1: u8, byte2: u8) -> Instruction {
             }
         },
 
+        (0x5, _, _, 0x2) => {
+            Instruction::SaveRegisterRange {
+                first_register: Register::General(value2),
+                last_register: Register::General(value3),
+            }
+        },
+
+        (0x5, _, _, 0x3) => {
+            Instruction::LoadRegisterRange {
+                first_register: Register::General(value2),
+                last_register: Register::General(value3),
+            }
+        },
+
         (0x5, _, _, _) => {
             Instruction::SkipIfRegistersEqual {
                 register1: Register::General(value2),
@@ -110,7 +164,8 @@ pub fn get_instruction(byte1: u8, byte2: u8) -> Instruction {
 
         (0x8, _, _, 0x6) => {
             Instruction::ShiftRight {
-                register: Register::General(value2)
+                register: Register::General(value2),
+                source: Register::General(value3),
             }
         },
 
@@ -125,6 +180,7 @@ pub fn get_instruction(byte1: u8, byte2: u8) -> Instruction {
         (0x8, _, _, 0xe) => {
             Instruction::ShiftLeft {
                 register: Register::General(value2),
+                source: Register::General(value3),
             }
         },
 
@@ -179,6 +235,14 @@ pub fn get_instruction(byte1: u8, byte2: u8) -> Instruction {
             }
         },
 
+        (0xf, _, 0x0, 0x1) => {
+            Instruction::SelectPlanes {planes: value2}
+        },
+
+        (0xf, 0x0, 0x0, 0x2) => {
+            Instruction::LoadAudioPattern
+        },
+
         (0xf, _, 0x0, 0xa) => {
             Instruction::LoadFromKeyPress {
                 destination: Register::General(value2),
@@ -212,6 +276,18 @@ pub fn get_instruction(byte1: u8, byte2: u8) -> Instruction {
             }
         },
 
+        (0xf, _, 0x3, 0x0) => {
+            Instruction::LoadBigSpriteLocation {
+                sprite_digit: Register::General(value2),
+            }
+        },
+
+        (0xf, _, 0x3, 0xa) => {
+            Instruction::SetPitch {
+                register: Register::General(value2),
+            }
+        },
+
         (0xf, _, 0x3, 0x3) => {
             Instruction::LoadBcdValue {
                 source: Register::General(value2),
@@ -230,6 +306,18 @@ pub fn get_instruction(byte1: u8, byte2: u8) -> Instruction {
             }
         },
 
+        (0xf, _, 0x7, 0x5) => {
+            Instruction::LoadIntoFlags {
+                last_register: Register::General(value2),
+            }
+        },
+
+        (0xf, _, 0x8, 0x5) => {
+            Instruction::LoadFromFlags {
+                last_register: Register::General(value2),
+            }
+        },
+
         _ => Instruction::Unknown {bytes: ((byte1 as u16) << 8) + byte2 as u16 },
     }
 }
@@ -412,9 +500,10 @@ mod tests {
     fn can_read_shift_right_instruction() {
         let expected = Instruction::ShiftRight {
             register: Register::General(0xb),
+            source: Register::General(0x4),
         };
 
-        let result = get_instruction(0x8b, 0x06);
+        let result = get_instruction(0x8b, 0x46);
         assert_eq!(result, expected);
     }
 
@@ -422,9 +511,10 @@ mod tests {
     fn can_read_shift_left_instruction() {
         let expected = Instruction::ShiftLeft {
             register: Register::General(0xb),
+            source: Register::General(0x4),
         };
 
-        let result = get_instruction(0x8b, 0x0e);
+        let result = get_instruction(0x8b, 0x4e);
         assert_eq!(result, expected);
     }
 
@@ -596,4 +686,143 @@ mod tests {
         let result = get_instruction(0xfb, 0x65);
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn can_read_scroll_down_instruction() {
+        let expected = Instruction::ScrollDown {rows: 0x5};
+        let result = get_instruction(0x00, 0xc5);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_scroll_right_instruction() {
+        let expected = Instruction::ScrollRight;
+        let result = get_instruction(0x00, 0xfb);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_scroll_left_instruction() {
+        let expected = Instruction::ScrollLeft;
+        let result = get_instruction(0x00, 0xfc);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_exit_instruction() {
+        let expected = Instruction::Exit;
+        let result = get_instruction(0x00, 0xfd);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_disable_high_resolution_instruction() {
+        let expected = Instruction::DisableHighResolution;
+        let result = get_instruction(0x00, 0xfe);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_enable_high_resolution_instruction() {
+        let expected = Instruction::EnableHighResolution;
+        let result = get_instruction(0x00, 0xff);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_load_big_sprite_location_instruction() {
+        let expected = Instruction::LoadBigSpriteLocation {
+            sprite_digit: Register::General(0xb),
+        };
+
+        let result = get_instruction(0xfb, 0x30);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_load_into_flags_instruction() {
+        let expected = Instruction::LoadIntoFlags {
+            last_register: Register::General(0x5),
+        };
+
+        let result = get_instruction(0xf5, 0x75);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_load_from_flags_instruction() {
+        let expected = Instruction::LoadFromFlags {
+            last_register: Register::General(0x5),
+        };
+
+        let result = get_instruction(0xf5, 0x85);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_scroll_up_instruction() {
+        let expected = Instruction::ScrollUp {rows: 0x3};
+        let result = get_instruction(0x00, 0xd3);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_save_register_range_instruction() {
+        let expected = Instruction::SaveRegisterRange {
+            first_register: Register::General(0x2),
+            last_register: Register::General(0x7),
+        };
+
+        let result = get_instruction(0x52, 0x72);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_load_register_range_instruction() {
+        let expected = Instruction::LoadRegisterRange {
+            first_register: Register::General(0x7),
+            last_register: Register::General(0x2),
+        };
+
+        let result = get_instruction(0x57, 0x23);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_select_planes_instruction() {
+        let expected = Instruction::SelectPlanes {planes: 0x3};
+        let result = get_instruction(0xf3, 0x01);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_load_audio_pattern_instruction() {
+        let expected = Instruction::LoadAudioPattern;
+        let result = get_instruction(0xf0, 0x02);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_set_pitch_instruction() {
+        let expected = Instruction::SetPitch {
+            register: Register::General(0x4),
+        };
+
+        let result = get_instruction(0xf4, 0x3a);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn can_read_long_load_address_instruction() {
+        let expected = Instruction::LoadLongAddressIntoIRegister {address: 0x1234};
+        let result = get_instruction_at(&[0x00, 0xf0, 0x00, 0x12, 0x34], 1);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn two_byte_instructions_are_read_normally_at_address() {
+        let expected = Instruction::Call {address: 0x345};
+        let result = get_instruction_at(&[0x00, 0x23, 0x45], 1);
+        assert_eq!(result, expected);
+    }
 }
@@ -0,0 +1,346 @@
+use std::str::FromStr;
+use crate::config::{ConfigError, ConfigEntry, ConfigValue, KEY_MAP_SECTION};
+
+// Where a setting came from, lowest priority first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PreferenceLayer {
+    GlobalFile,
+    RomDatabase,
+    DirectoryFile,
+    RomFile,
+    Environment,
+    CommandLine,
+}
+
+struct Preference {
+    key: &'static str,
+    value: ConfigValue,
+    layer: PreferenceLayer,
+    origin: String, // Shown to the user, such as "r8.toml line 3" or "R8_IPS"
+}
+
+// Settings gathered from every layer, where a higher layer wins no matter what order they're added
+// in.  Each setting has a name in config files (section.key for ones in a section) and a command
+// line option.  Its environment variable is the name in capitals with an R8_ prefix, so
+// audio.volume is R8_AUDIO_VOLUME.
+pub struct Preferences {
+    settings: &'static [(&'static str, &'static str)], // Config file name and command line option
+    alternatives: &'static [(&'static str, &'static str)], // Two ways of giving the same thing, so setting one clears the other
+    preferences: Vec<Preference>,
+}
+
+impl Preferences {
+    pub fn new(settings: &'static [(&'static str, &'static str)], alternatives: &'static [(&'static str, &'static str)]) -> Self {
+        Preferences {settings, alternatives, preferences: Vec::new()}
+    }
+
+    // The [keys] section is left for the key map to read
+    pub fn add_file(&mut self, layer: PreferenceLayer, file_name: &str, entries: &[ConfigEntry]) -> Result<(), ConfigError> {
+        for entry in entries.iter().filter(|x| x.section != KEY_MAP_SECTION) {
+            let name = match entry.section.is_empty() {
+                true => entry.key.clone(),
+                false => format!("{}.{}", entry.section, entry.key),
+            };
+
+            let origin = format!("{} line {}", file_name, entry.line);
+            let key = self.settings.iter().map(|(key, _)| *key).find(|key| *key == name)
+                .ok_or_else(|| ConfigError::UnknownSetting {origin: origin.clone(), name})?;
+
+            self.set(key, entry.value.clone(), layer, origin);
+        }
+
+        Ok(())
+    }
+
+    // Takes a function that gives an environment variable's value, if it's set
+    pub fn add_environment(&mut self, variable_value: impl Fn(&str) -> Option<String>) {
+        for (key, _) in self.settings.iter() {
+            let variable = environment_variable(key);
+            if let Some(text) = variable_value(&variable) {
+                self.set(key, ConfigValue::String(text), PreferenceLayer::Environment, variable);
+            }
+        }
+    }
+
+    // Takes a function that gives a command line option's values if it was given, with no values
+    // for a flag
+    pub fn add_command_line(&mut self, option_values: impl Fn(&str) -> Option<Vec<String>>) {
+        for (key, option) in self.settings.iter() {
+            let mut values = match option_values(option) {
+                Some(values) => values.into_iter().map(ConfigValue::String).collect::<Vec<_>>(),
+                None => continue,
+            };
+
+            let value = match values.len() {
+                0 => ConfigValue::Boolean(true),
+                1 => values.remove(0),
+                _ => ConfigValue::Array(values),
+            };
+
+            self.set(key, value, PreferenceLayer::CommandLine, "command line".to_owned());
+        }
+    }
+
+    // Ignored if a higher priority layer already set it, so layers can be added in any order
+    pub fn set(&mut self, key: &'static str, value: ConfigValue, layer: PreferenceLayer, origin: String) {
+        let alternative = self.alternatives.iter()
+            .find_map(|(first, second)| match (key == *first, key == *second) {
+                (true, _) => Some(*second),
+                (_, true) => Some(*first),
+                _ => None,
+            });
+
+        let is_same_setting = |x: &Preference| x.key == key || Some(x.key) == alternative;
+        if self.preferences.iter().any(|x| is_same_setting(x) && x.layer > layer) {
+            return;
+        }
+
+        self.preferences.retain(|x| !is_same_setting(x));
+        self.preferences.push(Preference {key, value, layer, origin});
+    }
+
+    pub fn layer(&self, key: &str) -> Option<PreferenceLayer> {
+        self.find(key).map(|x| x.layer)
+    }
+
+    // Where the setting came from, or "default" if nothing set it
+    pub fn origin(&self, key: &str) -> &str {
+        self.find(key).map(|x| x.origin.as_str()).unwrap_or("default")
+    }
+
+    pub fn text(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        match self.find(key) {
+            Some(Preference {value: ConfigValue::String(text), ..}) => Ok(Some(text.clone())),
+            Some(preference) => Err(preference.error("expected a string in quotes")),
+            None => Ok(None),
+        }
+    }
+
+    // One of a fixed set of names, such as a platform
+    pub fn choice(&self, key: &str, choices: &[&str]) -> Result<Option<String>, ConfigError> {
+        match self.text(key)? {
+            Some(text) if !choices.contains(&text.as_str()) => {
+                Err(self.find(key).unwrap().error(&format!("expected one of {}", choices.join(", "))))
+            }
+
+            text => Ok(text),
+        }
+    }
+
+    // Environment variables and the command line give every number as text
+    pub fn number<T: FromStr>(&self, key: &str) -> Result<Option<T>, ConfigError> {
+        let preference = match self.find(key) {
+            Some(preference) => preference,
+            None => return Ok(None),
+        };
+
+        let text = match &preference.value {
+            ConfigValue::Integer(number) => number.to_string(),
+            ConfigValue::Float(number) => number.to_string(),
+            ConfigValue::String(text) => text.trim().to_owned(),
+            _ => String::new(),
+        };
+
+        text.parse::<T>().map(Some).map_err(|_| preference.error("expected a number"))
+    }
+
+    pub fn flag(&self, key: &str) -> Result<Option<bool>, ConfigError> {
+        match self.find(key) {
+            Some(Preference {value: ConfigValue::Boolean(value), ..}) => Ok(Some(*value)),
+            Some(preference) => match preference.value.as_str().map(|x| x.trim().to_lowercase()).as_deref() {
+                Some("true") | Some("1") | Some("yes") => Ok(Some(true)),
+                Some("false") | Some("0") | Some("no") => Ok(Some(false)),
+                _ => Err(preference.error("expected true or false")),
+            },
+
+            None => Ok(None),
+        }
+    }
+
+    // A comma separated string works too, which is how environment variables give lists
+    pub fn list(&self, key: &str) -> Result<Option<Vec<String>>, ConfigError> {
+        let preference = match self.find(key) {
+            Some(preference) => preference,
+            None => return Ok(None),
+        };
+
+        let values = match &preference.value {
+            ConfigValue::String(text) => text.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()).map(|x| ConfigValue::String(x.to_owned())).collect(),
+            ConfigValue::Array(values) => values.clone(),
+            value => vec![value.clone()],
+        };
+
+        values.iter()
+            .map(|x| x.as_str().map(|x| x.to_owned()).ok_or_else(|| preference.error("expected a list of strings in quotes")))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+    }
+
+    fn find(&self, key: &str) -> Option<&Preference> {
+        self.preferences.iter().find(|x| x.key == key)
+    }
+}
+
+impl Preference {
+    fn error(&self, reason: &str) -> ConfigError {
+        ConfigError::InvalidValue {key: self.key.to_owned(), origin: self.origin.clone(), reason: reason.to_owned()}
+    }
+}
+
+fn environment_variable(key: &str) -> String {
+    format!("R8_{}", key.replace('.', "_").to_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+
+    const SETTINGS: [(&str, &str); 6] = [
+        ("platform", "platform"),
+        ("ips", "ips"),
+        ("cycles_per_tick", "cycles-per-tick"),
+        ("start_paused", "paused"),
+        ("palette", "palette"),
+        ("audio.volume", "volume"),
+    ];
+
+    const ALTERNATIVES: [(&str, &str); 1] = [("ips", "cycles_per_tick")];
+
+    fn preferences() -> Preferences {
+        Preferences::new(&SETTINGS, &ALTERNATIVES)
+    }
+
+    fn add_platform(preferences: &mut Preferences, layer: PreferenceLayer) {
+        let name = format!("{:?}", layer);
+        preferences.set("platform", ConfigValue::String(name.clone()), layer, name);
+    }
+
+    #[test]
+    fn each_layer_overrides_the_one_below() {
+        let layers = [
+            PreferenceLayer::GlobalFile,
+            PreferenceLayer::RomDatabase,
+            PreferenceLayer::DirectoryFile,
+            PreferenceLayer::RomFile,
+            PreferenceLayer::Environment,
+            PreferenceLayer::CommandLine,
+        ];
+
+        for (index, layer) in layers.iter().enumerate() {
+            // Added lowest first, then highest first, which should give the same result
+            let mut rising = preferences();
+            let mut falling = preferences();
+            for lower in &layers[..=index] {
+                add_platform(&mut rising, *lower);
+            }
+
+            for lower in layers[..=index].iter().rev() {
+                add_platform(&mut falling, *lower);
+            }
+
+            let expected = Some(format!("{:?}", layer));
+            assert_eq!(rising.text("platform").unwrap(), expected, "Incorrect value adding layers lowest first");
+            assert_eq!(falling.text("platform").unwrap(), expected, "Incorrect value adding layers highest first");
+            assert_eq!(falling.layer("platform"), Some(*layer), "Incorrect layer");
+        }
+    }
+
+    #[test]
+    fn files_environment_and_command_line_are_layered() {
+        let global = parse_config("platform = \"chip8\"\nips = 600\n[audio]\nvolume = 10\n[keys]\n1 = \"Q\"").unwrap();
+        let rom = parse_config("platform = \"schip\"").unwrap();
+        let mut preferences = preferences();
+        preferences.add_command_line(|option| match option {
+            "paused" => Some(Vec::new()),
+            "palette" => Some(vec!["#000000".to_owned(), "#ffffff".to_owned()]),
+            _ => None,
+        });
+
+        preferences.add_environment(|variable| match variable {
+            "R8_AUDIO_VOLUME" => Some("25".to_owned()),
+            "R8_PALETTE" => Some("#111111".to_owned()),
+            _ => None,
+        });
+
+        preferences.add_file(PreferenceLayer::RomFile, "game.ch8.toml", &rom).unwrap();
+        preferences.add_file(PreferenceLayer::GlobalFile, "config.toml", &global).unwrap();
+
+        assert_eq!(preferences.text("platform").unwrap().as_deref(), Some("schip"), "Incorrect platform");
+        assert_eq!(preferences.origin("platform"), "game.ch8.toml line 1", "Incorrect platform origin");
+        assert_eq!(preferences.number::<u32>("ips").unwrap(), Some(600), "Incorrect ips");
+        assert_eq!(preferences.origin("ips"), "config.toml line 2", "Incorrect ips origin");
+        assert_eq!(preferences.number::<f32>("audio.volume").unwrap(), Some(25.0), "Incorrect volume");
+        assert_eq!(preferences.origin("audio.volume"), "R8_AUDIO_VOLUME", "Incorrect volume origin");
+        assert_eq!(preferences.flag("start_paused").unwrap(), Some(true), "A flag should be true when given");
+        assert_eq!(preferences.list("palette").unwrap(), Some(vec!["#000000".to_owned(), "#ffffff".to_owned()]), "Incorrect palette");
+        assert_eq!(preferences.origin("cycles_per_tick"), "default", "Incorrect origin for an unset setting");
+    }
+
+    #[test]
+    fn command_line_ips_beats_database_cycles_per_tick() {
+        let mut preferences = preferences();
+        preferences.add_command_line(|option| match option {
+            "ips" => Some(vec!["1200".to_owned()]),
+            _ => None,
+        });
+
+        preferences.set("cycles_per_tick", ConfigValue::Integer(30), PreferenceLayer::RomDatabase, "ROM database".to_owned());
+        assert_eq!(preferences.number::<u32>("ips").unwrap(), Some(1200), "Incorrect ips");
+        assert_eq!(preferences.number::<u32>("cycles_per_tick").unwrap(), None, "The database tickrate should be ignored");
+
+        // A higher layer's tickrate replaces a lower layer's ips
+        let mut preferences = self::preferences();
+        preferences.set("ips", ConfigValue::Integer(600), PreferenceLayer::GlobalFile, "config.toml line 1".to_owned());
+        preferences.set("cycles_per_tick", ConfigValue::Integer(30), PreferenceLayer::RomDatabase, "ROM database".to_owned());
+        assert_eq!(preferences.number::<u32>("ips").unwrap(), None, "The global file's ips should be replaced");
+        assert_eq!(preferences.number::<u32>("cycles_per_tick").unwrap(), Some(30), "Incorrect cycles per tick");
+    }
+
+    #[test]
+    fn environment_lists_are_comma_separated() {
+        let mut preferences = preferences();
+        preferences.add_environment(|variable| match variable {
+            "R8_PALETTE" => Some("#000000, #ffffff,".to_owned()),
+            _ => None,
+        });
+
+        assert_eq!(preferences.list("palette").unwrap(), Some(vec!["#000000".to_owned(), "#ffffff".to_owned()]), "Incorrect palette");
+    }
+
+    #[test]
+    fn unknown_setting_in_file_is_an_error() {
+        let entries = parse_config("\n[window]\nsize = 2").unwrap();
+        match preferences().add_file(PreferenceLayer::DirectoryFile, "r8.toml", &entries).unwrap_err() {
+            ConfigError::UnknownSetting {ref origin, ref name} if origin == "r8.toml line 3" && name == "window.size" => (),
+            x => panic!("Expected UnknownSetting, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_are_errors() {
+        let entries = parse_config("platform = 8\nips = \"fast\"\nstart_paused = \"maybe\"\npalette = [1]").unwrap();
+        let mut preferences = preferences();
+        preferences.add_file(PreferenceLayer::RomFile, "game.toml", &entries).unwrap();
+
+        let results = [
+            preferences.text("platform").map(|_| ()),
+            preferences.number::<u32>("ips").map(|_| ()),
+            preferences.flag("start_paused").map(|_| ()),
+            preferences.list("palette").map(|_| ()),
+        ];
+
+        for (result, line) in results.iter().zip(1..) {
+            match result {
+                Err(ConfigError::InvalidValue {origin, ..}) if *origin == format!("game.toml line {}", line) => (),
+                x => panic!("Expected InvalidValue from line {}, instead got {:?}", line, x),
+            }
+        }
+
+        preferences.set("platform", ConfigValue::String("nes".to_owned()), PreferenceLayer::CommandLine, "command line".to_owned());
+        match preferences.choice("platform", &["chip8", "schip"]).unwrap_err() {
+            ConfigError::InvalidValue {..} => (),
+            x => panic!("Expected InvalidValue, instead got {:?}", x),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::Platform;
+
+// Opcodes whose behaviour differs between interpreters.  Programs are usually written against
+// one interpreter's behaviour, so the wrong set of quirks can make an otherwise correct program
+// misbehave.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Quirks {
+    // 8xy6 and 8xyE shift Vy and store the result in Vx, instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+
+    // Fx55 and Fx65 leave I pointing past the last register that was stored or loaded
+    pub load_store_increments_i: bool,
+
+    // Bnnn jumps to nnn plus Vx (where x is the highest nibble of nnn) instead of plus V0
+    pub jump_uses_vx: bool,
+
+    // Sprites are cut off at the edge of the screen instead of wrapping around to the other side
+    pub clip_sprites: bool,
+
+    // 8xy1, 8xy2 and 8xy3 reset VF to zero
+    pub logic_resets_vf: bool,
+
+    // Drawing a sprite waits for the next vertical blank, limiting programs to one draw per frame
+    pub display_wait: bool,
+}
+
+impl Default for Quirks {
+    // Matches how r8 has always behaved, which does not line up with any single interpreter
+    fn default() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            logic_resets_vf: false,
+            display_wait: false,
+        }
+    }
+}
+
+impl Quirks {
+    pub fn cosmac_vip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            clip_sprites: true,
+            logic_resets_vf: true,
+            display_wait: true,
+        }
+    }
+
+    pub fn super_chip() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            logic_resets_vf: false,
+            display_wait: false,
+        }
+    }
+
+    pub fn xo_chip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            logic_resets_vf: false,
+            display_wait: false,
+        }
+    }
+}
+
+impl Quirks {
+    // Names of the presets, as given to the `--quirks` option of every frontend
+    pub const PRESET_NAMES: [&'static str; 4] = ["legacy", "vip", "schip", "xochip"];
+
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name {
+            "legacy" => Some(Quirks::default()),
+            "vip" => Some(Quirks::cosmac_vip()),
+            "schip" => Some(Quirks::super_chip()),
+            "xochip" => Some(Quirks::xo_chip()),
+            _ => None,
+        }
+    }
+
+    // The preset these quirks match, if any
+    pub fn preset_name(&self) -> Option<&'static str> {
+        Quirks::PRESET_NAMES.iter().find(|x| Quirks::preset(x).as_ref() == Some(self)).cloned()
+    }
+}
+
+impl Platform {
+    // Names of the platforms, as given to the `--platform` option of every frontend
+    pub const NAMES: [&'static str; 3] = ["chip8", "schip", "xochip"];
+
+    pub fn from_name(name: &str) -> Option<Platform> {
+        match name {
+            "chip8" => Some(Platform::Chip8),
+            "schip" => Some(Platform::SuperChip),
+            "xochip" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip8",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+
+    // Plain CHIP-8 keeps r8's original behaviour, so existing ROMs run the way they always have
+    // unless the VIP's quirks are asked for
+    pub fn default_quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::SuperChip => Quirks::super_chip(),
+            Platform::XoChip => Quirks::xo_chip(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_platform_has_its_own_default_quirks() {
+        assert_eq!(Platform::Chip8.default_quirks(), Quirks::default(), "Incorrect CHIP-8 quirks");
+        assert_eq!(Platform::SuperChip.default_quirks(), Quirks::super_chip(), "Incorrect SUPER-CHIP quirks");
+        assert_eq!(Platform::XoChip.default_quirks(), Quirks::xo_chip(), "Incorrect XO-CHIP quirks");
+    }
+
+    #[test]
+    fn presets_and_platforms_can_be_named() {
+        for name in Quirks::PRESET_NAMES.iter() {
+            assert_eq!(Quirks::preset(name).unwrap().preset_name(), Some(*name), "Incorrect preset name");
+        }
+
+        for name in Platform::NAMES.iter() {
+            assert_eq!(Platform::from_name(name).unwrap().name(), *name, "Incorrect platform name");
+        }
+
+        assert_eq!(Quirks::preset("vip"), Some(Quirks::cosmac_vip()), "Incorrect VIP preset");
+        assert_eq!(Quirks::preset("cosmac"), None, "Unknown presets should not be found");
+        assert_eq!(Platform::from_name("schip"), Some(Platform::SuperChip), "Incorrect platform");
+    }
+}
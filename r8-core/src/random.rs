@@ -0,0 +1,80 @@
+// Small xorshift generator for `RND`.  Its whole state is a single number, so cloning the
+// hardware or saving its state captures exactly which values will be generated next.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        // Spread the seed out with splitmix64, so small seeds still produce well mixed values and
+        // a seed of zero doesn't leave xorshift stuck at zero
+        let mut mixed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        mixed ^= mixed >> 31;
+
+        Random {state: if mixed == 0 { 1 } else { mixed }}
+    }
+
+    // Seed for when the user doesn't care which values come out
+    pub fn generate_seed() -> u64 {
+        rand::random::<u64>()
+    }
+
+    // Restores a generator from a previous `state()`.  Zero is never a valid state.
+    pub fn from_state(state: u64) -> Option<Self> {
+        match state {
+            0 => None,
+            state => Some(Random {state}),
+        }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 56) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_produces_same_values() {
+        let mut first = Random::new(1234);
+        let mut second = Random::new(1234);
+        for _ in 0..100 {
+            assert_eq!(first.next_byte(), second.next_byte(), "Generators diverged");
+        }
+    }
+
+    #[test]
+    fn different_seeds_produce_different_values() {
+        let first = (0..16).scan(Random::new(1), |x, _| Some(x.next_byte())).collect::<Vec<u8>>();
+        let second = (0..16).scan(Random::new(2), |x, _| Some(x.next_byte())).collect::<Vec<u8>>();
+        assert_ne!(first, second, "Expected different seeds to produce different values");
+    }
+
+    #[test]
+    fn zero_seed_is_usable() {
+        let mut random = Random::new(0);
+        let values = (0..16).map(|_| random.next_byte()).collect::<Vec<u8>>();
+        assert!(values.iter().any(|x| *x != values[0]), "Expected zero seed to still vary");
+    }
+
+    #[test]
+    fn can_restore_from_state() {
+        let mut random = Random::new(99);
+        random.next_byte();
+        let mut restored = Random::from_state(random.state()).unwrap();
+        assert_eq!(restored.next_byte(), random.next_byte(), "Incorrect value after restoring");
+        assert_eq!(Random::from_state(0), None, "Zero state should be rejected");
+    }
+}
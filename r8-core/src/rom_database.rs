@@ -0,0 +1,146 @@
+use crate::{Platform, Quirks, parse_color};
+use crate::json::{Json, JsonError};
+use crate::sha1;
+
+// Keyboard keys and gamepad controls for the actions the database gives CHIP-8 keys for
+const ACTION_BINDINGS: [(&str, &[&str]); 6] = [
+    ("up", &["Up", "AxisY-"]),
+    ("down", &["Down", "AxisY+"]),
+    ("left", &["Left", "AxisX-"]),
+    ("right", &["Right", "AxisX+"]),
+    ("a", &["Button0"]),
+    ("b", &["Button1"]),
+];
+
+// What the database knows about how to run a ROM
+pub struct RomInfo {
+    pub title: String,
+    pub platform: Option<(Platform, Quirks)>,
+    pub tickrate: Option<u32>, // Instructions per 60hz tick
+    pub key_bindings: Vec<(u8, String)>,
+    pub palette: Vec<[u8; 3]>, // Background first, then each combination of bitplanes
+}
+
+// Returns None if the database doesn't have the ROM
+pub fn find_rom_info(database: &str, rom: &[u8]) -> Result<Option<RomInfo>, JsonError> {
+    let programs = Json::parse(database)?;
+    let hash = sha1::sha1_hex(rom);
+
+    for program in programs.as_array() {
+        let rom_entry = program.get("roms")
+            .and_then(|roms| roms.fields().iter().find(|(rom_hash, _)| rom_hash.eq_ignore_ascii_case(&hash)))
+            .map(|(_, entry)| entry);
+
+        if let Some(rom_entry) = rom_entry {
+            return Ok(Some(read_rom_info(program, rom_entry)));
+        }
+    }
+
+    Ok(None)
+}
+
+fn read_rom_info(program: &Json, rom_entry: &Json) -> RomInfo {
+    let title = program.get("title").and_then(Json::as_str).unwrap_or("Untitled").to_owned();
+
+    // The first platform r8 supports is used, with any quirks this ROM needs on top
+    let platform = rom_entry.get("platforms").map(Json::as_array).unwrap_or(&[]).iter()
+        .filter_map(Json::as_str)
+        .find_map(|id| platform_quirks(id).map(|x| (id, x)))
+        .map(|(id, (platform, mut quirks))| {
+            let overrides = rom_entry.get("quirkyPlatforms").and_then(|x| x.get(id));
+            for (name, value) in overrides.map(Json::fields).unwrap_or(&[]) {
+                if let Some(enabled) = value.as_bool() {
+                    apply_quirk(&mut quirks, name, enabled);
+                }
+            }
+
+            (platform, quirks)
+        });
+
+    let mut key_bindings = Vec::new();
+    for (action, value) in rom_entry.get("keys").map(Json::fields).unwrap_or(&[]) {
+        let bindings = ACTION_BINDINGS.iter().find(|(name, _)| name == action).map(|(_, x)| *x).unwrap_or(&[]);
+        if let Some(key) = value.as_f64().filter(|x| *x >= 0.0 && *x < 16.0) {
+            key_bindings.extend(bindings.iter().map(|binding| (key as u8, binding.to_string())));
+        }
+    }
+
+    let palette = rom_entry.get("colors").and_then(|x| x.get("pixels")).map(Json::as_array).unwrap_or(&[]).iter()
+        .filter_map(Json::as_str)
+        .filter_map(parse_color)
+        .collect();
+
+    RomInfo {
+        title,
+        platform,
+        tickrate: rom_entry.get("tickrate").and_then(Json::as_f64).filter(|x| *x >= 1.0).map(|x| x as u32),
+        key_bindings,
+        palette,
+    }
+}
+
+// Platforms from the database that r8 can run, along with their usual quirks
+fn platform_quirks(id: &str) -> Option<(Platform, Quirks)> {
+    match id {
+        "originalChip8" | "hybridVIP" => Some((Platform::Chip8, Quirks::cosmac_vip())),
+        "modernChip8" => Some((Platform::Chip8, Quirks {logic_resets_vf: false, display_wait: false, ..Quirks::cosmac_vip()})),
+        "chip48" => Some((Platform::Chip8, Quirks {load_store_increments_i: true, ..Quirks::super_chip()})),
+        "superchip1" | "superchip" => Some((Platform::SuperChip, Quirks::super_chip())),
+        "xochip" => Some((Platform::XoChip, Quirks::xo_chip())),
+        _ => None,
+    }
+}
+
+// The database names quirks by the behaviour that differs from the original interpreter
+fn apply_quirk(quirks: &mut Quirks, name: &str, enabled: bool) {
+    match name {
+        "shift" => quirks.shift_uses_vy = !enabled,
+        "memoryLeaveIUnchanged" => quirks.load_store_increments_i = !enabled,
+        "memoryIncrementByX" if enabled => quirks.load_store_increments_i = true, // The closest r8 has
+        "wrap" => quirks.clip_sprites = !enabled,
+        "jump" => quirks.jump_uses_vx = enabled,
+        "vblank" => quirks.display_wait = enabled,
+        "logic" => quirks.logic_resets_vf = enabled,
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATABASE: &str = include_str!("../tests/data/programs.json");
+
+    #[test]
+    fn rom_info_is_read_from_its_entry() {
+        let info = find_rom_info(DATABASE, &[0x00, 0xe0]).unwrap().unwrap();
+        let quirks = Quirks {shift_uses_vy: false, display_wait: false, load_store_increments_i: false, ..Quirks::cosmac_vip()};
+
+        assert_eq!(info.title, "Clear Screen", "Incorrect title");
+        assert_eq!(info.platform, Some((Platform::Chip8, quirks)), "Expected the first platform with its quirks");
+        assert_eq!(info.tickrate, Some(15), "Incorrect tickrate");
+        assert_eq!(info.palette, [[0, 0, 0], [255, 204, 0]], "Incorrect palette");
+
+        let bindings = info.key_bindings.iter().map(|(key, name)| (*key, name.as_str())).collect::<Vec<_>>();
+        assert_eq!(bindings, [(5, "Up"), (5, "AxisY-"), (6, "Button0")], "Expected only known actions and keys to be bound");
+    }
+
+    #[test]
+    fn unsupported_platforms_are_skipped() {
+        let info = find_rom_info(DATABASE, &[0x12, 0x00]).unwrap().unwrap();
+        assert_eq!(info.title, "Jump", "Incorrect title");
+        assert_eq!(info.platform, Some((Platform::SuperChip, Quirks::super_chip())), "Incorrect platform");
+        assert_eq!(info.tickrate, None, "A tickrate of 0 should be ignored");
+        assert!(info.key_bindings.is_empty() && info.palette.is_empty(), "Expected no bindings or palette");
+    }
+
+    #[test]
+    fn unknown_rom_is_not_found() {
+        assert!(find_rom_info(DATABASE, &[0x00, 0xfd]).unwrap().is_none(), "Expected the ROM not to be found");
+    }
+
+    #[test]
+    fn invalid_database_is_an_error() {
+        assert!(find_rom_info("[{\"title\": }]", &[0x00, 0xe0]).is_err(), "Expected a syntax error");
+    }
+}
@@ -0,0 +1,570 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{Read, Write};
+use custom_error::custom_error;
+use crate::{Hardware, Platform, Random};
+use crate::json::Json;
+use crate::hardware::{Framebuffer, FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT, STACK_SIZE, XO_CHIP_MEMORY_SIZE,
+                      FLAG_REGISTER_COUNT, AUDIO_PATTERN_SIZE};
+
+const MAGIC: &[u8; 4] = b"R8ST";
+pub const SAVE_STATE_VERSION: u16 = 1;
+
+const NUMBER_TAG: u8 = 0;
+const BOOL_TAG: u8 = 1;
+const TEXT_TAG: u8 = 2;
+const BYTES_TAG: u8 = 3;
+const WORDS_TAG: u8 = 4;
+
+custom_error!{pub SaveStateError
+    Io {source: io::Error} = "Could not read or write save state: {source}",
+    NotASaveState = "File is not an r8 save state",
+    UnsupportedVersion {version: u16} = "Save state version {version} is not supported",
+    Malformed {reason: String} = "Save state is malformed: {reason}",
+    MissingField {name: String} = "Save state is missing the '{name}' field",
+    InvalidField {name: String} = "Save state has an invalid value for '{name}'",
+}
+
+// Both formats store the same named fields, and every field has to be present to load
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(u64),
+    Bool(bool),
+    Text(String),
+    Bytes(Vec<u8>),
+    Words(Vec<u16>),
+    List(Vec<u64>), // JSON arrays, which don't say how wide their values are
+}
+
+struct Fields {
+    values: HashMap<String, Value>,
+}
+
+impl Hardware {
+    pub fn save_state(&self, writer: &mut impl Write) -> Result<(), SaveStateError> {
+        let fields = to_fields(self);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&SAVE_STATE_VERSION.to_le_bytes())?;
+        writer.write_all(&(fields.len() as u16).to_le_bytes())?;
+
+        for (name, value) in fields {
+            writer.write_all(&[name.len() as u8])?;
+            writer.write_all(name.as_bytes())?;
+            write_value(writer, &value)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load_state(reader: &mut impl Read) -> Result<Hardware, SaveStateError> {
+        let mut magic = [0_u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(SaveStateError::NotASaveState);
+        }
+
+        let version = read_u16(reader)?;
+        if version > SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion {version});
+        }
+
+        let mut values = HashMap::new();
+        for _ in 0..read_u16(reader)? {
+            let mut name = vec![0_u8; read_u8(reader)? as usize];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| malformed("field name is not valid UTF-8"))?;
+            values.insert(name, read_value(reader)?);
+        }
+
+        from_fields(&Fields {values})
+    }
+
+    pub fn save_state_json(&self, writer: &mut impl Write) -> Result<(), SaveStateError> {
+        let fields = to_fields(self);
+        writeln!(writer, "{{")?;
+        writeln!(writer, "  \"version\": {},", SAVE_STATE_VERSION)?;
+        for (index, (name, value)) in fields.iter().enumerate() {
+            let separator = if index + 1 < fields.len() { "," } else { "" };
+            writeln!(writer, "  \"{}\": {}{}", name, json_value(value), separator)?;
+        }
+
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+
+    pub fn load_state_json(reader: &mut impl Read) -> Result<Hardware, SaveStateError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+
+        let fields = match Json::parse(&text) {
+            Ok(Json::Object(fields)) => fields,
+            Ok(_) => return Err(SaveStateError::NotASaveState),
+            Err(error) => return Err(malformed(&error.to_string())),
+        };
+
+        let mut values = HashMap::new();
+        for (name, value) in fields {
+            values.insert(name, from_json(value)?);
+        }
+
+        let version = match values.remove("version") {
+            Some(Value::Number(x)) if x <= u16::MAX as u64 => x as u16,
+            _ => return Err(SaveStateError::NotASaveState),
+        };
+
+        if version > SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion {version});
+        }
+
+        from_fields(&Fields {values})
+    }
+}
+
+fn to_fields(hardware: &Hardware) -> Vec<(&'static str, Value)> {
+    let quirks = &hardware.quirks;
+    vec![
+        ("platform", Value::Text(format!("{:?}", hardware.platform))),
+        ("memory", Value::Bytes(hardware.memory.clone())),
+        ("gen_registers", Value::Bytes(hardware.gen_registers.to_vec())),
+        ("i_register", Value::Number(hardware.i_register as u64)),
+        ("sound_timer", Value::Number(hardware.sound_timer as u64)),
+        ("delay_timer", Value::Number(hardware.delay_timer as u64)),
+        ("program_counter", Value::Number(hardware.program_counter as u64)),
+        ("stack", Value::Words(hardware.stack.to_vec())),
+        ("stack_pointer", Value::Number(hardware.stack_pointer as u64)),
+        ("keys_down", Value::Number(hardware.keys_down as u64)),
+        ("keys_released", Value::Number(hardware.keys_released as u64)),
+        ("framebuffer", Value::Bytes(flatten(&hardware.framebuffer))),
+        ("second_framebuffer", Value::Bytes(flatten(&hardware.second_framebuffer))),
+        ("selected_planes", Value::Number(hardware.selected_planes as u64)),
+        ("high_resolution", Value::Bool(hardware.high_resolution)),
+        ("flag_registers", Value::Bytes(hardware.flag_registers.to_vec())),
+        ("halted", Value::Bool(hardware.halted)),
+        ("audio_pattern", Value::Bytes(hardware.audio_pattern.to_vec())),
+        ("pitch", Value::Number(hardware.pitch as u64)),
+        ("vertical_blank", Value::Bool(hardware.vertical_blank)),
+        ("quirks.shift_uses_vy", Value::Bool(quirks.shift_uses_vy)),
+        ("quirks.load_store_increments_i", Value::Bool(quirks.load_store_increments_i)),
+        ("quirks.jump_uses_vx", Value::Bool(quirks.jump_uses_vx)),
+        ("quirks.clip_sprites", Value::Bool(quirks.clip_sprites)),
+        ("quirks.logic_resets_vf", Value::Bool(quirks.logic_resets_vf)),
+        ("quirks.display_wait", Value::Bool(quirks.display_wait)),
+        ("random_state", Value::Number(hardware.random.state())),
+    ]
+}
+
+fn from_fields(fields: &Fields) -> Result<Hardware, SaveStateError> {
+    let mut hardware = Hardware::new();
+    hardware.platform = match fields.text("platform")?.as_ref() {
+        "Chip8" => Platform::Chip8,
+        "SuperChip" => Platform::SuperChip,
+        "XoChip" => Platform::XoChip,
+        _ => return Err(SaveStateError::InvalidField {name: "platform".to_owned()}),
+    };
+
+    let memory = fields.bytes("memory", None)?;
+    if memory.len() > XO_CHIP_MEMORY_SIZE {
+        return Err(SaveStateError::InvalidField {name: "memory".to_owned()});
+    }
+
+    hardware.memory[..memory.len()].copy_from_slice(&memory);
+    hardware.gen_registers.copy_from_slice(&fields.bytes("gen_registers", Some(16))?);
+    hardware.i_register = fields.number("i_register", u16::MAX as u64)? as u16;
+    hardware.sound_timer = fields.number("sound_timer", u8::MAX as u64)? as u8;
+    hardware.delay_timer = fields.number("delay_timer", u8::MAX as u64)? as u8;
+    hardware.program_counter = fields.number("program_counter", u16::MAX as u64)? as u16;
+    hardware.stack.copy_from_slice(&fields.words("stack", STACK_SIZE)?);
+    hardware.stack_pointer = fields.number("stack_pointer", STACK_SIZE as u64)? as usize;
+    hardware.keys_down = fields.number("keys_down", u16::MAX as u64)? as u16;
+    hardware.keys_released = fields.number("keys_released", u16::MAX as u64)? as u16;
+    hardware.framebuffer = unflatten(&fields.bytes("framebuffer", Some(FRAMEBUFFER_WIDTH / 8 * FRAMEBUFFER_HEIGHT))?);
+    hardware.second_framebuffer = unflatten(&fields.bytes("second_framebuffer", Some(FRAMEBUFFER_WIDTH / 8 * FRAMEBUFFER_HEIGHT))?);
+    hardware.selected_planes = fields.number("selected_planes", 0b11)? as u8;
+    hardware.high_resolution = fields.boolean("high_resolution")?;
+    hardware.flag_registers.copy_from_slice(&fields.bytes("flag_registers", Some(FLAG_REGISTER_COUNT))?);
+    hardware.halted = fields.boolean("halted")?;
+    hardware.audio_pattern.copy_from_slice(&fields.bytes("audio_pattern", Some(AUDIO_PATTERN_SIZE))?);
+    hardware.pitch = fields.number("pitch", u8::MAX as u64)? as u8;
+    hardware.vertical_blank = fields.boolean("vertical_blank")?;
+    hardware.quirks.shift_uses_vy = fields.boolean("quirks.shift_uses_vy")?;
+    hardware.quirks.load_store_increments_i = fields.boolean("quirks.load_store_increments_i")?;
+    hardware.quirks.jump_uses_vx = fields.boolean("quirks.jump_uses_vx")?;
+    hardware.quirks.clip_sprites = fields.boolean("quirks.clip_sprites")?;
+    hardware.quirks.logic_resets_vf = fields.boolean("quirks.logic_resets_vf")?;
+    hardware.quirks.display_wait = fields.boolean("quirks.display_wait")?;
+    hardware.random = Random::from_state(fields.number("random_state", u64::MAX)?)
+        .ok_or_else(|| SaveStateError::InvalidField {name: "random_state".to_owned()})?;
+
+    Ok(hardware)
+}
+
+impl Fields {
+    fn get(&self, name: &str) -> Result<&Value, SaveStateError> {
+        self.values.get(name).ok_or_else(|| SaveStateError::MissingField {name: name.to_owned()})
+    }
+
+    fn number(&self, name: &str, maximum: u64) -> Result<u64, SaveStateError> {
+        match self.get(name)? {
+            Value::Number(x) if *x <= maximum => Ok(*x),
+            _ => Err(SaveStateError::InvalidField {name: name.to_owned()}),
+        }
+    }
+
+    fn boolean(&self, name: &str) -> Result<bool, SaveStateError> {
+        match self.get(name)? {
+            Value::Bool(x) => Ok(*x),
+            _ => Err(SaveStateError::InvalidField {name: name.to_owned()}),
+        }
+    }
+
+    fn text(&self, name: &str) -> Result<String, SaveStateError> {
+        match self.get(name)? {
+            Value::Text(x) => Ok(x.clone()),
+            _ => Err(SaveStateError::InvalidField {name: name.to_owned()}),
+        }
+    }
+
+    fn bytes(&self, name: &str, length: Option<usize>) -> Result<Vec<u8>, SaveStateError> {
+        let bytes = match self.get(name)? {
+            Value::Bytes(x) => x.clone(),
+            Value::List(x) if x.iter().all(|x| *x <= u8::MAX as u64) => x.iter().map(|x| *x as u8).collect(),
+            _ => return Err(SaveStateError::InvalidField {name: name.to_owned()}),
+        };
+
+        match length {
+            Some(length) if length != bytes.len() => Err(SaveStateError::InvalidField {name: name.to_owned()}),
+            _ => Ok(bytes),
+        }
+    }
+
+    fn words(&self, name: &str, length: usize) -> Result<Vec<u16>, SaveStateError> {
+        let words = match self.get(name)? {
+            Value::Words(x) => x.clone(),
+            Value::List(x) if x.iter().all(|x| *x <= u16::MAX as u64) => x.iter().map(|x| *x as u16).collect(),
+            _ => return Err(SaveStateError::InvalidField {name: name.to_owned()}),
+        };
+
+        match words.len() == length {
+            true => Ok(words),
+            false => Err(SaveStateError::InvalidField {name: name.to_owned()}),
+        }
+    }
+}
+
+fn flatten(framebuffer: &Framebuffer) -> Vec<u8> {
+    framebuffer.iter().flat_map(|row| row.iter().cloned()).collect()
+}
+
+fn unflatten(bytes: &[u8]) -> Framebuffer {
+    let mut framebuffer = [[0; FRAMEBUFFER_WIDTH / 8]; FRAMEBUFFER_HEIGHT];
+    for (row, chunk) in framebuffer.iter_mut().zip(bytes.chunks(FRAMEBUFFER_WIDTH / 8)) {
+        row.copy_from_slice(chunk);
+    }
+
+    framebuffer
+}
+
+fn malformed(reason: &str) -> SaveStateError {
+    SaveStateError::Malformed {reason: reason.to_owned()}
+}
+
+fn write_value(writer: &mut impl Write, value: &Value) -> Result<(), SaveStateError> {
+    match value {
+        Value::Number(x) => {
+            writer.write_all(&[NUMBER_TAG])?;
+            writer.write_all(&x.to_le_bytes())?;
+        }
+
+        Value::Bool(x) => {
+            writer.write_all(&[BOOL_TAG, *x as u8])?;
+        }
+
+        Value::Text(x) => {
+            writer.write_all(&[TEXT_TAG])?;
+            writer.write_all(&(x.len() as u32).to_le_bytes())?;
+            writer.write_all(x.as_bytes())?;
+        }
+
+        Value::Bytes(x) => {
+            writer.write_all(&[BYTES_TAG])?;
+            writer.write_all(&(x.len() as u32).to_le_bytes())?;
+            writer.write_all(x)?;
+        }
+
+        Value::Words(x) => {
+            writer.write_all(&[WORDS_TAG])?;
+            writer.write_all(&(x.len() as u32).to_le_bytes())?;
+            for word in x {
+                writer.write_all(&word.to_le_bytes())?;
+            }
+        }
+
+        Value::List(x) => {
+            return write_value(writer, &Value::Words(x.iter().map(|x| *x as u16).collect()));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_value(reader: &mut impl Read) -> Result<Value, SaveStateError> {
+    match read_u8(reader)? {
+        NUMBER_TAG => {
+            let mut bytes = [0_u8; 8];
+            reader.read_exact(&mut bytes)?;
+            Ok(Value::Number(u64::from_le_bytes(bytes)))
+        }
+
+        BOOL_TAG => Ok(Value::Bool(read_u8(reader)? != 0)),
+
+        TEXT_TAG => {
+            let mut bytes = vec![0_u8; read_length(reader)?];
+            reader.read_exact(&mut bytes)?;
+            String::from_utf8(bytes).map(Value::Text).map_err(|_| malformed("text is not valid UTF-8"))
+        }
+
+        BYTES_TAG => {
+            let mut bytes = vec![0_u8; read_length(reader)?];
+            reader.read_exact(&mut bytes)?;
+            Ok(Value::Bytes(bytes))
+        }
+
+        WORDS_TAG => {
+            let mut words = Vec::new();
+            for _ in 0..read_length(reader)? {
+                words.push(read_u16(reader)?);
+            }
+
+            Ok(Value::Words(words))
+        }
+
+        tag => Err(SaveStateError::Malformed {reason: format!("unknown value type {}", tag)}),
+    }
+}
+
+// Nothing saved is bigger than XO-CHIP memory, so a longer length means the file is corrupt
+// rather than something worth allocating for
+fn read_length(reader: &mut impl Read) -> Result<usize, SaveStateError> {
+    match read_u32(reader)? as usize {
+        length if length > XO_CHIP_MEMORY_SIZE => Err(SaveStateError::Malformed {reason: format!("length {} is too long", length)}),
+        length => Ok(length),
+    }
+}
+
+fn read_u8(reader: &mut impl Read) -> Result<u8, SaveStateError> {
+    let mut bytes = [0_u8; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u16(reader: &mut impl Read) -> Result<u16, SaveStateError> {
+    let mut bytes = [0_u8; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, SaveStateError> {
+    let mut bytes = [0_u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn from_json(json: Json) -> Result<Value, SaveStateError> {
+    match json {
+        Json::Number(_) => json.as_u64().map(Value::Number).ok_or_else(|| malformed("expected a whole number")),
+        Json::Boolean(x) => Ok(Value::Bool(x)),
+        Json::String(x) => Ok(Value::Text(x)),
+        Json::Array(values) => values.iter().map(Json::as_u64).collect::<Option<Vec<u64>>>()
+            .map(Value::List)
+            .ok_or_else(|| malformed("expected an array of whole numbers")),
+
+        Json::Null | Json::Object(_) => Err(malformed("unexpected value")),
+    }
+}
+
+fn json_value(value: &Value) -> String {
+    fn list<T: ToString>(values: &[T]) -> String {
+        let values = values.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+        format!("[{}]", values.join(","))
+    }
+
+    match value {
+        Value::Number(x) => x.to_string(),
+        Value::Bool(x) => x.to_string(),
+        Value::Text(x) => format!("\"{}\"", x.replace('\\', "\\\\").replace('"', "\\\"")),
+        Value::Bytes(x) => list(x),
+        Value::Words(x) => list(x),
+        Value::List(x) => list(x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    fn busy_hardware() -> Hardware {
+        let mut hardware = Hardware::new();
+        hardware.platform = Platform::XoChip;
+        hardware.quirks = Quirks::xo_chip();
+        hardware.memory[0xfffe] = 0xab;
+        hardware.gen_registers[3] = 0x33;
+        hardware.i_register = 0x1234;
+        hardware.sound_timer = 7;
+        hardware.delay_timer = 9;
+        hardware.program_counter = 0x2f0;
+        hardware.stack[1] = 0x456;
+        hardware.stack_pointer = 2;
+        hardware.press_key(0x4);
+        hardware.framebuffer[63][15] = 0x81;
+        hardware.second_framebuffer[0][0] = 0x18;
+        hardware.selected_planes = 3;
+        hardware.high_resolution = true;
+        hardware.flag_registers[15] = 0x42;
+        hardware.audio_pattern[4] = 0xcc;
+        hardware.pitch = 100;
+        hardware.random = Random::new(7);
+        hardware.random.next_byte();
+        hardware
+    }
+
+    fn assert_same_state(actual: &Hardware, expected: &Hardware) {
+        assert_eq!(actual.platform, expected.platform, "Incorrect platform");
+        assert_eq!(actual.quirks, expected.quirks, "Incorrect quirks");
+        assert_eq!(actual.memory, expected.memory, "Incorrect memory");
+        assert_eq!(actual.gen_registers, expected.gen_registers, "Incorrect general registers");
+        assert_eq!(actual.i_register, expected.i_register, "Incorrect I register");
+        assert_eq!(actual.sound_timer, expected.sound_timer, "Incorrect sound timer");
+        assert_eq!(actual.delay_timer, expected.delay_timer, "Incorrect delay timer");
+        assert_eq!(actual.program_counter, expected.program_counter, "Incorrect program counter");
+        assert_eq!(actual.stack, expected.stack, "Incorrect stack");
+        assert_eq!(actual.stack_pointer, expected.stack_pointer, "Incorrect stack pointer");
+        assert_eq!(actual.keys_down, expected.keys_down, "Incorrect keys down");
+        assert_eq!(&actual.framebuffer[..], &expected.framebuffer[..], "Incorrect framebuffer");
+        assert_eq!(&actual.second_framebuffer[..], &expected.second_framebuffer[..], "Incorrect second framebuffer");
+        assert_eq!(actual.selected_planes, expected.selected_planes, "Incorrect selected planes");
+        assert_eq!(actual.high_resolution, expected.high_resolution, "Incorrect resolution");
+        assert_eq!(actual.flag_registers, expected.flag_registers, "Incorrect flag registers");
+        assert_eq!(actual.audio_pattern, expected.audio_pattern, "Incorrect audio pattern");
+        assert_eq!(actual.pitch, expected.pitch, "Incorrect pitch");
+        assert_eq!(actual.random, expected.random, "Incorrect random number generator state");
+    }
+
+    fn load_error(result: Result<Hardware, SaveStateError>) -> SaveStateError {
+        match result {
+            Ok(_) => panic!("Expected loading the save state to fail"),
+            Err(x) => x,
+        }
+    }
+
+    #[test]
+    fn binary_save_state_round_trips() {
+        let hardware = busy_hardware();
+        let mut bytes = Vec::new();
+        hardware.save_state(&mut bytes).unwrap();
+
+        let loaded = Hardware::load_state(&mut &bytes[..]).unwrap();
+        assert_same_state(&loaded, &hardware);
+    }
+
+    #[test]
+    fn json_save_state_round_trips() {
+        let hardware = busy_hardware();
+        let mut bytes = Vec::new();
+        hardware.save_state_json(&mut bytes).unwrap();
+
+        let loaded = Hardware::load_state_json(&mut &bytes[..]).unwrap();
+        assert_same_state(&loaded, &hardware);
+    }
+
+    #[test]
+    fn binary_save_state_starts_with_magic_and_version() {
+        let mut bytes = Vec::new();
+        Hardware::new().save_state(&mut bytes).unwrap();
+        assert_eq!(&bytes[0..4], b"R8ST", "Incorrect magic");
+        assert_eq!(&bytes[4..6], &SAVE_STATE_VERSION.to_le_bytes(), "Incorrect version");
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let mut bytes = Vec::new();
+        Hardware::new().save_state(&mut bytes).unwrap();
+        bytes[4] = 0xff;
+
+        match load_error(Hardware::load_state(&mut &bytes[..])) {
+            SaveStateError::UnsupportedVersion {..} => (),
+            x => panic!("Expected UnsupportedVersion, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let bytes = b"not a save state";
+        match load_error(Hardware::load_state(&mut &bytes[..])) {
+            SaveStateError::NotASaveState => (),
+            x => panic!("Expected NotASaveState, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn truncated_file_is_an_error() {
+        let mut bytes = Vec::new();
+        Hardware::new().save_state(&mut bytes).unwrap();
+        bytes.truncate(bytes.len() / 2);
+
+        match load_error(Hardware::load_state(&mut &bytes[..])) {
+            SaveStateError::Io {..} => (),
+            x => panic!("Expected Io, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn oversized_length_is_malformed() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&SAVE_STATE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&1_u16.to_le_bytes());
+        bytes.push(6);
+        bytes.extend_from_slice(b"memory");
+        bytes.push(BYTES_TAG);
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+
+        match load_error(Hardware::load_state(&mut &bytes[..])) {
+            SaveStateError::Malformed {..} => (),
+            x => panic!("Expected Malformed, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn missing_random_state_is_an_error() {
+        let hardware = busy_hardware();
+        let mut bytes = Vec::new();
+        hardware.save_state_json(&mut bytes).unwrap();
+        let json = String::from_utf8(bytes).unwrap()
+            .replace(&format!(",\n  \"random_state\": {}", hardware.random.state()), "");
+
+        match load_error(Hardware::load_state_json(&mut json.as_bytes())) {
+            SaveStateError::MissingField {ref name} if name == "random_state" => (),
+            x => panic!("Expected MissingField for random_state, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn malformed_json_is_an_error() {
+        for json in &["{\"version\": 1,\n\"platform\" \"Chip8\"}", "{\"version\": 1, \"pitch\": -4}", "[1]"] {
+            match load_error(Hardware::load_state_json(&mut json.as_bytes())) {
+                SaveStateError::Malformed {..} | SaveStateError::NotASaveState => (),
+                x => panic!("Expected Malformed or NotASaveState for {}, instead got {:?}", json, x),
+            }
+        }
+    }
+
+    #[test]
+    fn missing_json_field_is_an_error() {
+        let json = "{\"version\": 1, \"platform\": \"Chip8\"}";
+        match load_error(Hardware::load_state_json(&mut json.as_bytes())) {
+            SaveStateError::MissingField {ref name} if name == "memory" => (),
+            x => panic!("Expected MissingField for memory, instead got {:?}", x),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+pub const TIMER_FREQUENCY: u32 = 60;
+
+const NANOSECONDS_PER_SECOND: u128 = 1_000_000_000;
+
+// Past this many ticks behind, such as after the window was dragged or the computer slept, the
+// missed time is dropped instead of being run all at once
+const MAX_TICKS_DUE: u32 = 5;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum ScheduledEvent {
+    Cycle, // Execute one instruction
+    TimerTick, // Tick the delay and sound timers, which ends the current tick
+}
+
+// Fixed timestep timing for frontends.  Real time is added as it passes and spent in whole 60hz
+// ticks, each made of a fixed number of instruction cycles followed by one timer tick.  Timers
+// therefore run at exactly 60hz and programs run at the same speed no matter how often frames
+// are rendered.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Scheduler {
+    cycles_per_tick: u32,
+    cycles_run_in_tick: u32, // Can start above zero when the last tick's final instruction ran over
+    is_tick_started: bool,
+    ticks_due: u32,
+    unspent_time: u128, // In nanoseconds multiplied by the timer frequency, so nothing is lost to rounding
+}
+
+impl Scheduler {
+    pub fn new(cycles_per_tick: u32) -> Self {
+        Scheduler {
+            cycles_per_tick,
+            cycles_run_in_tick: 0,
+            is_tick_started: false,
+            ticks_due: 0,
+            unspent_time: 0,
+        }
+    }
+
+    pub fn cycles_per_tick(&self) -> u32 {
+        self.cycles_per_tick
+    }
+
+    // Makes one more tick due, for frontends that aren't tied to real time
+    pub fn add_tick(&mut self) {
+        self.ticks_due = (self.ticks_due + 1).min(MAX_TICKS_DUE);
+    }
+
+    // Adds real time that has passed, making every whole tick in it due
+    pub fn advance(&mut self, elapsed: Duration) {
+        self.unspent_time += elapsed.as_nanos() * TIMER_FREQUENCY as u128;
+        let ticks = self.unspent_time / NANOSECONDS_PER_SECOND;
+        self.unspent_time %= NANOSECONDS_PER_SECOND;
+        self.ticks_due = (self.ticks_due as u128 + ticks).min(MAX_TICKS_DUE as u128) as u32;
+    }
+
+    // What should happen next, or None once every due tick has run.  The event stays next until
+    // `complete_event` is called, so a caller that stops early, such as on a breakpoint, picks up
+    // in the same place.
+    pub fn next_event(&self) -> Option<ScheduledEvent> {
+        match self.ticks_due {
+            0 => None,
+            _ if self.cycles_run_in_tick < self.cycles_per_tick => Some(ScheduledEvent::Cycle),
+            _ => Some(ScheduledEvent::TimerTick),
+        }
+    }
+
+    pub fn complete_event(&mut self) {
+        match self.next_event() {
+            Some(ScheduledEvent::Cycle) => self.complete_cycles(1),
+            Some(ScheduledEvent::TimerTick) => {
+                self.cycles_run_in_tick = self.cycles_run_in_tick.saturating_sub(self.cycles_per_tick);
+                self.is_tick_started = false;
+                self.ticks_due -= 1;
+            }
+
+            None => (),
+        }
+    }
+
+    // Completes an instruction that took more than one cycle.  Cycles past the end of the tick
+    // are taken from the next one.
+    pub fn complete_cycles(&mut self, cycles: u32) {
+        self.cycles_run_in_tick += cycles;
+        self.is_tick_started = true;
+    }
+
+    pub fn cycles_left_in_tick(&self) -> u32 {
+        self.cycles_per_tick.saturating_sub(self.cycles_run_in_tick)
+    }
+
+    // True until the first instruction of the current tick has run
+    pub fn is_starting_tick(&self) -> bool {
+        !self.is_tick_started
+    }
+
+    // Forgets time that passed while the machine wasn't running, such as while paused, without
+    // losing the place in the current tick
+    pub fn discard_due_time(&mut self) {
+        self.ticks_due = 0;
+        self.unspent_time = 0;
+    }
+
+    // Starts over at the beginning of a tick, such as after the ROM is reloaded
+    pub fn reset(&mut self) {
+        self.discard_due_time();
+        self.cycles_run_in_tick = 0;
+        self.is_tick_started = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Rounded up, so it's always at least a whole tick
+    fn tick_length() -> Duration {
+        Duration::from_nanos(16_666_667)
+    }
+
+    fn run_due_events(scheduler: &mut Scheduler) -> Vec<ScheduledEvent> {
+        let mut events = Vec::new();
+        while let Some(event) = scheduler.next_event() {
+            events.push(event);
+            scheduler.complete_event();
+        }
+
+        events
+    }
+
+    #[test]
+    fn nothing_is_due_until_a_tick_has_passed() {
+        let mut scheduler = Scheduler::new(3);
+        scheduler.advance(tick_length() / 2);
+        assert_eq!(scheduler.next_event(), None, "Expected nothing to be due");
+    }
+
+    #[test]
+    fn tick_runs_cycles_then_timer_tick() {
+        let mut scheduler = Scheduler::new(2);
+        scheduler.advance(Duration::from_millis(25)); // One and a half ticks
+
+        let expected = vec![ScheduledEvent::Cycle, ScheduledEvent::Cycle, ScheduledEvent::TimerTick];
+        assert_eq!(run_due_events(&mut scheduler), expected, "Incorrect events");
+
+        // The half tick left over carries into the next advance, even though this is less than a tick
+        scheduler.advance(Duration::from_millis(9));
+        assert_eq!(run_due_events(&mut scheduler).len(), 3, "Expected the leftover time to complete a tick");
+    }
+
+    #[test]
+    fn one_second_is_exactly_sixty_ticks() {
+        // Uneven frame times, as when rendering slows down, shouldn't make the timers drift
+        let mut scheduler = Scheduler::new(0);
+        let mut ticks = 0;
+        for frame in 0..100 {
+            scheduler.advance(Duration::from_micros(if frame % 2 == 0 { 7_000 } else { 13_000 }));
+            ticks += run_due_events(&mut scheduler).len();
+        }
+
+        assert_eq!(ticks, 60, "Incorrect number of timer ticks");
+    }
+
+    #[test]
+    fn stopping_early_resumes_at_same_event() {
+        let mut scheduler = Scheduler::new(2);
+        scheduler.advance(tick_length());
+        scheduler.complete_event();
+        assert!(!scheduler.is_starting_tick(), "Expected to be part way through a tick");
+
+        scheduler.discard_due_time();
+        assert_eq!(scheduler.next_event(), None, "Expected nothing to be due after discarding time");
+
+        scheduler.advance(tick_length());
+        let expected = vec![ScheduledEvent::Cycle, ScheduledEvent::TimerTick];
+        assert_eq!(run_due_events(&mut scheduler), expected, "Expected the rest of the tick to run");
+    }
+
+    #[test]
+    fn cycles_past_the_end_of_a_tick_carry_over() {
+        let mut scheduler = Scheduler::new(10);
+        scheduler.advance(Duration::from_millis(50)); // Three ticks
+        scheduler.complete_cycles(25);
+        assert_eq!(scheduler.next_event(), Some(ScheduledEvent::TimerTick), "Expected the first tick to end");
+
+        scheduler.complete_event();
+        assert_eq!(scheduler.next_event(), Some(ScheduledEvent::TimerTick), "Expected the second tick to be used up");
+
+        scheduler.complete_event();
+        assert_eq!(scheduler.cycles_left_in_tick(), 5, "Incorrect cycles left in the third tick");
+        assert!(scheduler.is_starting_tick(), "Expected no instruction to have started the third tick");
+    }
+
+    #[test]
+    fn long_pauses_do_not_run_every_missed_tick() {
+        let mut scheduler = Scheduler::new(1);
+        scheduler.advance(Duration::from_secs(10));
+        assert_eq!(run_due_events(&mut scheduler).len(), MAX_TICKS_DUE as usize * 2, "Incorrect number of events");
+    }
+}
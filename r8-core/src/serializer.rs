@@ -6,6 +6,17 @@ custom_error! {pub SerializationError
     InvalidSubtractionStoredIn {instruction: Instruction} = "Subtraction requested with invalid storage register: {instruction}",
 }
 
+// Serializes instructions of any size, including XO-CHIP's four byte long load
+pub fn serialize_instruction_bytes(instruction: Instruction) -> Result<Vec<u8>, SerializationError> {
+    match instruction {
+        Instruction::LoadLongAddressIntoIRegister { address } => {
+            Ok(vec![0xf0, 0x00, (address >> 8) as u8, address as u8])
+        }
+
+        instruction => serialize_instruction(instruction).map(|(byte1, byte2)| vec![byte1, byte2])
+    }
+}
+
 pub fn serialize_instruction(instruction: Instruction) -> Result<(u8, u8), SerializationError> {
     match instruction {
         Instruction::AddFromRegister { register1: Register::General(reg1_num), register2: Register::General(reg2_num) } => {
@@ -32,10 +43,22 @@ pub fn serialize_instruction(instruction: Instruction) -> Result<(u8, u8), Seria
             Ok((0x00, 0xe0))
         }
 
+        Instruction::DisableHighResolution => {
+            Ok((0x00, 0xfe))
+        }
+
         Instruction::DrawSprite { x_register: Register::General(x_reg), y_register: Register::General(y_reg), height } => {
             Ok((0xd0 + x_reg, (y_reg << 4) + height))
         }
 
+        Instruction::EnableHighResolution => {
+            Ok((0x00, 0xff))
+        }
+
+        Instruction::Exit => {
+            Ok((0x00, 0xfd))
+        }
+
         Instruction::JumpToAddress { address, add_register_0 } => {
             match add_register_0 {
                 true => Ok((0xb0 + (address >> 8) as u8, address as u8)),
@@ -55,6 +78,18 @@ pub fn serialize_instruction(instruction: Instruction) -> Result<(u8, u8), Seria
             Ok((0xf0 + reg_num, 0x33))
         }
 
+        Instruction::LoadAudioPattern => {
+            Ok((0xf0, 0x02))
+        }
+
+        Instruction::LoadBigSpriteLocation { sprite_digit: Register::General(reg_num) } => {
+            Ok((0xf0 + reg_num, 0x30))
+        }
+
+        Instruction::LoadFromFlags { last_register: Register::General(reg_num) } => {
+            Ok((0xf0 + reg_num, 0x85))
+        }
+
         Instruction::LoadFromKeyPress { destination: Register::General(reg_num) } => {
             Ok((0xf0 + reg_num, 0x0a))
         }
@@ -83,10 +118,18 @@ pub fn serialize_instruction(instruction: Instruction) -> Result<(u8, u8), Seria
             Ok((0x60 + reg_num, value))
         }
 
+        Instruction::LoadIntoFlags { last_register: Register::General(reg_num) } => {
+            Ok((0xf0 + reg_num, 0x75))
+        }
+
         Instruction::LoadIntoMemory { last_register: Register::General(reg_num) } => {
             Ok((0xf0 + reg_num, 0x55))
         }
 
+        Instruction::LoadRegisterRange { first_register: Register::General(first_num), last_register: Register::General(last_num) } => {
+            Ok((0x50 + first_num, (last_num << 4) + 0x3))
+        }
+
         Instruction::LoadSpriteLocation { sprite_digit: Register::General(reg_num) } => {
             Ok((0xf0 + reg_num, 0x29))
         }
@@ -99,16 +142,44 @@ pub fn serialize_instruction(instruction: Instruction) -> Result<(u8, u8), Seria
             Ok((0x00, 0xee))
         }
 
+        Instruction::SaveRegisterRange { first_register: Register::General(first_num), last_register: Register::General(last_num) } => {
+            Ok((0x50 + first_num, (last_num << 4) + 0x2))
+        }
+
+        Instruction::ScrollDown { rows } => {
+            Ok((0x00, 0xc0 + rows))
+        }
+
+        Instruction::ScrollLeft => {
+            Ok((0x00, 0xfc))
+        }
+
+        Instruction::ScrollRight => {
+            Ok((0x00, 0xfb))
+        }
+
+        Instruction::ScrollUp { rows } => {
+            Ok((0x00, 0xd0 + rows))
+        }
+
+        Instruction::SelectPlanes { planes } => {
+            Ok((0xf0 + planes, 0x01))
+        }
+
+        Instruction::SetPitch { register: Register::General(reg_num) } => {
+            Ok((0xf0 + reg_num, 0x3a))
+        }
+
         Instruction::SetRandom { register: Register::General(reg_num), and_value } => {
             Ok((0xc0 + reg_num, and_value))
         }
 
-        Instruction::ShiftLeft { register: Register::General(reg_num)} => {
-            Ok((0x80 + reg_num, 0x0e))
+        Instruction::ShiftLeft { register: Register::General(reg_num), source: Register::General(source_num)} => {
+            Ok((0x80 + reg_num, (source_num << 4) + 0x0e))
         }
 
-        Instruction::ShiftRight { register: Register::General(reg_num)} => {
-            Ok((0x80 + reg_num, 0x06))
+        Instruction::ShiftRight { register: Register::General(reg_num), source: Register::General(source_num)} => {
+            Ok((0x80 + reg_num, (source_num << 4) + 0x06))
         }
 
         Instruction::SkipIfEqual { register: Register::General(reg_num), value} => {
@@ -315,11 +386,11 @@ mod tests {
 
     #[test]
     fn can_serialize_shift_right_instruction() {
-        let instruction = Instruction::ShiftRight {register: Register::General(3)};
+        let instruction = Instruction::ShiftRight {register: Register::General(3), source: Register::General(5)};
         let (byte1, byte2) = serialize_instruction(instruction).unwrap();
 
         assert_eq!(byte1, 0x83, "Incorrect byte 1 value");
-        assert_eq!(byte2, 0x06, "Incorrect byte 2 value");
+        assert_eq!(byte2, 0x56, "Incorrect byte 2 value");
     }
 
     #[test]
@@ -333,11 +404,11 @@ mod tests {
 
     #[test]
     fn can_serialize_shift_left_instruction() {
-        let instruction = Instruction::ShiftLeft {register: Register::General(3)};
+        let instruction = Instruction::ShiftLeft {register: Register::General(3), source: Register::General(5)};
         let (byte1, byte2) = serialize_instruction(instruction).unwrap();
 
         assert_eq!(byte1, 0x83, "Incorrect byte 1 value");
-        assert_eq!(byte2, 0x0e, "Incorrect byte 2 value");
+        assert_eq!(byte2, 0x5e, "Incorrect byte 2 value");
     }
 
     #[test]
@@ -483,4 +554,156 @@ mod tests {
         assert_eq!(byte1, 0xf5, "Incorrect byte 1 value");
         assert_eq!(byte2, 0x65, "Incorrect byte 2 value");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn can_serialize_scroll_down_instruction() {
+        let instruction = Instruction::ScrollDown {rows: 0xa};
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0x00, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0xca, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_scroll_right_instruction() {
+        let instruction = Instruction::ScrollRight;
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0x00, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0xfb, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_scroll_left_instruction() {
+        let instruction = Instruction::ScrollLeft;
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0x00, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0xfc, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_exit_instruction() {
+        let instruction = Instruction::Exit;
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0x00, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0xfd, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_disable_high_resolution_instruction() {
+        let instruction = Instruction::DisableHighResolution;
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0x00, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0xfe, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_enable_high_resolution_instruction() {
+        let instruction = Instruction::EnableHighResolution;
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0x00, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0xff, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_load_big_sprite_digit_instruction() {
+        let instruction = Instruction::LoadBigSpriteLocation {sprite_digit: Register::General(5)};
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0xf5, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0x30, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_load_into_flags_instruction() {
+        let instruction = Instruction::LoadIntoFlags {last_register: Register::General(5)};
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0xf5, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0x75, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_load_from_flags_instruction() {
+        let instruction = Instruction::LoadFromFlags {last_register: Register::General(5)};
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0xf5, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0x85, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_scroll_up_instruction() {
+        let instruction = Instruction::ScrollUp {rows: 0xa};
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0x00, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0xda, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_save_register_range_instruction() {
+        let instruction = Instruction::SaveRegisterRange {first_register: Register::General(2), last_register: Register::General(9)};
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0x52, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0x92, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_load_register_range_instruction() {
+        let instruction = Instruction::LoadRegisterRange {first_register: Register::General(9), last_register: Register::General(2)};
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0x59, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0x23, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_select_planes_instruction() {
+        let instruction = Instruction::SelectPlanes {planes: 2};
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0xf2, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0x01, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_load_audio_pattern_instruction() {
+        let instruction = Instruction::LoadAudioPattern;
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0xf0, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0x02, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_set_pitch_instruction() {
+        let instruction = Instruction::SetPitch {register: Register::General(6)};
+        let (byte1, byte2) = serialize_instruction(instruction).unwrap();
+
+        assert_eq!(byte1, 0xf6, "Incorrect byte 1 value");
+        assert_eq!(byte2, 0x3a, "Incorrect byte 2 value");
+    }
+
+    #[test]
+    fn can_serialize_long_load_address_instruction() {
+        let instruction = Instruction::LoadLongAddressIntoIRegister {address: 0xbeef};
+        let bytes = serialize_instruction_bytes(instruction).unwrap();
+
+        assert_eq!(bytes, vec![0xf0, 0x00, 0xbe, 0xef], "Incorrect bytes");
+    }
+
+    #[test]
+    fn long_load_address_instruction_does_not_fit_in_two_bytes() {
+        let instruction = Instruction::LoadLongAddressIntoIRegister {address: 0xbeef};
+        match serialize_instruction(instruction).unwrap_err() {
+            SerializationError::UnserializableInstruction {..} => (),
+            x => panic!("Expected UnserializableInstruction, instead got {:?}", x),
+        }
+    }
+}
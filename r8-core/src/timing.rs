@@ -0,0 +1,139 @@
+use crate::{Hardware, Instruction, Register};
+
+// The VIP's 1802 runs at 1.76064mhz with 8 clock cycles per machine cycle, giving 3668 machine
+// cycles per 60hz frame.  About 1100 of them go to the display's DMA and the interrupt routine,
+// leaving the rest for the interpreter.
+pub const VIP_CYCLES_PER_TICK: u32 = 3668 - 1100;
+
+// Fetching and decoding each instruction, before the instruction's own routine runs
+const VIP_FETCH_CYCLES: u32 = 40;
+
+// Loading a value into a register is the quickest instruction
+const VIP_CHEAPEST_EXECUTION_CYCLES: u32 = 6;
+
+// Sprites are drawn a row at a time, with each row's byte shifted one bit at a time into place
+// and written to a second byte as well when it isn't aligned to one
+const VIP_DRAW_CYCLES: u32 = 68;
+const VIP_DRAW_ROW_CYCLES: u32 = 46;
+const VIP_DRAW_SHIFT_CYCLES: u32 = 8;
+const VIP_DRAW_UNALIGNED_ROW_CYCLES: u32 = 26;
+
+// Fx55 and Fx65 copy one register at a time
+const VIP_LOAD_STORE_CYCLES: u32 = 16;
+const VIP_LOAD_STORE_REGISTER_CYCLES: u32 = 14;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum TimingModel {
+    // Every instruction takes one cycle, so the speed is set by how many cycles run per tick
+    Fixed,
+
+    // Instructions take about as many machine cycles as they did in the original COSMAC VIP
+    // interpreter.  With the display wait quirk, a sprite draw waiting for the display uses up
+    // the rest of the tick, as the VIP sat idle until its next display interrupt.
+    CosmacVip,
+}
+
+impl TimingModel {
+    // Cycles the scheduler should run per tick, if the model has its own speed
+    pub fn cycles_per_tick(&self) -> Option<u32> {
+        match self {
+            TimingModel::Fixed => None,
+            TimingModel::CosmacVip => Some(VIP_CYCLES_PER_TICK),
+        }
+    }
+
+    // The most instructions that can run in a tick, for sizing things kept per instruction
+    pub fn max_instructions_per_tick(&self, cycles_per_tick: u32) -> u32 {
+        match self {
+            TimingModel::Fixed => cycles_per_tick,
+            TimingModel::CosmacVip => cycles_per_tick / (VIP_FETCH_CYCLES + VIP_CHEAPEST_EXECUTION_CYCLES),
+        }
+    }
+
+    // How many cycles the instruction takes when run on the given hardware
+    pub fn instruction_cycles(&self, instruction: &Instruction, hardware: &Hardware) -> u32 {
+        match self {
+            TimingModel::Fixed => 1,
+            TimingModel::CosmacVip => VIP_FETCH_CYCLES + vip_execution_cycles(instruction, hardware),
+        }
+    }
+}
+
+// Costs for the SUPER-CHIP and XO-CHIP instructions are made up, since they never ran on the VIP
+fn vip_execution_cycles(instruction: &Instruction, hardware: &Hardware) -> u32 {
+    match instruction {
+        Instruction::DrawSprite {x_register: Register::General(x), height, ..} => {
+            let shift = (hardware.gen_registers[*x as usize] % 8) as u32;
+            let mut row_cycles = VIP_DRAW_ROW_CYCLES + shift * VIP_DRAW_SHIFT_CYCLES;
+            if shift > 0 {
+                row_cycles += VIP_DRAW_UNALIGNED_ROW_CYCLES;
+            }
+
+            VIP_DRAW_CYCLES + *height as u32 * row_cycles
+        }
+
+        Instruction::LoadIntoMemory {last_register: Register::General(last)} |
+        Instruction::LoadFromMemory {last_register: Register::General(last)} => {
+            VIP_LOAD_STORE_CYCLES + (*last as u32 + 1) * VIP_LOAD_STORE_REGISTER_CYCLES
+        }
+
+        Instruction::ClearDisplay => 24,
+        Instruction::Return | Instruction::Call {..} | Instruction::JumpToAddress {..} | Instruction::JumpToMachineCode {..} => 23,
+        Instruction::SkipIfEqual {..} | Instruction::SkipIfNotEqual {..} => 12,
+        Instruction::SkipIfRegistersEqual {..} | Instruction::SkipIfRegistersNotEqual {..} => 16,
+        Instruction::LoadFromValue {..} => VIP_CHEAPEST_EXECUTION_CYCLES,
+        Instruction::AddFromValue {..} => 10,
+        Instruction::AddFromRegister {register1: Register::I, ..} => 19,
+        Instruction::LoadFromRegister {destination: Register::General(_), source: Register::General(_)} |
+        Instruction::Or {..} | Instruction::And {..} | Instruction::Xor {..} | Instruction::AddFromRegister {..} |
+        Instruction::Subtract {..} | Instruction::ShiftLeft {..} | Instruction::ShiftRight {..} => 44,
+        Instruction::LoadAddressIntoIRegister {..} => 12,
+        Instruction::SetRandom {..} => 36,
+        Instruction::SkipIfKeyPressed {..} | Instruction::SkipIfKeyNotPressed {..} => 16,
+        Instruction::LoadFromRegister {..} => 10, // Timer loads
+        Instruction::LoadFromKeyPress {..} => 10, // Each check while waiting for a key
+        Instruction::LoadSpriteLocation {..} => 20,
+        Instruction::LoadBcdValue {..} => 204,
+        _ => 10,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(height: u8) -> Instruction {
+        Instruction::DrawSprite {x_register: Register::General(0), y_register: Register::General(1), height}
+    }
+
+    #[test]
+    fn fixed_timing_costs_one_cycle() {
+        let hardware = Hardware::new();
+        assert_eq!(TimingModel::Fixed.instruction_cycles(&draw(15), &hardware), 1, "Incorrect draw cycles");
+        assert_eq!(TimingModel::Fixed.cycles_per_tick(), None, "Fixed timing should not set the speed");
+    }
+
+    #[test]
+    fn vip_draw_cost_depends_on_height_and_alignment() {
+        let mut hardware = Hardware::new();
+        let timing = TimingModel::CosmacVip;
+        let aligned_short = timing.instruction_cycles(&draw(1), &hardware);
+        let aligned_tall = timing.instruction_cycles(&draw(8), &hardware);
+
+        hardware.gen_registers[0] = 3;
+        let unaligned_tall = timing.instruction_cycles(&draw(8), &hardware);
+
+        assert!(aligned_tall > aligned_short, "Taller sprites should take longer");
+        assert!(unaligned_tall > aligned_tall, "Unaligned sprites should take longer");
+        assert_eq!(aligned_tall, VIP_FETCH_CYCLES + VIP_DRAW_CYCLES + 8 * VIP_DRAW_ROW_CYCLES, "Incorrect aligned draw cycles");
+    }
+
+    #[test]
+    fn vip_load_store_cost_depends_on_register_count() {
+        let hardware = Hardware::new();
+        let store = |last| Instruction::LoadIntoMemory {last_register: Register::General(last)};
+        let timing = TimingModel::CosmacVip;
+        let difference = timing.instruction_cycles(&store(3), &hardware) - timing.instruction_cycles(&store(0), &hardware);
+        assert_eq!(difference, 3 * VIP_LOAD_STORE_REGISTER_CYCLES, "Incorrect cycles per register");
+    }
+}
@@ -0,0 +1,429 @@
+use std::io;
+use std::io::{Read, Write};
+use custom_error::custom_error;
+use crate::{Hardware, Instruction, get_instruction_at};
+use crate::debugger::{instruction_name, parse_address, DebuggerError};
+
+const BINARY_MAGIC: &[u8; 4] = b"R8TR";
+const BINARY_VERSION: u8 = 1;
+const MAX_INSTRUCTION_SIZE: usize = 4;
+const REGISTERS_SIZE: usize = 20;
+const BINARY_ENTRY_SIZE: usize = 8 + 2 + 1 + MAX_INSTRUCTION_SIZE + REGISTERS_SIZE * 2;
+
+custom_error!{pub TraceError
+    Io {source: io::Error} = "Could not read trace: {source}",
+    UnsupportedVersion {version: u8} = "Trace version {version} is not supported",
+    Malformed {line: usize, reason: String} = "Trace entry {line} is malformed: {reason}",
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum TraceFormat {
+    Text,
+    Binary,
+}
+
+// The values compared before and after each instruction
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct TraceRegisters {
+    pub gen_registers: [u8; 16],
+    pub i_register: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+// State captured just before an instruction runs, which is completed into an entry once it has
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct PendingTrace {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub instruction: Instruction,
+    pub before: TraceRegisters,
+}
+
+// One instruction read back from a trace
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct TraceEntry {
+    pub cycle: u64,
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub before: TraceRegisters,
+    pub after: TraceRegisters,
+}
+
+// Only instructions matching every filter that is set are written
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct TraceFilter {
+    pub address_range: Option<(u16, u16)>, // inclusive
+    pub instruction_names: Vec<String>, // names of `Instruction` variants, such as `DrawSprite`
+}
+
+// Writes one entry per executed instruction.  Each entry has the cycle number (counting every
+// executed instruction, even ones that were filtered out), the address, the raw bytes and the
+// registers, I and timers before and after it ran.
+pub struct Tracer<W: Write> {
+    writer: W,
+    format: TraceFormat,
+    filter: TraceFilter,
+    cycle: u64,
+}
+
+impl TraceRegisters {
+    pub fn capture(hardware: &Hardware) -> Self {
+        TraceRegisters {
+            gen_registers: hardware.gen_registers,
+            i_register: hardware.i_register,
+            delay_timer: hardware.delay_timer,
+            sound_timer: hardware.sound_timer,
+        }
+    }
+}
+
+impl PendingTrace {
+    pub fn capture(hardware: &Hardware) -> Self {
+        let instruction = hardware.get_current_instruction();
+        let start = hardware.program_counter as usize;
+        let end = (start + instruction.size() as usize).min(hardware.memory.len());
+        PendingTrace {
+            address: hardware.program_counter,
+            bytes: hardware.memory[start.min(end)..end].to_vec(),
+            instruction,
+            before: TraceRegisters::capture(hardware),
+        }
+    }
+}
+
+impl TraceFilter {
+    // Parses either a single address or an inclusive range such as `0x200-0x2ff`
+    pub fn parse_address_range(text: &str) -> Result<(u16, u16), DebuggerError> {
+        let invalid = || DebuggerError::InvalidRange {text: text.to_owned()};
+        let (start, end) = match text.find('-') {
+            Some(index) => (&text[..index], &text[index + 1..]),
+            None => (text, text),
+        };
+
+        match (parse_address(start), parse_address(end)) {
+            (Ok(start), Ok(end)) if start <= end => Ok((start, end)),
+            _ => Err(invalid()),
+        }
+    }
+
+    pub fn matches(&self, address: u16, instruction: &Instruction) -> bool {
+        let in_range = match self.address_range {
+            Some((start, end)) => address >= start && address <= end,
+            None => true,
+        };
+
+        let name = instruction_name(instruction);
+        let is_wanted_type = self.instruction_names.is_empty()
+            || self.instruction_names.iter().any(|x| x.eq_ignore_ascii_case(&name));
+
+        in_range && is_wanted_type
+    }
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(mut writer: W, format: TraceFormat, filter: TraceFilter) -> io::Result<Self> {
+        match format {
+            TraceFormat::Text => writeln!(writer, "# cycle pc=address op=bytes registers before -> registers after ; instruction")?,
+            TraceFormat::Binary => {
+                writer.write_all(BINARY_MAGIC)?;
+                writer.write_all(&[BINARY_VERSION])?;
+            }
+        }
+
+        Ok(Tracer {writer, format, filter, cycle: 0})
+    }
+
+    // Completes the entry for an instruction that has just been executed
+    pub fn record(&mut self, pending: &PendingTrace, hardware: &Hardware) -> io::Result<()> {
+        self.cycle += 1;
+        if !self.filter.matches(pending.address, &pending.instruction) {
+            return Ok(());
+        }
+
+        let after = TraceRegisters::capture(hardware);
+        match self.format {
+            TraceFormat::Text => {
+                let bytes = pending.bytes.iter().map(|x| format!("{:02x}", x)).collect::<String>();
+                writeln!(self.writer, "{} pc={:04x} op={} {} -> {} ; {}",
+                         self.cycle, pending.address, bytes, format_registers(&pending.before), format_registers(&after), pending.instruction)
+            }
+
+            TraceFormat::Binary => {
+                let mut bytes = [0_u8; MAX_INSTRUCTION_SIZE];
+                bytes[..pending.bytes.len()].copy_from_slice(&pending.bytes);
+
+                self.writer.write_all(&self.cycle.to_le_bytes())?;
+                self.writer.write_all(&pending.address.to_le_bytes())?;
+                self.writer.write_all(&[pending.bytes.len() as u8])?;
+                self.writer.write_all(&bytes)?;
+                write_registers(&mut self.writer, &pending.before)?;
+                write_registers(&mut self.writer, &after)
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl TraceEntry {
+    pub fn instruction(&self) -> Instruction {
+        get_instruction_at(&self.bytes, 0)
+    }
+}
+
+// Reads a trace written in either format, which is detected from the start of the file
+pub fn read_trace(reader: &mut impl Read) -> Result<Vec<TraceEntry>, TraceError> {
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents)?;
+
+    match contents.starts_with(BINARY_MAGIC) {
+        true => read_binary_trace(&contents[BINARY_MAGIC.len()..]),
+        false => read_text_trace(&String::from_utf8_lossy(&contents)),
+    }
+}
+
+fn read_binary_trace(contents: &[u8]) -> Result<Vec<TraceEntry>, TraceError> {
+    match contents.first() {
+        Some(&BINARY_VERSION) => (),
+        Some(&version) => return Err(TraceError::UnsupportedVersion {version}),
+        None => return Err(TraceError::Malformed {line: 0, reason: "missing version".to_owned()}),
+    }
+
+    let records = &contents[1..];
+    if !records.len().is_multiple_of(BINARY_ENTRY_SIZE) {
+        let line = records.len() / BINARY_ENTRY_SIZE + 1;
+        return Err(TraceError::Malformed {line, reason: "entry is cut short".to_owned()});
+    }
+
+    let mut entries = Vec::new();
+    for (index, record) in records.chunks(BINARY_ENTRY_SIZE).enumerate() {
+        let mut cycle = [0_u8; 8];
+        cycle.copy_from_slice(&record[0..8]);
+
+        let byte_count = record[10] as usize;
+        if byte_count > MAX_INSTRUCTION_SIZE {
+            return Err(TraceError::Malformed {line: index + 1, reason: format!("{} instruction bytes", byte_count)});
+        }
+
+        let registers_start = 11 + MAX_INSTRUCTION_SIZE;
+        entries.push(TraceEntry {
+            cycle: u64::from_le_bytes(cycle),
+            address: u16::from_le_bytes([record[8], record[9]]),
+            bytes: record[11..11 + byte_count].to_vec(),
+            before: read_registers(&record[registers_start..registers_start + REGISTERS_SIZE]),
+            after: read_registers(&record[registers_start + REGISTERS_SIZE..]),
+        });
+    }
+
+    Ok(entries)
+}
+
+fn read_text_trace(contents: &str) -> Result<Vec<TraceEntry>, TraceError> {
+    let mut entries = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let malformed = |reason: &str| TraceError::Malformed {line: index + 1, reason: reason.to_owned()};
+
+        // The disassembly after the `;` is only there for people reading the trace
+        let values = line.split(';').next().unwrap_or("");
+        let (before, after) = match values.find("->") {
+            Some(arrow) => (&values[..arrow], &values[arrow + 2..]),
+            None => return Err(malformed("missing '->'")),
+        };
+
+        let mut words = before.split_whitespace();
+        let cycle = words.next().and_then(|x| x.parse::<u64>().ok()).ok_or_else(|| malformed("invalid cycle"))?;
+        let address = text_field(words.next(), "pc=").and_then(|x| u16::from_str_radix(x, 16).ok())
+            .ok_or_else(|| malformed("invalid pc"))?;
+
+        let bytes = text_field(words.next(), "op=").and_then(parse_hex_bytes)
+            .filter(|x| x.len() <= MAX_INSTRUCTION_SIZE)
+            .ok_or_else(|| malformed("invalid op"))?;
+
+        entries.push(TraceEntry {
+            cycle,
+            address,
+            bytes,
+            before: parse_text_registers(words).ok_or_else(|| malformed("invalid registers before"))?,
+            after: parse_text_registers(after.split_whitespace()).ok_or_else(|| malformed("invalid registers after"))?,
+        });
+    }
+
+    Ok(entries)
+}
+
+fn text_field<'a>(word: Option<&'a str>, prefix: &str) -> Option<&'a str> {
+    word.and_then(|x| match x.starts_with(prefix) {
+        true => Some(&x[prefix.len()..]),
+        false => None,
+    })
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..text.len()).step_by(2)
+        .map(|index| text.get(index..index + 2).and_then(|x| u8::from_str_radix(x, 16).ok()))
+        .collect()
+}
+
+fn parse_text_registers<'a>(mut words: impl Iterator<Item = &'a str>) -> Option<TraceRegisters> {
+    let values = text_field(words.next(), "V=").and_then(parse_hex_bytes).filter(|x| x.len() == 16)?;
+    let mut gen_registers = [0_u8; 16];
+    gen_registers.copy_from_slice(&values);
+
+    Some(TraceRegisters {
+        gen_registers,
+        i_register: text_field(words.next(), "I=").and_then(|x| u16::from_str_radix(x, 16).ok())?,
+        delay_timer: text_field(words.next(), "DT=").and_then(|x| u8::from_str_radix(x, 16).ok())?,
+        sound_timer: text_field(words.next(), "ST=").and_then(|x| u8::from_str_radix(x, 16).ok())?,
+    })
+}
+
+fn format_registers(registers: &TraceRegisters) -> String {
+    let values = registers.gen_registers.iter().map(|x| format!("{:02x}", x)).collect::<String>();
+    format!("V={} I={:04x} DT={:02x} ST={:02x}", values, registers.i_register, registers.delay_timer, registers.sound_timer)
+}
+
+fn write_registers(writer: &mut impl Write, registers: &TraceRegisters) -> io::Result<()> {
+    writer.write_all(&registers.gen_registers)?;
+    writer.write_all(&registers.i_register.to_le_bytes())?;
+    writer.write_all(&[registers.delay_timer, registers.sound_timer])
+}
+
+fn read_registers(bytes: &[u8]) -> TraceRegisters {
+    let mut gen_registers = [0_u8; 16];
+    gen_registers.copy_from_slice(&bytes[0..16]);
+
+    TraceRegisters {
+        gen_registers,
+        i_register: u16::from_le_bytes([bytes[16], bytes[17]]),
+        delay_timer: bytes[18],
+        sound_timer: bytes[19],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn traced_step(tracer: &mut Tracer<Vec<u8>>, hardware: &mut Hardware) {
+        let pending = PendingTrace::capture(hardware);
+        let instruction = hardware.get_current_instruction();
+        crate::execute_instruction(instruction, hardware).unwrap();
+        tracer.record(&pending, hardware).unwrap();
+    }
+
+    fn hardware_with_program(bytes: &[u8]) -> Hardware {
+        let mut hardware = Hardware::new();
+        hardware.memory[0x200..0x200 + bytes.len()].copy_from_slice(bytes);
+        hardware
+    }
+
+    #[test]
+    fn text_trace_has_one_line_per_instruction() {
+        // 200: LD Va, 5 / 202: LD I, 300
+        let mut hardware = hardware_with_program(&[0x6a, 0x05, 0xa3, 0x00]);
+        let mut tracer = Tracer::new(Vec::new(), TraceFormat::Text, TraceFilter::default()).unwrap();
+        traced_step(&mut tracer, &mut hardware);
+        traced_step(&mut tracer, &mut hardware);
+
+        let text = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        let lines = text.lines().collect::<Vec<&str>>();
+        let zeros = "00".repeat(16);
+        let after = format!("{}05{}", "00".repeat(10), "00".repeat(5));
+
+        assert_eq!(lines.len(), 3, "Expected a header and two entries");
+        assert_eq!(lines[1], format!("1 pc=0200 op=6a05 V={} I=0000 DT=00 ST=00 -> V={} I=0000 DT=00 ST=00 ; LD Va, 5", zeros, after));
+        assert_eq!(lines[2], format!("2 pc=0202 op=a300 V={} I=0000 DT=00 ST=00 -> V={} I=0300 DT=00 ST=00 ; LD I, 300", after, after));
+    }
+
+    #[test]
+    fn filtered_instructions_still_count_cycles() {
+        // 200: LD Va, 5 / 202: LD I, 300
+        let mut hardware = hardware_with_program(&[0x6a, 0x05, 0xa3, 0x00]);
+        let filter = TraceFilter {address_range: Some((0x202, 0x2ff)), instruction_names: Vec::new()};
+        let mut tracer = Tracer::new(Vec::new(), TraceFormat::Text, filter).unwrap();
+        traced_step(&mut tracer, &mut hardware);
+        traced_step(&mut tracer, &mut hardware);
+
+        let text = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        let lines = text.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 2, "Expected only the second instruction");
+        assert!(lines[1].starts_with("2 pc=0202"), "Incorrect entry: {}", lines[1]);
+    }
+
+    #[test]
+    fn can_filter_by_instruction_type() {
+        let filter = TraceFilter {address_range: None, instruction_names: vec!["drawsprite".to_owned()]};
+        let draw = Instruction::DrawSprite {x_register: crate::Register::General(0), y_register: crate::Register::General(1), height: 1};
+        assert!(filter.matches(0x200, &draw), "Expected draw to match");
+        assert!(!filter.matches(0x200, &Instruction::ClearDisplay), "Expected clear to not match");
+    }
+
+    #[test]
+    fn binary_trace_uses_fixed_size_entries() {
+        // 200: LD Va, 5 / 202: LDL I, 1234
+        let mut hardware = hardware_with_program(&[0x6a, 0x05, 0xf0, 0x00, 0x12, 0x34]);
+        hardware.platform = crate::Platform::XoChip;
+        let mut tracer = Tracer::new(Vec::new(), TraceFormat::Binary, TraceFilter::default()).unwrap();
+        traced_step(&mut tracer, &mut hardware);
+        traced_step(&mut tracer, &mut hardware);
+
+        let bytes = tracer.finish().unwrap();
+        let entry_size = BINARY_ENTRY_SIZE;
+        assert_eq!(&bytes[0..5], b"R8TR\x01", "Incorrect header");
+        assert_eq!(bytes.len(), 5 + entry_size * 2, "Incorrect trace size");
+
+        let second = &bytes[5 + entry_size..];
+        assert_eq!(&second[0..8], &2_u64.to_le_bytes(), "Incorrect cycle");
+        assert_eq!(&second[8..10], &0x202_u16.to_le_bytes(), "Incorrect address");
+        assert_eq!(&second[10..15], &[4, 0xf0, 0x00, 0x12, 0x34], "Incorrect instruction bytes");
+    }
+
+    #[test]
+    fn can_read_back_both_formats() {
+        for format in &[TraceFormat::Text, TraceFormat::Binary] {
+            // 200: LD Va, 5 / 202: LDL I, 1234
+            let mut hardware = hardware_with_program(&[0x6a, 0x05, 0xf0, 0x00, 0x12, 0x34]);
+            hardware.platform = crate::Platform::XoChip;
+            let mut tracer = Tracer::new(Vec::new(), *format, TraceFilter::default()).unwrap();
+            traced_step(&mut tracer, &mut hardware);
+            traced_step(&mut tracer, &mut hardware);
+
+            let bytes = tracer.finish().unwrap();
+            let entries = read_trace(&mut bytes.as_slice()).unwrap();
+            assert_eq!(entries.len(), 2, "Incorrect number of entries for {:?}", format);
+            assert_eq!(entries[1].cycle, 2, "Incorrect cycle for {:?}", format);
+            assert_eq!(entries[1].address, 0x202, "Incorrect address for {:?}", format);
+            assert_eq!(entries[1].instruction(), Instruction::LoadLongAddressIntoIRegister {address: 0x1234}, "Incorrect instruction for {:?}", format);
+            assert_eq!(entries[1].before.gen_registers[0xa], 5, "Incorrect Va for {:?}", format);
+            assert_eq!(entries[1].after.i_register, 0x1234, "Incorrect I for {:?}", format);
+        }
+    }
+
+    #[test]
+    fn malformed_text_trace_reports_line() {
+        let text = "# header\n1 pc=0200 op=zz V=00 I=0000 DT=00 ST=00 -> V=00 I=0000 DT=00 ST=00\n";
+        match read_trace(&mut text.as_bytes()).unwrap_err() {
+            TraceError::Malformed {line: 2, ..} => (),
+            x => panic!("Expected Malformed on line 2, instead got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn can_parse_address_range() {
+        assert_eq!(TraceFilter::parse_address_range("0x200-0x2ff").unwrap(), (0x200, 0x2ff));
+        assert!(TraceFilter::parse_address_range("0x2ff-0x200").is_err(), "Expected reversed range to fail");
+    }
+}
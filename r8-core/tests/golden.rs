@@ -0,0 +1,175 @@
+// Whole program tests, which run the programs in `roms` and compare the display with the
+// expected images in `golden`.  Run with R8_BLESS=1 to update the images after an intended change.
+//
+// The images only catch changes, since they were taken from this interpreter.  The quirk results
+// are also checked against how each platform is documented to behave, and well known test ROMs
+// are run against hand drawn images when R8_TEST_ROMS names a directory holding them (see
+// `well_known_roms`).
+
+extern crate r8_core;
+extern crate r8_golden;
+
+use std::env;
+use std::path::PathBuf;
+use r8_core::{Hardware, Platform, Quirks};
+use r8_golden::GoldenTest;
+
+const QUIRKS: &str = include_str!("roms/quirks.asm");
+const HIRES: &str = include_str!("roms/hires.asm");
+const XOCHIP: &str = include_str!("roms/xochip.asm");
+const KEYS: &str = include_str!("roms/keys.asm");
+const ARITHMETIC: &str = include_str!("roms/arithmetic.asm");
+
+// Directory holding ROMs that can't be checked in, for `well_known_roms`
+const TEST_ROMS_VARIABLE: &str = "R8_TEST_ROMS";
+
+// The digits quirks.asm shows for each quirk, from the platform descriptions in Timendus'
+// chip8-test-suite (its quirks test expects the same behaviour for each platform)
+struct QuirkResults {
+    shift: u8,
+    logic: u8,
+    load_store: u8,
+    jump: u8,
+    display_wait: bool,
+    clipping: bool,
+}
+
+fn golden_directory() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn check_quirk_results(hardware: &Hardware, expected: QuirkResults) {
+    assert_eq!(hardware.gen_registers[4], expected.shift, "Incorrect shift result");
+    assert_eq!(hardware.gen_registers[5], expected.logic, "Incorrect logic result");
+    assert_eq!(hardware.gen_registers[9], expected.load_store, "Incorrect load/store result");
+    assert_eq!(hardware.gen_registers[0xd], expected.jump, "Incorrect jump result");
+
+    // Waiting draws can only happen once per frame, so three frames give exactly three
+    let sprites_drawn = hardware.gen_registers[0xe];
+    match expected.display_wait {
+        true => assert_eq!(sprites_drawn, 3, "Expected one draw per frame"),
+        false => assert!(sprites_drawn > 3, "Expected more than one draw per frame, instead got {}", sprites_drawn),
+    }
+
+    // The 0 drawn at x=0x3e, y=0x14 only reaches the left edge when it wraps
+    assert_eq!(hardware.pixel(0, 0x14) == 0, expected.clipping, "Incorrect clipping");
+}
+
+#[test]
+fn quirks_legacy() {
+    // Not a real platform, but how r8 behaved before it had quirks
+    let mut test = GoldenTest::from_assembly("quirks_legacy", QUIRKS);
+    test.quirks = Quirks::default();
+    test.check(&golden_directory());
+
+    let expected = QuirkResults {shift: 1, logic: 5, load_store: 7, jump: 1, display_wait: false, clipping: false};
+    check_quirk_results(&test.run().unwrap(), expected);
+}
+
+#[test]
+fn quirks_cosmac_vip() {
+    let mut test = GoldenTest::from_assembly("quirks_cosmac_vip", QUIRKS);
+    test.quirks = Quirks::cosmac_vip();
+    test.check(&golden_directory());
+
+    let expected = QuirkResults {shift: 6, logic: 0, load_store: 7, jump: 1, display_wait: true, clipping: true};
+    check_quirk_results(&test.run().unwrap(), expected);
+}
+
+#[test]
+fn quirks_super_chip() {
+    // The test suite's modern SUPER-CHIP, which unlike SUPER-CHIP 1.1 doesn't wait for the display
+    let mut test = GoldenTest::from_assembly("quirks_super_chip", QUIRKS);
+    test.quirks = Quirks::super_chip();
+    test.check(&golden_directory());
+
+    let expected = QuirkResults {shift: 1, logic: 5, load_store: 9, jump: 2, display_wait: false, clipping: true};
+    check_quirk_results(&test.run().unwrap(), expected);
+}
+
+#[test]
+fn quirks_xo_chip() {
+    let mut test = GoldenTest::from_assembly("quirks_xo_chip", QUIRKS);
+    test.quirks = Quirks::xo_chip();
+    test.check(&golden_directory());
+
+    let expected = QuirkResults {shift: 6, logic: 5, load_store: 7, jump: 1, display_wait: false, clipping: false};
+    check_quirk_results(&test.run().unwrap(), expected);
+}
+
+#[test]
+fn super_chip_high_resolution() {
+    let mut test = GoldenTest::from_assembly("super_chip_high_resolution", HIRES);
+    test.set_platform(Platform::SuperChip);
+    test.check(&golden_directory());
+}
+
+#[test]
+fn xo_chip_bitplanes() {
+    let mut test = GoldenTest::from_assembly("xo_chip_bitplanes", XOCHIP);
+    test.set_platform(Platform::XoChip);
+    test.check(&golden_directory());
+}
+
+#[test]
+fn key_presses() {
+    let mut test = GoldenTest::from_assembly("key_presses", KEYS);
+    test.key_changes = vec![(2, 1 << 5), (4, 0), (6, 1 << 0xa), (8, 0)];
+    test.check(&golden_directory());
+}
+
+#[test]
+fn arithmetic_and_bcd() {
+    let test = GoldenTest::from_assembly("arithmetic_and_bcd", ARITHMETIC);
+    test.check(&golden_directory());
+}
+
+// Runs well known test ROMs from the directory in R8_TEST_ROMS.  Their licenses don't all allow
+// checking them in, so they come from
+//   github.com/Timendus/chip8-test-suite (the numbered ROMs)
+//   github.com/corax89/chip8-test-rom (test_opcode.ch8)
+//   BestCoder's BC_test.ch8, which is in most CHIP-8 ROM collections
+// Their expected images in `golden/external` are drawn by hand, never blessed, since otherwise
+// they would only check r8 against itself.  2-ibm-logo.txt is the logo's sprite data drawn at the
+// positions the ROM gives.  ROMs without an image yet, or missing from the directory, are skipped.
+#[test]
+fn well_known_roms() {
+    let rom_directory = match env::var_os(TEST_ROMS_VARIABLE) {
+        Some(directory) => PathBuf::from(directory),
+        None => {
+            eprintln!("Skipping well known ROMs, set {} to the directory holding them to run them", TEST_ROMS_VARIABLE);
+            return;
+        }
+    };
+
+    let roms = [
+        ("1-chip8-logo.ch8", Platform::Chip8, 40),
+        ("2-ibm-logo.ch8", Platform::Chip8, 20),
+        ("3-corax+.ch8", Platform::Chip8, 40),
+        ("4-flags.ch8", Platform::Chip8, 60),
+        ("test_opcode.ch8", Platform::Chip8, 40),
+        ("BC_test.ch8", Platform::Chip8, 120),
+    ];
+
+    let image_directory = golden_directory().join("external");
+    for (file_name, platform, frames) in roms.iter() {
+        let name = file_name.trim_end_matches(".ch8");
+        if !image_directory.join(format!("{}.txt", name)).exists() {
+            eprintln!("Skipping {}, it has no hand drawn expected image yet", file_name);
+            continue;
+        }
+
+        let rom = match std::fs::read(rom_directory.join(file_name)) {
+            Ok(rom) => rom,
+            Err(error) => {
+                eprintln!("Skipping {}, it could not be read from {} ({})", file_name, rom_directory.display(), error);
+                continue;
+            }
+        };
+
+        let mut test = GoldenTest::new(name, rom);
+        test.set_platform(*platform);
+        test.frames = *frames;
+        test.verify(&image_directory);
+    }
+}
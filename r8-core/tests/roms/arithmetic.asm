@@ -0,0 +1,26 @@
+; Adds 7b (123) and 2d (45), then splits the result into decimal digits with the BCD instruction
+; and shows them with a subroutine, which should read 168
+
+        LD V0, 7b
+        LD V1, 2d
+        ADD V0, V1
+        LD I, digits
+        LD B, V0
+        LD V2, [I]
+        LD V3, 0
+        LD V4, 0
+        LD VA, V0
+        CALL show
+        LD VA, V1
+        CALL show
+        LD VA, V2
+        CALL show
+done:   JP done
+
+; Draws the digit in VA at V3, V4 and moves V3 along to the next digit
+show:   LD F, VA
+        DRW V3, V4, 5
+        ADD V3, 5
+        RET
+
+digits: db 0, 0, 0
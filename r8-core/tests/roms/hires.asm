@@ -0,0 +1,15 @@
+; SUPER-CHIP high resolution mode: a large 8 from the 8x10 font in the top left, scrolled down four rows and right
+; four pixels, with a small 8 drawn after scrolling in the bottom right corner
+
+        HIGH
+        LD V0, 8
+        LD V1, 0
+        LD HF, V0
+        DRW V1, V1, a
+        SCD 4
+        SCR
+        LD F, V0
+        LD V1, 7a
+        LD V2, 3b
+        DRW V1, V2, 5
+done:   JP done
@@ -0,0 +1,12 @@
+; Waits for two key presses and shows each key as a digit, so scripted input reaches the program
+
+        LD V1, 0
+        LD V2, 0
+        LD V0, K
+        LD F, V0
+        DRW V1, V2, 5
+        LD V1, 5
+        LD V0, K
+        LD F, V0
+        DRW V1, V2, 5
+done:   JP done
@@ -0,0 +1,84 @@
+; Shows the result of each quirk as a digit along the top of the screen, and keeps it in a
+; register so tests can check it without reading the display:
+;   x=00  V4  shift:        6 when 8xy6 shifts Vy, 1 when it shifts Vx in place
+;   x=05  V5  logic:        0 when OR resets VF, 5 when it doesn't
+;   x=0a  V9  load/store:   7 when Fx55 moves I past the stored registers, 9 when it doesn't
+;   x=0f  VD  jump:         2 when Bnnn adds Vx, 1 when it adds V0
+;   x=14  VE  display wait: how many sprites were drawn in three frames, which is 3 when each
+;                           draw waits for the display and more when they don't
+; Then draws a 0 on the right edge of the screen, which wraps round to the left edge unless
+; sprites are clipped.
+
+        LD VC, 0
+
+        LD V0, 3
+        LD V1, C
+        SHR V0, V1
+        LD VA, V0
+        LD V4, VA
+        LD VB, 0
+        CALL digit
+
+        LD VF, 5
+        OR V2, V3
+        LD VA, VF
+        LD V5, VA
+        LD VB, 5
+        CALL digit
+
+        LD I, scratch
+        LD V0, 9
+        LD [I], V0
+        LD V0, [I]
+        LD VA, V0
+        LD V9, VA
+        LD VB, 0a
+        CALL digit
+
+        LD V0, 0
+        LD V2, 2
+        JP V0, jumps
+jumps:  JP jump_v0
+        JP jump_vx
+jump_v0:
+        LD VA, 1
+        JP jump_done
+jump_vx:
+        LD VA, 2
+jump_done:
+        LD VD, VA
+        LD VB, 0f
+        CALL digit
+
+; When draws wait for the display, the second blank draw starts the count at the beginning of a frame
+        LD V6, 0
+        LD I, blank
+        DRW V6, VC, 1
+        DRW V6, VC, 1
+        LD V7, 3
+        LD DT, V7
+count:  DRW V6, VC, 1
+        ADD V6, 1
+        LD V8, DT
+        SE V8, 0
+        JP count
+        LD VA, V6
+        LD VE, VA
+        LD VB, 14
+        CALL digit
+
+        LD VA, 0
+        LD VB, 3e
+        LD VC, 14
+        CALL digit
+
+done:   JP done
+
+; Draws the digit in VA at VB, VC
+digit:  LD F, VA
+        DRW VB, VC, 5
+        RET
+
+blank:  db 0
+scratch:
+        db 0, 7
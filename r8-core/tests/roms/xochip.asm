@@ -0,0 +1,18 @@
+; XO-CHIP bitplanes: two overlapping squares, one on each plane, so the four possible colors are
+; all on screen.  The square on the second plane is loaded with the long form of LD I.
+
+        LD V0, 8
+        LD V1, 8
+        LD I, square
+        PLANE 1
+        DRW V0, V1, 8
+        LD V0, 0c
+        LD V1, 0c
+        LDL I, square
+        PLANE 2
+        DRW V0, V1, 8
+        PLANE 3
+        SCL
+done:   JP done
+
+square: db ff, ff, ff, ff, ff, ff, ff, ff
@@ -0,0 +1,192 @@
+use r8_core::{Breakpoint, Watchpoint, Condition, Operand, parse_address};
+
+pub const HELP: &str = "\
+step [n]          Executes the next n instructions (default 1)
+next              Like step, but runs a whole subroutine when the next instruction is a CALL
+finish            Runs until the current subroutine returns
+continue [n]      Runs until a breakpoint is hit, the program exits or n instructions have run
+break ADDR        Pauses before the instruction at ADDR
+break if COND     Pauses once a condition such as 'V3 == 0x10' is true
+break on NAME     Pauses before any instruction of a kind such as Call or DrawSprite
+watch START[-END] Pauses before memory in the range is written
+info              Lists breakpoints and watchpoints
+delete            Removes all breakpoints and watchpoints
+regs              Shows the registers, timers and stack
+x[/n] [ADDR]      Dumps n bytes of memory (default 16) from ADDR (default I)
+disas [ADDR] [n]  Disassembles n instructions (default 10) from ADDR (default PC)
+set TARGET=VALUE  Sets a register (V0-VF, I, PC, DT or ST) to a value
+press KEY         Holds down a key (0-F)
+release KEY       Releases a key (0-F)
+rewind [n]        Undoes the last n instructions (default 1)
+help              Shows this list
+quit              Exits the debugger
+An empty line repeats the last command.  Numbers are decimal unless prefixed with 0x.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Step(u32),
+    Next,
+    Finish,
+    Continue(Option<u64>),
+    Break(Breakpoint),
+    Watch(Watchpoint),
+    Info,
+    Delete,
+    Registers,
+    Examine {address: Option<u16>, count: u16},
+    Disassemble {address: Option<u16>, count: u16},
+    Set {target: Operand, value: u16},
+    Press(u8),
+    Release(u8),
+    Rewind(u32),
+    Help,
+    Quit,
+}
+
+pub fn parse(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    let (name, arguments) = match line.find(char::is_whitespace) {
+        Some(index) => (&line[..index], line[index..].trim()),
+        None => (line, ""),
+    };
+
+    // `x/16` keeps its count in the command name
+    if name == "x" || name.starts_with("x/") {
+        let count = match name.find('/') {
+            Some(index) => parse_number(&name[index + 1..])?,
+            None => 16,
+        };
+
+        return Ok(Command::Examine {address: optional_address(arguments)?, count});
+    }
+
+    let words = arguments.split_whitespace().collect::<Vec<&str>>();
+    match name {
+        "step" | "s" => Ok(Command::Step(optional_count(arguments, 1)?)),
+        "next" | "n" => Ok(Command::Next),
+        "finish" | "fin" => Ok(Command::Finish),
+        "continue" | "c" => match arguments.is_empty() {
+            true => Ok(Command::Continue(None)),
+            false => Ok(Command::Continue(Some(parse_number(arguments)? as u64))),
+        },
+
+        "break" | "b" => parse_break(arguments),
+        "watch" | "w" => Watchpoint::parse(arguments).map(Command::Watch).map_err(|x| x.to_string()),
+        "info" | "i" => Ok(Command::Info),
+        "delete" | "d" => Ok(Command::Delete),
+        "regs" | "registers" | "r" => Ok(Command::Registers),
+        "disas" | "disassemble" => {
+            let address = match words.first() {
+                Some(address) => Some(parse_address(address).map_err(|x| x.to_string())?),
+                None => None,
+            };
+
+            let count = match words.get(1) {
+                Some(count) => parse_number(count)?,
+                None => 10,
+            };
+
+            Ok(Command::Disassemble {address, count})
+        }
+
+        "set" => parse_set(arguments),
+        "press" => parse_key(arguments).map(Command::Press),
+        "release" => parse_key(arguments).map(Command::Release),
+        "rewind" => Ok(Command::Rewind(optional_count(arguments, 1)?)),
+        "help" | "h" | "?" => Ok(Command::Help),
+        "quit" | "q" | "exit" => Ok(Command::Quit),
+        _ => Err(format!("Unknown command '{}', type 'help' for a list of commands", name)),
+    }
+}
+
+fn parse_break(arguments: &str) -> Result<Command, String> {
+    if let Some(condition) = arguments.strip_prefix("if ") {
+        let condition = Condition::parse(condition).map_err(|x| x.to_string())?;
+        return Ok(Command::Break(Breakpoint::Condition(condition)));
+    }
+
+    if let Some(name) = arguments.strip_prefix("on ") {
+        return Breakpoint::parse_opcode(name).map(Command::Break).map_err(|x| x.to_string());
+    }
+
+    let address = parse_address(arguments).map_err(|x| x.to_string())?;
+    Ok(Command::Break(Breakpoint::Address(address)))
+}
+
+fn parse_set(arguments: &str) -> Result<Command, String> {
+    let index = arguments.find('=').ok_or_else(|| "Expected something like 'set V3=5'".to_owned())?;
+    let target = Operand::parse(&arguments[..index]).map_err(|x| x.to_string())?;
+    if let Operand::Value(_) = target {
+        return Err(format!("Cannot set '{}', expected a register", arguments[..index].trim()));
+    }
+
+    let value = parse_address(&arguments[index + 1..]).map_err(|x| x.to_string())?;
+    Ok(Command::Set {target, value})
+}
+
+fn parse_key(arguments: &str) -> Result<u8, String> {
+    match u8::from_str_radix(arguments, 16) {
+        Ok(key) if key <= 0xf => Ok(key),
+        _ => Err(format!("'{}' is not a key, expected 0 to F", arguments)),
+    }
+}
+
+fn parse_number(text: &str) -> Result<u16, String> {
+    parse_address(text).map_err(|x| x.to_string())
+}
+
+fn optional_count(arguments: &str, default: u32) -> Result<u32, String> {
+    match arguments.is_empty() {
+        true => Ok(default),
+        false => parse_number(arguments).map(|x| x as u32),
+    }
+}
+
+fn optional_address(arguments: &str) -> Result<Option<u16>, String> {
+    match arguments.is_empty() {
+        true => Ok(None),
+        false => parse_number(arguments).map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use r8_core::Comparison;
+
+    #[test]
+    fn can_parse_memory_dump() {
+        assert_eq!(parse("x/32 0x300").unwrap(), Command::Examine {address: Some(0x300), count: 32});
+        assert_eq!(parse("x").unwrap(), Command::Examine {address: None, count: 16});
+    }
+
+    #[test]
+    fn can_parse_breakpoints() {
+        assert_eq!(parse("break 0x2a0").unwrap(), Command::Break(Breakpoint::Address(0x2a0)));
+        assert_eq!(parse("b on drawsprite").unwrap(), Command::Break(Breakpoint::Opcode("DrawSprite".to_owned())));
+        assert!(parse("b on DrawSprit").is_err(), "Expected unknown instructions to be an error");
+
+        let condition = Condition {left: Operand::Register(3), comparison: Comparison::Equal, right: Operand::Value(0x10)};
+        assert_eq!(parse("break if V3 == 0x10").unwrap(), Command::Break(Breakpoint::Condition(condition)));
+    }
+
+    #[test]
+    fn can_parse_set() {
+        assert_eq!(parse("set V3=5").unwrap(), Command::Set {target: Operand::Register(3), value: 5});
+        assert_eq!(parse("set i = 0x300").unwrap(), Command::Set {target: Operand::I, value: 0x300});
+        assert!(parse("set 5=3").is_err(), "Expected values to not be settable");
+    }
+
+    #[test]
+    fn can_parse_counts() {
+        assert_eq!(parse("step").unwrap(), Command::Step(1));
+        assert_eq!(parse("s 10").unwrap(), Command::Step(10));
+        assert_eq!(parse("rewind 0x10").unwrap(), Command::Rewind(16));
+        assert_eq!(parse("disas 0x200 4").unwrap(), Command::Disassemble {address: Some(0x200), count: 4});
+    }
+
+    #[test]
+    fn unknown_command_is_an_error() {
+        assert!(parse("frobnicate").is_err(), "Expected unknown command to be an error");
+    }
+}
@@ -0,0 +1,67 @@
+#[macro_use] extern crate clap;
+extern crate r8_core;
+
+mod commands;
+mod session;
+mod settings;
+
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use r8_core::Machine;
+use crate::commands::Command;
+use crate::session::Session;
+use crate::settings::Settings;
+
+fn main() {
+    let settings = Settings::from_cli_arguments();
+
+    let rom = fs::read(&settings.rom_file).unwrap_or_else(|error| {
+        eprintln!("Could not read {}: {}", settings.rom_file, error);
+        std::process::exit(1);
+    });
+
+    let mut machine = Machine::new(settings.platform);
+    machine.hardware.quirks = settings.quirks;
+    machine.set_seed(settings.seed);
+    if let Err(error) = machine.load_rom(&rom) {
+        eprintln!("Could not load {}: {}", settings.rom_file, error);
+        std::process::exit(1);
+    }
+
+    println!("Loaded {} ({} bytes)", settings.rom_file, rom.len());
+    println!("Platform: {:?}, Seed: {}", settings.platform, settings.seed);
+    println!("Type 'help' for a list of commands");
+
+    let mut session = Session::new(machine, settings.cycles_per_frame);
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut last_command = None;
+
+    loop {
+        print!("(r8) ");
+        stdout.lock().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break; // End of input
+        }
+
+        // Like gdb, an empty line repeats the last command
+        let command = match (line.trim().is_empty(), &last_command) {
+            (true, Some(command)) => Ok(Command::clone(command)),
+            (true, None) => continue,
+            (false, _) => commands::parse(&line),
+        };
+
+        match command {
+            Ok(Command::Quit) => break,
+            Ok(command) => {
+                session.execute(&command, &mut stdout.lock()).unwrap();
+                last_command = Some(command);
+            },
+
+            Err(error) => println!("{}", error),
+        }
+    }
+}
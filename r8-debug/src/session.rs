@@ -0,0 +1,330 @@
+use std::io;
+use std::io::Write;
+use r8_core::{Machine, Hardware, Debugger, History, Breakpoint, Operand, Instruction, get_instruction_at};
+use crate::commands::{Command, HELP};
+
+const HISTORY_LIMIT: usize = 100_000;
+
+// Stops `continue` from hanging on programs that loop forever without hitting a breakpoint
+const DEFAULT_CONTINUE_LIMIT: u64 = 10_000_000;
+
+pub struct Session {
+    machine: Machine,
+    debugger: Debugger,
+    history: History,
+    cycles_per_frame: u32,
+    cycles_executed: u64,
+}
+
+impl Session {
+    pub fn new(machine: Machine, cycles_per_frame: u32) -> Self {
+        let mut history = History::new(HISTORY_LIMIT);
+        history.reset(&machine.hardware);
+
+        Session {
+            machine,
+            debugger: Debugger::new(),
+            history,
+            cycles_per_frame: cycles_per_frame.max(1),
+            cycles_executed: 0,
+        }
+    }
+
+    pub fn execute(&mut self, command: &Command, out: &mut impl Write) -> io::Result<()> {
+        match command {
+            Command::Step(count) => {
+                for _ in 0..*count {
+                    if !self.step_once(out)? {
+                        break;
+                    }
+                }
+
+                self.print_location(out)
+            }
+
+            Command::Next => {
+                match self.machine.hardware.get_current_instruction() {
+                    Instruction::Call {..} => {
+                        let depth = self.machine.hardware.stack_pointer;
+                        self.run_until(out, DEFAULT_CONTINUE_LIMIT, |hardware| hardware.stack_pointer <= depth)
+                    }
+
+                    _ => self.execute(&Command::Step(1), out),
+                }
+            }
+
+            Command::Finish => {
+                let depth = self.machine.hardware.stack_pointer;
+                match depth {
+                    0 => writeln!(out, "Not inside a subroutine"),
+                    _ => self.run_until(out, DEFAULT_CONTINUE_LIMIT, |hardware| hardware.stack_pointer < depth),
+                }
+            }
+
+            Command::Continue(limit) => {
+                self.run_until(out, limit.unwrap_or(DEFAULT_CONTINUE_LIMIT), |_| false)
+            }
+
+            Command::Break(breakpoint) => {
+                self.debugger.add_breakpoint(breakpoint.clone());
+                writeln!(out, "Added breakpoint {}", breakpoint)
+            }
+
+            Command::Watch(watchpoint) => {
+                self.debugger.add_watchpoint(*watchpoint);
+                writeln!(out, "Watching writes to {:#06x}-{:#06x}", watchpoint.start, watchpoint.end)
+            }
+
+            Command::Info => {
+                if self.debugger.breakpoints().is_empty() && self.debugger.watchpoints().is_empty() {
+                    return writeln!(out, "No breakpoints or watchpoints");
+                }
+
+                for breakpoint in self.debugger.breakpoints() {
+                    writeln!(out, "Breakpoint {}", breakpoint)?;
+                }
+
+                for watchpoint in self.debugger.watchpoints() {
+                    writeln!(out, "Watchpoint {:#06x}-{:#06x}", watchpoint.start, watchpoint.end)?;
+                }
+
+                Ok(())
+            }
+
+            Command::Delete => {
+                self.debugger.clear();
+                writeln!(out, "Removed all breakpoints and watchpoints")
+            }
+
+            Command::Registers => writeln!(out, "{}", self.machine.hardware.describe_registers()),
+            Command::Examine {address, count} => {
+                let hardware = &self.machine.hardware;
+                let start = address.unwrap_or(hardware.i_register) as usize;
+                let end = (start + *count as usize).min(hardware.memory_size());
+                for line_start in (start..end).step_by(16) {
+                    let bytes = hardware.memory[line_start..end.min(line_start + 16)].iter()
+                        .map(|x| format!("{:02x}", x))
+                        .collect::<Vec<String>>();
+
+                    writeln!(out, "{:#06x}: {}", line_start, bytes.join(" "))?;
+                }
+
+                Ok(())
+            }
+
+            Command::Disassemble {address, count} => {
+                let hardware = &self.machine.hardware;
+                let mut current = address.unwrap_or(hardware.program_counter) as usize;
+                for _ in 0..*count {
+                    if current >= hardware.memory_size() {
+                        break;
+                    }
+
+                    let instruction = get_instruction_at(&hardware.memory, current);
+                    let marker = match current == hardware.program_counter as usize {
+                        true => "=>",
+                        false => "  ",
+                    };
+
+                    let has_breakpoint = self.debugger.breakpoints().contains(&Breakpoint::Address(current as u16));
+                    let breakpoint = if has_breakpoint { "*" } else { " " };
+                    writeln!(out, "{}{} {:#06x}: {}", marker, breakpoint, current, instruction)?;
+                    current += instruction.size() as usize;
+                }
+
+                Ok(())
+            }
+
+            Command::Set {target, value} => {
+                let hardware = &mut self.machine.hardware;
+                let byte = *value as u8;
+                match (target, *value <= 0xff) {
+                    (Operand::Register(number), true) => hardware.gen_registers[*number as usize] = byte,
+                    (Operand::DelayTimer, true) => hardware.delay_timer = byte,
+                    (Operand::SoundTimer, true) => hardware.sound_timer = byte,
+                    (Operand::I, _) => hardware.i_register = *value,
+                    (Operand::ProgramCounter, _) => hardware.program_counter = *value,
+                    _ => return writeln!(out, "{:#x} does not fit in {}", value, target),
+                }
+
+                writeln!(out, "{} = {:#x}", target, value)
+            }
+
+            Command::Press(key) => {
+                self.machine.hardware.press_key(*key);
+                writeln!(out, "Key {:X} is down", key)
+            }
+
+            Command::Release(key) => {
+                self.machine.hardware.release_key(*key);
+                writeln!(out, "Key {:X} is up", key)
+            }
+
+            Command::Rewind(count) => {
+                let mut rewound = 0;
+                while rewound < *count && self.history.rewind(&mut self.machine.hardware) {
+                    rewound += 1;
+                }
+
+                self.cycles_executed -= rewound as u64;
+
+                writeln!(out, "Rewound {} instruction(s)", rewound)?;
+                self.print_location(out)
+            }
+
+            Command::Help => writeln!(out, "{}", HELP),
+            Command::Quit => Ok(()),
+        }
+    }
+
+    // Returns false if the instruction couldn't be executed
+    fn step_once(&mut self, out: &mut impl Write) -> io::Result<bool> {
+        if self.machine.hardware.halted {
+            writeln!(out, "Program has exited")?;
+            return Ok(false);
+        }
+
+        if let Err(error) = self.machine.step() {
+            writeln!(out, "Could not execute instruction: {}", error)?;
+            return Ok(false);
+        }
+
+        self.cycles_executed += 1;
+        if self.cycles_executed.is_multiple_of(self.cycles_per_frame as u64) {
+            self.machine.hardware.simulate_timer_tick();
+        }
+
+        self.history.record(&self.machine.hardware);
+
+        Ok(true)
+    }
+
+    fn run_until(&mut self, out: &mut impl Write, limit: u64, is_done: impl Fn(&Hardware) -> bool) -> io::Result<()> {
+        // Whatever stopped execution last time would otherwise stop it again straight away
+        self.debugger.resume();
+
+        let mut executed = 0;
+        loop {
+            if executed >= limit {
+                writeln!(out, "Stopped after {} instructions", executed)?;
+                break;
+            }
+
+            if let Some(reason) = self.debugger.check(&self.machine.hardware) {
+                writeln!(out, "Paused on {}", reason)?;
+                break;
+            }
+
+            if !self.step_once(out)? {
+                break;
+            }
+
+            executed += 1;
+            if is_done(&self.machine.hardware) {
+                break;
+            }
+        }
+
+        self.print_location(out)
+    }
+
+    fn print_location(&self, out: &mut impl Write) -> io::Result<()> {
+        let hardware = &self.machine.hardware;
+        writeln!(out, "{:#06x}: {}", hardware.program_counter, hardware.get_current_instruction())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use r8_core::Platform;
+    use crate::commands::parse;
+
+    // 200: CALL 206 / 202: ADD V1, 1 / 204: JP 204 / 206: ADD V0, 1 / 208: ADD V0, 1 / 20a: RET
+    const ROM: [u8; 12] = [0x22, 0x06, 0x71, 0x01, 0x12, 0x04, 0x70, 0x01, 0x70, 0x01, 0x00, 0xee];
+
+    fn session() -> Session {
+        let mut machine = Machine::new(Platform::Chip8);
+        machine.load_rom(&ROM).unwrap();
+        Session::new(machine, 5)
+    }
+
+    fn run(session: &mut Session, line: &str) -> String {
+        let mut output = Vec::new();
+        session.execute(&parse(line).unwrap(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn step_enters_subroutine() {
+        let mut session = session();
+        let output = run(&mut session, "step");
+        assert_eq!(session.machine.hardware.program_counter, 0x206, "Incorrect program counter");
+        assert_eq!(output, "0x0206: ADD V0, 1\n", "Incorrect output");
+    }
+
+    #[test]
+    fn next_steps_over_subroutine() {
+        let mut session = session();
+        run(&mut session, "next");
+        assert_eq!(session.machine.hardware.program_counter, 0x202, "Incorrect program counter");
+        assert_eq!(session.machine.hardware.gen_registers[0], 2, "Subroutine should have run");
+    }
+
+    #[test]
+    fn finish_runs_until_return() {
+        let mut session = session();
+        run(&mut session, "step 2");
+        run(&mut session, "finish");
+        assert_eq!(session.machine.hardware.program_counter, 0x202, "Incorrect program counter");
+        assert_eq!(session.machine.hardware.stack_pointer, 0, "Incorrect stack pointer");
+    }
+
+    #[test]
+    fn continue_stops_at_breakpoint() {
+        let mut session = session();
+        run(&mut session, "break 0x204");
+        let output = run(&mut session, "continue");
+        assert_eq!(session.machine.hardware.program_counter, 0x204, "Incorrect program counter");
+        assert!(output.starts_with("Paused on breakpoint at 0x204"), "Incorrect output: {}", output);
+
+        run(&mut session, "delete");
+        let output = run(&mut session, "continue 3");
+        assert!(output.starts_with("Stopped after 3 instructions"), "Incorrect output: {}", output);
+    }
+
+    #[test]
+    fn rewind_restores_previous_state() {
+        let mut session = session();
+        run(&mut session, "step 3");
+        run(&mut session, "rewind 2");
+        assert_eq!(session.machine.hardware.program_counter, 0x206, "Incorrect program counter");
+        assert_eq!(session.machine.hardware.gen_registers[0], 0, "Incorrect V0");
+    }
+
+    #[test]
+    fn can_set_registers() {
+        let mut session = session();
+        run(&mut session, "set V3=5");
+        run(&mut session, "set I=0x300");
+        assert_eq!(session.machine.hardware.gen_registers[3], 5, "Incorrect V3");
+        assert_eq!(session.machine.hardware.i_register, 0x300, "Incorrect I");
+
+        let output = run(&mut session, "set V3=0x100");
+        assert_eq!(output, "0x100 does not fit in V3\n", "Incorrect output");
+    }
+
+    #[test]
+    fn can_dump_memory() {
+        let mut session = session();
+        let output = run(&mut session, "x/4 0x200");
+        assert_eq!(output, "0x0200: 22 06 71 01\n", "Incorrect output");
+    }
+
+    #[test]
+    fn can_disassemble() {
+        let mut session = session();
+        let output = run(&mut session, "disas 0x200 2");
+        assert_eq!(output, "=>  0x0200: CALL 206\n    0x0202: ADD V1, 1\n", "Incorrect output");
+    }
+}
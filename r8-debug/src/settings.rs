@@ -0,0 +1,26 @@
+use clap::App;
+use r8_core::{Platform, Quirks, Random};
+
+pub struct Settings {
+    pub rom_file: String,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub seed: u64,
+    pub cycles_per_frame: u32,
+}
+
+impl Settings {
+    pub fn from_cli_arguments() -> Self {
+        let yaml = load_yaml!("cli.yml");
+        let matches = App::from_yaml(yaml).get_matches();
+        let platform = matches.value_of("platform").and_then(Platform::from_name).unwrap_or(Platform::Chip8);
+
+        Self {
+            rom_file: matches.value_of("INPUT").unwrap().to_owned(),
+            platform,
+            quirks: matches.value_of("quirks").and_then(Quirks::preset).unwrap_or_else(|| platform.default_quirks()),
+            seed: value_t!(matches, "seed", u64).unwrap_or_else(|_| Random::generate_seed()),
+            cycles_per_frame: value_t!(matches, "cycles-per-frame", u32).unwrap_or(5),
+        }
+    }
+}
@@ -0,0 +1,199 @@
+// Runs whole programs for a fixed number of frames and compares what ends up on screen with a
+// stored image, so interpreter changes that break real programs are caught even when every
+// single instruction test still passes.
+//
+// Expected images are plain text with one character per pixel, so they can be read and diffed
+// like any other file.  Running the tests with `R8_BLESS=1` writes the current output as the
+// expected image, for adding a new test or after an intended change in behaviour.
+
+extern crate r8_core;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use r8_core::{Hardware, Machine, Platform, Quirks, ExecutionError, assemble};
+
+pub const BLESS_VARIABLE: &str = "R8_BLESS";
+
+// Indexed by which bitplanes a pixel is on
+const PIXEL_CHARACTERS: [char; 4] = ['.', '#', '+', '@'];
+
+pub struct GoldenTest {
+    pub name: String, // Also the name of the expected image file
+    pub rom: Vec<u8>,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub frames: u32,
+    pub cycles_per_frame: u32,
+    pub seed: u64,
+    pub key_changes: Vec<(u32, u16)>, // Keys held down from the start of a frame, with bit n for key n
+}
+
+impl GoldenTest {
+    pub fn new(name: &str, rom: Vec<u8>) -> Self {
+        GoldenTest {
+            name: name.to_owned(),
+            rom,
+            platform: Platform::Chip8,
+            quirks: Platform::Chip8.default_quirks(),
+            frames: 60,
+            cycles_per_frame: 10,
+            seed: 0,
+            key_changes: Vec::new(),
+        }
+    }
+
+    pub fn from_assembly(name: &str, source: &str) -> Self {
+        let rom = assemble(source).unwrap_or_else(|error| panic!("Could not assemble {}: {}", name, error));
+        GoldenTest::new(name, rom)
+    }
+
+    // Also switches to the platform's default quirks
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.quirks = platform.default_quirks();
+    }
+
+    pub fn run(&self) -> Result<Hardware, ExecutionError> {
+        let mut machine = Machine::new(self.platform);
+        machine.hardware.quirks = self.quirks;
+        machine.set_seed(self.seed);
+        machine.load_rom(&self.rom).unwrap_or_else(|error| panic!("Could not load {}: {}", self.name, error));
+
+        for frame in 0..self.frames {
+            if let Some((_, keys)) = self.key_changes.iter().rev().find(|(change, _)| *change == frame) {
+                machine.set_keys(*keys);
+            }
+
+            machine.run_frame(self.cycles_per_frame)?;
+        }
+
+        Ok(machine.hardware)
+    }
+
+    // Panics with a readable diff if the display doesn't match the expected image in the directory
+    pub fn check(&self, directory: &Path) {
+        self.compare_with_image(directory, true);
+    }
+
+    // Like `check`, but never blesses, for expected images drawn by hand from a reference
+    // interpreter or the ROM author's screenshots rather than taken from this one
+    pub fn verify(&self, directory: &Path) {
+        self.compare_with_image(directory, false);
+    }
+
+    fn compare_with_image(&self, directory: &Path, can_bless: bool) {
+        let hardware = self.run().unwrap_or_else(|error| panic!("{} failed to run: {}", self.name, error));
+        let actual = render(&hardware);
+        let file = self.expected_image_file(directory);
+
+        if can_bless && env::var_os(BLESS_VARIABLE).is_some() {
+            fs::write(&file, &actual).unwrap_or_else(|error| panic!("Could not write {}: {}", file.display(), error));
+            return;
+        }
+
+        let expected = fs::read_to_string(&file).unwrap_or_else(|error| match can_bless {
+            true => panic!("Could not read the expected image for {} from {} ({}), run with {}=1 to create it from the current output:\n{}",
+                           self.name, file.display(), error, BLESS_VARIABLE, actual),
+            false => panic!("Could not read the expected image for {} from {} ({}), it has to be drawn by hand, the current output is:\n{}",
+                            self.name, file.display(), error, actual),
+        });
+
+        if let Err(difference) = compare(&expected, &actual) {
+            match can_bless {
+                true => panic!("{} does not match {}\n{}\nIf the new output is correct, run with {}=1 to update it",
+                               self.name, file.display(), difference, BLESS_VARIABLE),
+                false => panic!("{} does not match {}\n{}", self.name, file.display(), difference),
+            }
+        }
+    }
+
+    fn expected_image_file(&self, directory: &Path) -> PathBuf {
+        directory.join(format!("{}.txt", self.name))
+    }
+}
+
+// Draws the visible part of the display as text, with a line giving its size first
+pub fn render(hardware: &Hardware) -> String {
+    let mut text = format!("{}x{}\n", hardware.display_width(), hardware.display_height());
+    for y in 0..hardware.display_height() {
+        for x in 0..hardware.display_width() {
+            text.push(PIXEL_CHARACTERS[hardware.pixel(x, y) as usize]);
+        }
+
+        text.push('\n');
+    }
+
+    text
+}
+
+// Lists each row that differs, with the expected row above the actual one and the differing
+// pixels marked underneath
+pub fn compare(expected: &str, actual: &str) -> Result<(), String> {
+    let expected_lines = expected.lines().collect::<Vec<&str>>();
+    let actual_lines = actual.lines().collect::<Vec<&str>>();
+
+    if expected_lines.first() != actual_lines.first() {
+        return Err(format!("Expected a {} display but it was {}, actual image:\n{}",
+                           expected_lines.first().unwrap_or(&"empty"), actual_lines.first().unwrap_or(&"empty"), actual));
+    }
+
+    let mut report = Vec::new();
+    let mut differing_pixels = 0;
+    let rows = expected_lines.len().max(actual_lines.len());
+    for row in 1..rows {
+        let expected_row = expected_lines.get(row).cloned().unwrap_or("");
+        let actual_row = actual_lines.get(row).cloned().unwrap_or("");
+        if expected_row == actual_row {
+            continue;
+        }
+
+        let width = expected_row.chars().count().max(actual_row.chars().count());
+        let markers = (0..width)
+            .map(|column| match expected_row.chars().nth(column) == actual_row.chars().nth(column) {
+                true => ' ',
+                false => '^',
+            })
+            .collect::<String>();
+
+        differing_pixels += markers.chars().filter(|x| *x == '^').count();
+        report.push(format!("row {:>3} expected: {}", row - 1, expected_row));
+        report.push(format!("          actual: {}", actual_row));
+        report.push(format!("                  {}", markers));
+    }
+
+    match report.is_empty() {
+        true => Ok(()),
+        false => Err(format!("{} pixel(s) differ:\n{}", differing_pixels, report.join("\n"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_shows_each_plane() {
+        let mut hardware = Hardware::new();
+        hardware.framebuffer[0][0] = 0b1010_0000;
+        hardware.second_framebuffer[0][0] = 0b0110_0000;
+
+        let image = render(&hardware);
+        let lines = image.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "64x32", "Incorrect size line");
+        assert_eq!(&lines[1][0..4], "#+@.", "Incorrect first row");
+        assert_eq!(lines.len(), 33, "Incorrect number of lines");
+    }
+
+    #[test]
+    fn compare_marks_differing_pixels() {
+        let error = compare("4x2\n....\n.##.\n", "4x2\n....\n.#.#\n").unwrap_err();
+        let expected = "2 pixel(s) differ:\nrow   1 expected: .##.\n          actual: .#.#\n                    ^^";
+        assert_eq!(error, expected, "Incorrect report");
+    }
+
+    #[test]
+    fn compare_reports_different_sizes() {
+        assert!(compare("4x2\n", "8x4\n").unwrap_err().starts_with("Expected a 4x2 display but it was 8x4"), "Incorrect report");
+    }
+}
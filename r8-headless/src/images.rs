@@ -0,0 +1,161 @@
+use std::io;
+use std::io::Write;
+use r8_core::Hardware;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+// Deflate's stored blocks can't be longer than this
+const MAX_STORED_BLOCK_SIZE: usize = 0xffff;
+
+// Plain text PBMs can be diffed, but shouldn't have lines longer than 70 characters
+const PBM_LINE_LENGTH: usize = 64;
+
+// Writes the display at its native resolution, coloring each pixel by which bitplanes it is on.
+// The image data is stored uncompressed, which keeps the encoder simple and is still small at this size.
+pub fn write_png(writer: &mut impl Write, hardware: &Hardware, palette: &[[u8; 3]; 4]) -> io::Result<()> {
+    let width = hardware.display_width();
+    let height = hardware.display_height();
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8 bit RGB, no interlacing
+
+    let mut pixels = Vec::with_capacity(height * (width * 3 + 1));
+    for y in 0..height {
+        pixels.push(0); // No filter
+        for x in 0..width {
+            pixels.extend_from_slice(&palette[hardware.pixel(x, y) as usize]);
+        }
+    }
+
+    writer.write_all(&PNG_SIGNATURE)?;
+    write_png_chunk(writer, b"IHDR", &header)?;
+    write_png_chunk(writer, b"IDAT", &zlib_stored(&pixels))?;
+    write_png_chunk(writer, b"IEND", &[])
+}
+
+// Writes the display as a plain text black and white image, where any lit pixel is black
+pub fn write_pbm(writer: &mut impl Write, hardware: &Hardware) -> io::Result<()> {
+    let width = hardware.display_width();
+    let height = hardware.display_height();
+    writeln!(writer, "P1")?;
+    writeln!(writer, "{} {}", width, height)?;
+
+    for y in 0..height {
+        let row = (0..width)
+            .map(|x| if hardware.pixel(x, y) > 0 { '1' } else { '0' })
+            .collect::<Vec<char>>();
+
+        for line in row.chunks(PBM_LINE_LENGTH) {
+            writeln!(writer, "{}", line.iter().collect::<String>())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_png_chunk(writer: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut checksum = Crc32::new();
+    checksum.update(kind);
+    checksum.update(data);
+
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&checksum.finish().to_be_bytes())
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut output = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK_SIZE).peekable();
+    if blocks.peek().is_none() {
+        output.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let is_last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        output.push(if is_last { 1 } else { 0 });
+        output.extend_from_slice(&length.to_le_bytes());
+        output.extend_from_slice(&(!length).to_le_bytes());
+        output.extend_from_slice(block);
+    }
+
+    output.extend_from_slice(&adler32(data).to_be_bytes());
+    output
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
+
+struct Crc32 {
+    value: u32,
+}
+
+impl Crc32 {
+    fn new() -> Self {
+        Crc32 {value: 0xffff_ffff}
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.value ^= *byte as u32;
+            for _ in 0..8 {
+                let mask = (self.value & 1).wrapping_neg();
+                self.value = (self.value >> 1) ^ (0xedb8_8320 & mask);
+            }
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        !self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use r8_core::DEFAULT_PALETTE;
+
+    #[test]
+    fn checksums_match_known_values() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.finish(), 0xcbf4_3926, "Incorrect crc32");
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398, "Incorrect adler32");
+    }
+
+    #[test]
+    fn png_has_header_and_end_chunks() {
+        let mut bytes = Vec::new();
+        write_png(&mut bytes, &Hardware::new(), &DEFAULT_PALETTE).unwrap();
+
+        assert_eq!(&bytes[0..8], &PNG_SIGNATURE, "Incorrect signature");
+        assert_eq!(&bytes[12..16], b"IHDR", "Expected header chunk first");
+        assert_eq!(&bytes[16..24], &[0, 0, 0, 64, 0, 0, 0, 32], "Incorrect dimensions");
+        assert_eq!(&bytes[bytes.len() - 12..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82], "Incorrect end chunk");
+    }
+
+    #[test]
+    fn pbm_marks_lit_pixels() {
+        let mut hardware = Hardware::new();
+        hardware.framebuffer[1][0] = 0b1010_0000;
+
+        let mut bytes = Vec::new();
+        write_pbm(&mut bytes, &hardware).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        let lines = text.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines[0..2], ["P1", "64 32"], "Incorrect header");
+        assert_eq!(lines.len(), 2 + 32, "Incorrect number of rows");
+        assert_eq!(lines[3], format!("101{}", "0".repeat(61)), "Incorrect second row");
+    }
+}
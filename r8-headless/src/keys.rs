@@ -0,0 +1,57 @@
+// Which keys are held down from each frame onwards, such as `0:5,30:,45:5a`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct KeyScript {
+    changes: Vec<(u64, u16)>, // Frame and key bits, ordered by frame
+}
+
+impl KeyScript {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut changes = Vec::new();
+        for change in text.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            let index = change.find(':').ok_or_else(|| format!("Expected FRAME:KEYS, found '{}'", change))?;
+            let frame = change[..index].parse::<u64>().map_err(|_| format!("'{}' is not a frame number", &change[..index]))?;
+
+            let mut keys = 0_u16;
+            for key in change[index + 1..].chars() {
+                let key = key.to_digit(16).ok_or_else(|| format!("'{}' is not a key, expected 0 to F", key))?;
+                keys |= 1 << key;
+            }
+
+            changes.push((frame, keys));
+        }
+
+        Ok(KeyScript::from_changes(changes))
+    }
+
+    pub fn from_changes(mut changes: Vec<(u64, u16)>) -> Self {
+        changes.sort_by_key(|(frame, _)| *frame);
+        KeyScript {changes}
+    }
+
+    // The keys to hold down if they change at the start of the frame
+    pub fn keys_at(&self, frame: u64) -> Option<u16> {
+        self.changes.iter().rev()
+            .find(|(change_frame, _)| *change_frame == frame)
+            .map(|(_, keys)| *keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_script() {
+        let script = KeyScript::parse("45:5a, 0:5,30:").unwrap();
+        assert_eq!(script.keys_at(0), Some(0b10_0000), "Incorrect keys at frame 0");
+        assert_eq!(script.keys_at(30), Some(0), "Incorrect keys at frame 30");
+        assert_eq!(script.keys_at(45), Some(0b100_0010_0000), "Incorrect keys at frame 45");
+        assert_eq!(script.keys_at(10), None, "Keys should not change at frame 10");
+    }
+
+    #[test]
+    fn invalid_key_is_an_error() {
+        assert!(KeyScript::parse("0:g").is_err(), "Expected invalid key to be an error");
+        assert!(KeyScript::parse("5").is_err(), "Expected missing keys to be an error");
+    }
+}
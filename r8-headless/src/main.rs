@@ -0,0 +1,155 @@
+#[macro_use] extern crate clap;
+extern crate r8_core;
+
+mod images;
+mod keys;
+mod settings;
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use r8_core::{Machine, Movie, ExecutionError, Scheduler, ScheduledEvent, DEFAULT_PALETTE};
+use crate::settings::{Settings, Duration};
+
+fn main() {
+    let settings = Settings::from_cli_arguments();
+
+    let rom = fs::read(&settings.rom_file).unwrap_or_else(|error| exit_with_error(format!("Could not read {}: {}", settings.rom_file, error)));
+    let mut machine = match settings.movie {
+        Some(ref movie) => movie.create_machine(&rom).unwrap_or_else(|error| exit_with_error(format!("Could not play movie: {}", error))),
+        None => {
+            let mut machine = Machine::new(settings.platform);
+            machine.hardware.quirks = settings.quirks;
+            machine.timing = settings.timing;
+            machine.set_seed(settings.seed);
+            if let Err(error) = machine.load_rom(&rom) {
+                exit_with_error(format!("Could not load {}: {}", settings.rom_file, error));
+            }
+
+            machine
+        }
+    };
+
+    let palette = screenshot_palette(&settings, &rom);
+    let result = match settings.movie {
+        Some(ref movie) => play_movie(&mut machine, movie, &settings),
+        None => run(&mut machine, &settings),
+    };
+
+    if let Some(ref file_name) = settings.screenshot_file {
+        if let Err(error) = save_screenshot(&machine, file_name, &palette) {
+            exit_with_error(format!("Could not save screenshot to {}: {}", file_name, error));
+        }
+    }
+
+    let registers = machine.hardware.describe_registers();
+    match settings.registers_file {
+        Some(ref file_name) => fs::write(file_name, format!("{}\n", registers))
+            .unwrap_or_else(|error| exit_with_error(format!("Could not save registers to {}: {}", file_name, error))),
+
+        None => println!("{}", registers),
+    }
+
+    // The screenshot and registers are still saved on failure, since they help show what went wrong
+    if let Err(error) = result {
+        exit_with_error(format!("Error at {:#05x}: {}", machine.hardware.program_counter, error));
+    }
+}
+
+// Runs whole frames, pressing scripted keys at the start of each one, until the requested number
+// of frames or instructions have run or the program exits
+fn run(machine: &mut Machine, settings: &Settings) -> Result<(), ExecutionError> {
+    let mut scheduler = Scheduler::new(settings.cycles_per_frame);
+    let mut frame = 0;
+    let mut instructions_run = 0;
+    while !machine.hardware.halted {
+        if let Duration::Frames(frames) = settings.duration {
+            if frame == frames {
+                break;
+            }
+        }
+
+        if let Some(keys) = settings.keys.keys_at(frame) {
+            machine.set_keys(keys);
+        }
+
+        scheduler.add_tick();
+        while let Some(event) = scheduler.next_event() {
+            match event {
+                ScheduledEvent::Cycle => {
+                    if let Duration::Cycles(cycles) = settings.duration {
+                        if instructions_run == cycles {
+                            return Ok(());
+                        }
+                    }
+
+                    let cycles = machine.next_instruction_cycles(&scheduler);
+                    machine.step()?;
+                    scheduler.complete_cycles(cycles);
+                    instructions_run += 1;
+                }
+
+                ScheduledEvent::TimerTick => {
+                    machine.hardware.simulate_timer_tick();
+                    scheduler.complete_event();
+                }
+            }
+        }
+
+        frame += 1;
+    }
+
+    Ok(())
+}
+
+// Plays the movie's frames the same way the runner played them back
+fn play_movie(machine: &mut Machine, movie: &Movie, settings: &Settings) -> Result<(), ExecutionError> {
+    let frames = match settings.duration {
+        Duration::Frames(frames) => frames,
+        Duration::Cycles(_) => movie.length,
+    };
+
+    let mut scheduler = movie.create_scheduler();
+    for frame in 0..frames {
+        if machine.hardware.halted {
+            break;
+        }
+
+        movie.play_frame(machine, &mut scheduler, frame)?;
+    }
+
+    Ok(())
+}
+
+// Colors not given keep their default, so a black and white palette only needs two
+fn screenshot_palette(settings: &Settings, rom: &[u8]) -> [[u8; 3]; 4] {
+    let mut colors = settings.palette.clone();
+    if let (true, Some(file_name)) = (colors.is_empty(), &settings.rom_database_file) {
+        let database = fs::read_to_string(file_name).unwrap_or_else(|error| exit_with_error(format!("Could not read {}: {}", file_name, error)));
+        match r8_core::find_rom_info(&database, rom) {
+            Ok(info) => colors = info.map(|x| x.palette).unwrap_or_default(),
+            Err(error) => exit_with_error(format!("{}: {}", file_name, error)),
+        }
+    }
+
+    let mut palette = DEFAULT_PALETTE;
+    for (color, new_color) in palette.iter_mut().zip(colors) {
+        *color = new_color;
+    }
+
+    palette
+}
+
+fn save_screenshot(machine: &Machine, file_name: &str, palette: &[[u8; 3]; 4]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(file_name)?);
+    match file_name.to_lowercase().ends_with(".pbm") {
+        true => images::write_pbm(&mut file, &machine.hardware),
+        false => images::write_png(&mut file, &machine.hardware, palette),
+    }
+}
+
+fn exit_with_error(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
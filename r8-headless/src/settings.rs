@@ -0,0 +1,86 @@
+use clap::App;
+use std::fs::File;
+use std::io::BufReader;
+use r8_core::{Platform, Quirks, Movie, TimingModel, VIP_CYCLES_PER_TICK};
+use crate::keys::KeyScript;
+
+// How long to run the ROM for
+pub enum Duration {
+    Frames(u64),
+    Cycles(u64),
+}
+
+// A movie brings its own platform, quirks, seed, speed, timing and keys, so those settings are
+// only used without one
+pub struct Settings {
+    pub rom_file: String,
+    pub movie: Option<Movie>,
+    pub duration: Duration,
+    pub cycles_per_frame: u32, // In the timing model's cycles
+    pub timing: TimingModel,
+    pub keys: KeyScript,
+    pub screenshot_file: Option<String>,
+    pub palette: Vec<[u8; 3]>, // Replaces the start of the default palette
+    pub rom_database_file: Option<String>,
+    pub registers_file: Option<String>,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub seed: u64,
+}
+
+impl Settings {
+    pub fn from_cli_arguments() -> Self {
+        let yaml = load_yaml!("cli.yml");
+        let matches = App::from_yaml(yaml).get_matches();
+        let platform = matches.value_of("platform").and_then(Platform::from_name).unwrap_or(Platform::Chip8);
+
+        let keys = KeyScript::parse(matches.value_of("keys").unwrap_or("")).unwrap_or_else(|error| {
+            eprintln!("error: {}", error);
+            std::process::exit(1);
+        });
+
+        let palette = matches.values_of("palette").into_iter().flatten()
+            .map(|text| r8_core::parse_color(text).unwrap_or_else(|| {
+                eprintln!("error: '{}' is not a color such as #ffcc00", text);
+                std::process::exit(1);
+            }))
+            .collect();
+
+        let movie = matches.value_of("movie").map(|file_name| {
+            File::open(file_name).map_err(|error| error.into())
+                .and_then(|file| Movie::load(&mut BufReader::new(file)))
+                .unwrap_or_else(|error| {
+                    eprintln!("Could not load movie {}: {}", file_name, error);
+                    std::process::exit(1);
+                })
+        });
+
+        let default_frames = movie.as_ref().map_or(60, |x| x.length);
+        let timing = match matches.value_of("timing") {
+            Some("vip") => TimingModel::CosmacVip,
+            _ => TimingModel::Fixed,
+        };
+
+        Self {
+            rom_file: matches.value_of("INPUT").unwrap().to_owned(),
+            movie,
+            duration: match value_t!(matches, "cycles", u64) {
+                Ok(cycles) => Duration::Cycles(cycles),
+                Err(_) => Duration::Frames(value_t!(matches, "frames", u64).unwrap_or(default_frames)),
+            },
+            cycles_per_frame: match timing {
+                TimingModel::CosmacVip => VIP_CYCLES_PER_TICK,
+                TimingModel::Fixed => value_t!(matches, "cycles-per-frame", u32).unwrap_or(5).max(1),
+            },
+            timing,
+            keys,
+            screenshot_file: matches.value_of("screenshot").map(|x| x.to_owned()),
+            palette,
+            rom_database_file: matches.value_of("rom-database").map(|x| x.to_owned()),
+            registers_file: matches.value_of("registers").map(|x| x.to_owned()),
+            platform,
+            quirks: matches.value_of("quirks").and_then(Quirks::preset).unwrap_or_else(|| platform.default_quirks()),
+            seed: value_t!(matches, "seed", u64).unwrap_or(0),
+        }
+    }
+}
@@ -0,0 +1,14 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use r8_core::ConfigEntry;
+
+// Returns None if the file doesn't exist, since every config file is optional
+pub fn read_file(file_name: &str) -> Result<Option<Vec<ConfigEntry>>, String> {
+    if !Path::new(file_name).exists() {
+        return Ok(None);
+    }
+
+    let text = fs::read_to_string(file_name).map_err(|error: io::Error| format!("{}: {}", file_name, error))?;
+    r8_core::parse_config(&text).map(Some).map_err(|error| format!("{}: {}", file_name, error))
+}
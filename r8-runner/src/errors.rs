@@ -0,0 +1,99 @@
+use std::collections::VecDeque;
+use std::fs;
+use r8_core::{Hardware, Instruction, ExecutionError};
+use crate::settings::Settings;
+
+// Number of recently executed instructions included in crash reports
+const INSTRUCTION_LOG_SIZE: usize = 20;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum ErrorPolicy {
+    Pause, // Stop in the debugger with the error shown on screen
+    Skip, // Move past the instruction that failed and keep going
+    Exit, // Write a crash report and exit with a non-zero code
+}
+
+// What the main loop should do after an instruction fails
+pub enum ErrorAction {
+    Pause(String),
+    Continue,
+    Exit, // The crash report has been written, so only recordings need finishing before exiting
+}
+
+// The most recently executed instructions and where they were, oldest first
+pub struct InstructionLog {
+    entries: VecDeque<(u16, Instruction)>,
+}
+
+impl InstructionLog {
+    pub fn new() -> Self {
+        InstructionLog {
+            entries: VecDeque::with_capacity(INSTRUCTION_LOG_SIZE),
+        }
+    }
+
+    // Should be called just before the instruction at the program counter is executed
+    pub fn record(&mut self, hardware: &Hardware) {
+        if self.entries.len() >= INSTRUCTION_LOG_SIZE {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back((hardware.program_counter, hardware.get_current_instruction()));
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+pub fn handle_execution_error(error: ExecutionError, hardware: &mut Hardware, log: &InstructionLog, settings: &Settings) -> ErrorAction {
+    let address = hardware.program_counter;
+    let message = format!("Error at {:#05x}: {}", address, error);
+    println!("{}", message);
+
+    match (settings.error_policy, &error) {
+        // There is nothing after the end of memory to skip to
+        (ErrorPolicy::Skip, ExecutionError::ProgramCounterOutOfBounds {..}) => ErrorAction::Pause(message),
+        (ErrorPolicy::Skip, _) => {
+            let size = hardware.get_current_instruction().size();
+            hardware.program_counter = address.wrapping_add(size);
+            ErrorAction::Continue
+        }
+
+        (ErrorPolicy::Pause, _) => ErrorAction::Pause(message),
+        (ErrorPolicy::Exit, _) => {
+            let report = crash_report(&error, hardware, log, settings);
+            eprintln!("{}", report);
+
+            let file_name = format!("{}.crash.txt", settings.rom_file);
+            match fs::write(&file_name, &report) {
+                Ok(_) => eprintln!("Crash report written to: {}", file_name),
+                Err(write_error) => eprintln!("Could not write crash report to {}: {}", file_name, write_error),
+            }
+
+            ErrorAction::Exit
+        }
+    }
+}
+
+fn crash_report(error: &ExecutionError, hardware: &Hardware, log: &InstructionLog, settings: &Settings) -> String {
+    let mut lines = vec![
+        "r8 crash report".to_owned(),
+        format!("ROM: {}", settings.rom_file),
+        format!("Platform: {:?}", settings.platform),
+        format!("Error: {}", error),
+        format!("PC: {:#06x}", hardware.program_counter),
+        String::new(),
+        "Last instructions:".to_owned(),
+    ];
+
+    for (index, (address, instruction)) in log.entries.iter().enumerate() {
+        let marker = if index + 1 == log.entries.len() { ">" } else { " " };
+        lines.push(format!("{} {:#06x}: {}", marker, address, instruction));
+    }
+
+    lines.push(String::new());
+    lines.push("Registers:".to_owned());
+    lines.push(hardware.describe_registers());
+    lines.join("\n")
+}
@@ -1 +1,72 @@
-use sfml::window::{K}
\ No newline at end of file
+use sfml::window::Key;
+use sfml::window::joystick::Axis;
+use crate::config;
+use crate::settings::Settings;
+use r8_core::KeyMap;
+
+pub const DEFAULT_KEY_MAP_FILE: &str = "keymap.toml";
+
+// The original QWERTY layout, with the keypad's four columns on 1234, QWER, ASDF and ZXCV
+const DEFAULT_BINDINGS: [(u8, Key); 16] = [
+    (0x1, Key::Num1), (0x2, Key::Num2), (0x3, Key::Num3), (0xc, Key::Num4),
+    (0x4, Key::Q), (0x5, Key::W), (0x6, Key::E), (0xd, Key::R),
+    (0x7, Key::A), (0x8, Key::S), (0x9, Key::D), (0xe, Key::F),
+    (0xa, Key::Z), (0x0, Key::X), (0xb, Key::C), (0xf, Key::V),
+];
+
+// Keys that can be bound, by the names used in key map files.  Space, Return, BackSpace, Escape,
+// F5 and F9 control the runner so they're left out, and binding B replaces the breakpoint toggle.
+const KEY_NAMES: [(&str, Key); 87] = [
+    ("A", Key::A), ("B", Key::B), ("C", Key::C), ("D", Key::D), ("E", Key::E), ("F", Key::F),
+    ("G", Key::G), ("H", Key::H), ("I", Key::I), ("J", Key::J), ("K", Key::K), ("L", Key::L),
+    ("M", Key::M), ("N", Key::N), ("O", Key::O), ("P", Key::P), ("Q", Key::Q), ("R", Key::R),
+    ("S", Key::S), ("T", Key::T), ("U", Key::U), ("V", Key::V), ("W", Key::W), ("X", Key::X),
+    ("Y", Key::Y), ("Z", Key::Z),
+    ("Num0", Key::Num0), ("Num1", Key::Num1), ("Num2", Key::Num2), ("Num3", Key::Num3), ("Num4", Key::Num4),
+    ("Num5", Key::Num5), ("Num6", Key::Num6), ("Num7", Key::Num7), ("Num8", Key::Num8), ("Num9", Key::Num9),
+    ("Numpad0", Key::Numpad0), ("Numpad1", Key::Numpad1), ("Numpad2", Key::Numpad2), ("Numpad3", Key::Numpad3),
+    ("Numpad4", Key::Numpad4), ("Numpad5", Key::Numpad5), ("Numpad6", Key::Numpad6), ("Numpad7", Key::Numpad7),
+    ("Numpad8", Key::Numpad8), ("Numpad9", Key::Numpad9),
+    ("Add", Key::Add), ("Subtract", Key::Subtract), ("Multiply", Key::Multiply), ("Divide", Key::Divide),
+    ("Left", Key::Left), ("Right", Key::Right), ("Up", Key::Up), ("Down", Key::Down),
+    ("LControl", Key::LControl), ("LShift", Key::LShift), ("LAlt", Key::LAlt),
+    ("RControl", Key::RControl), ("RShift", Key::RShift), ("RAlt", Key::RAlt),
+    ("LBracket", Key::LBracket), ("RBracket", Key::RBracket), ("SemiColon", Key::SemiColon),
+    ("Comma", Key::Comma), ("Period", Key::Period), ("Quote", Key::Quote), ("Slash", Key::Slash),
+    ("BackSlash", Key::BackSlash), ("Tilde", Key::Tilde), ("Equal", Key::Equal), ("Dash", Key::Dash),
+    ("Tab", Key::Tab), ("PageUp", Key::PageUp), ("PageDown", Key::PageDown), ("End", Key::End),
+    ("Home", Key::Home), ("Insert", Key::Insert), ("Delete", Key::Delete),
+    ("F1", Key::F1), ("F2", Key::F2), ("F3", Key::F3), ("F4", Key::F4), ("F6", Key::F6),
+    ("F7", Key::F7), ("F8", Key::F8), ("F10", Key::F10), ("F11", Key::F11),
+];
+
+const AXIS_NAMES: [(&str, Axis); 8] = [
+    ("X", Axis::X), ("Y", Axis::Y), ("Z", Axis::Z), ("R", Axis::R),
+    ("U", Axis::U), ("V", Axis::V), ("PovX", Axis::PovX), ("PovY", Axis::PovY),
+];
+
+// Starts from the defaults plus any bindings from the ROM database, then applies the [keys]
+// sections of the config files, the global key map and the ROM's own key map next to it, where
+// each CHIP-8 key listed replaces all of that key's earlier bindings
+pub fn create_key_map(settings: &Settings) -> Result<KeyMap<Key, Axis>, String> {
+    let mut key_map = KeyMap::new(&KEY_NAMES, &AXIS_NAMES, &DEFAULT_BINDINGS);
+    key_map.add_bindings("ROM database", &settings.rom_key_bindings).map_err(|error| error.to_string())?;
+    for (file_name, entries) in &settings.config_files {
+        key_map.apply(entries).map_err(|error| format!("{}: {}", file_name, error))?;
+    }
+
+    let global_file = settings.key_map_file.clone().unwrap_or_else(|| DEFAULT_KEY_MAP_FILE.to_owned());
+    for file_name in &[global_file, rom_key_map_file(settings)] {
+        if let Some(entries) = config::read_file(file_name)? {
+            key_map.apply(&entries).map_err(|error| format!("{}: {}", file_name, error))?;
+            println!("Loaded key map: {}", file_name);
+        }
+    }
+
+    Ok(key_map)
+}
+
+// Per ROM key maps are kept next to the ROM they're for
+fn rom_key_map_file(settings: &Settings) -> String {
+    format!("{}.keymap.toml", settings.rom_file)
+}
@@ -5,158 +5,347 @@ extern crate r8_core;
 mod rendering;
 mod settings;
 mod roms;
+mod sound;
+mod states;
+mod errors;
+mod traces;
+mod movies;
+mod config;
+mod input;
+mod romdb;
 
 use std::time::{Duration, Instant};
 use sfml::window::{Event, Style, Key};
-use sfml::graphics::{RenderWindow, Font};
+use sfml::window::joystick::Axis;
+use sfml::graphics::{RenderWindow, RenderTarget, Font, View, FloatRect};
+use sfml::audio::Sound;
 
-use r8_core::Hardware;
+use r8_core::{Hardware, Machine, Debugger, History, ExecutionError, Scheduler, ScheduledEvent, KeyMap, Input};
 use crate::settings::Settings;
 use crate::rendering::RenderState;
+use crate::errors::{ErrorAction, InstructionLog};
+use crate::traces::FileTracer;
+use crate::sound::WavRecorder;
+use crate::movies::MovieSession;
 
 fn main() {
     println!("{}", std::env::current_dir().unwrap().display());
 
-    let settings = Settings::from_cli_arguments();
+    let mut settings = Settings::from_cli_arguments();
+    let mut movie = MovieSession::from_settings(&mut settings).unwrap_or_else(|error| {
+        eprintln!("Could not start movie: {}", error);
+        std::process::exit(1);
+    });
 
-    let mut hardware = Hardware::new();
-    roms::load_from_file(&mut hardware, &settings).unwrap();
+    let mut key_map = input::create_key_map(&settings).unwrap_or_else(|error| {
+        eprintln!("Could not load key map: {}", error);
+        std::process::exit(1);
+    });
+
+    let mut machine = create_machine(&settings);
 
     let font = Font::from_file("cour.ttf").unwrap();
-    let mut window = RenderWindow::new((800, 600), "R8 Runner - Chip 8", Style::CLOSE, &Default::default());
-    window.set_framerate_limit(60);
+    // The layout is drawn at its usual size and scaled up to fill the window
+    let window_size = (
+        (rendering::WINDOW_WIDTH as f32 * settings.window_scale) as u32,
+        (rendering::WINDOW_HEIGHT as f32 * settings.window_scale) as u32,
+    );
+
+    let mut window = RenderWindow::new(window_size, "R8 Runner - Chip 8", Style::CLOSE, &Default::default());
+    window.set_view(&View::from_rect(&FloatRect::new(0.0, 0.0, rendering::WINDOW_WIDTH as f32, rendering::WINDOW_HEIGHT as f32)));
+    window.set_framerate_limit(60); // Only limits rendering, the scheduler keeps time for the machine
 
     println!("Starting paused: {}", settings.start_paused);
-    println!("Instructions Per Second: {}", settings.instructions_per_second);
+    println!("Cycles Per Timer Tick: {}", settings.cycles_per_tick);
+    println!("Timing: {:?}", settings.timing);
+    println!("Platform: {:?}", settings.platform);
+    println!("Quirks: {:?}", settings.quirks);
+    println!("Seed: {}", settings.seed);
+
+    let tone_buffer = sound::create_tone_buffer(&settings);
+    let mut buzzer = Sound::with_buffer(&tone_buffer);
+    buzzer.set_looping(true);
 
-    let instruction_micro = 1_000_000 as f32 / settings.instructions_per_second as f32;
-    let mut last_instruction_at = Instant::now();
+    let mut wav_recorder = WavRecorder::from_settings(&settings).unwrap();
+    let mut tracer = traces::create_tracer(&settings).unwrap();
+
+    let mut scheduler = Scheduler::new(settings.cycles_per_tick);
+    let mut last_update_at = Instant::now();
 
     let mut is_paused = settings.start_paused;
-    let mut render_state = RenderState::new();
+    let mut render_state = RenderState::new(&settings.palette, settings.debugger_layout);
     let mut last_step_at = Instant::now();
     let time_between_held_steps = Duration::from_millis(10);
 
-    let mut history_stack = Vec::with_capacity(10);
+    let mut history = History::new(settings.rewind_depth);
+    history.reset(&machine.hardware);
+    let mut debugger = create_debugger(&settings);
+    let mut instruction_log = InstructionLog::new();
+
+    // Movies being played back press their own keys
+    let is_input_enabled = !movie.as_ref().is_some_and(MovieSession::is_playing);
 
     while window.is_open() {
         while let Some(event) = window.poll_event() {
             match event {
                 Event::Closed => window.close(),
                 Event::KeyPressed {code, alt: _, ctrl: _, shift: _, system: _} => {
-                    if !handle_key_pressed(&mut hardware, code) {
-                        if code == Key::Return && is_paused {
+                    if !handle_key_pressed(&mut machine.hardware, movie.as_mut(), &mut key_map, Input::Key(code), is_input_enabled) {
+                        if (code == Key::Return || code == Key::BackSpace) && is_paused && movie.is_some() {
+                            println!("Stepping and rewinding are not available while a movie is recording or playing");
+                        } else if code == Key::Return && is_paused {
                             // Since we are paused, enter being pressed means execute one instruction
                             if Instant::now() - last_step_at >= time_between_held_steps {
-                                history_stack.push(hardware.clone());
+                                render_state.clear_error();
+                                match step(&mut machine, &mut instruction_log, &mut tracer) {
+                                    Ok(_) => history.record(&machine.hardware),
+                                    Err(error) => {
+                                        match errors::handle_execution_error(error, &mut machine.hardware, &instruction_log, &settings) {
+                                            ErrorAction::Pause(message) => render_state.show_error(message),
+                                            ErrorAction::Exit => exit_after_crash(wav_recorder, tracer, movie),
+                                            ErrorAction::Continue => (),
+                                        }
+                                    }
+                                }
 
-                                execute_next_instruction(&mut hardware);
-                                hardware.key_released_since_last_instruction = None;
+                                if let Some(ref mut recorder) = wav_recorder {
+                                    recorder.record_tick(machine.hardware.is_buzzer_active()).unwrap();
+                                }
 
-                                hardware.simulate_timer_tick(); // Since we are paused, a step should simulate a frame tick
+                                machine.hardware.simulate_timer_tick(); // Since we are paused, a step should simulate a frame tick
                                 last_step_at = Instant::now();
                             }
-                        } else if code == Key::BackSpace && is_paused && history_stack.len() > 0 {
+                        } else if code == Key::BackSpace && is_paused {
                             if Instant::now() - last_step_at >= time_between_held_steps {
-                                hardware = history_stack.pop().unwrap();
+                                history.rewind(&mut machine.hardware);
                                 last_step_at = Instant::now();
                             }
                         }
                     }
                 },
                 Event::KeyReleased {code, alt: _, ctrl: _, shift: _, system: _} => {
-                    if !handle_key_released(&mut hardware, code) {
+                    if !handle_key_released(&mut machine.hardware, movie.as_mut(), &mut key_map, Input::Key(code), is_input_enabled) {
                         // Unmapped key was pressed, so see if this is a non-chip8 key
                         if code == Key::Space {
                             is_paused = !is_paused;
 
                             if !is_paused {
-                                last_instruction_at = Instant::now();
-                                history_stack.clear();
+                                debugger.resume();
+                                render_state.clear_error();
                             }
                         } else if code == Key::Escape {
                             println!("Reloading ROM");
-                            hardware = Hardware::new();
-                            history_stack.clear();
-                            roms::load_from_file(&mut hardware, &settings).unwrap();
+                            machine = create_machine(&settings);
+                            history.reset(&machine.hardware);
+                            scheduler.reset();
+                            instruction_log.clear();
+                            render_state.clear_error();
+                            if let Some(ref mut movie) = movie {
+                                movie.restart();
+                            }
+                        } else if code == Key::B {
+                            let address = machine.hardware.program_counter;
+                            match debugger.toggle_address_breakpoint(address) {
+                                true => println!("Added breakpoint at {:#x}", address),
+                                false => println!("Removed breakpoint at {:#x}", address),
+                            }
+                        } else if code == Key::F5 {
+                            if let Err(error) = states::quick_save(&machine.hardware, &settings) {
+                                println!("Could not save state: {}", error);
+                            }
+                        } else if code == Key::F9 && movie.is_some() {
+                            println!("Loading states is not available while a movie is recording or playing");
+                        } else if code == Key::F9 {
+                            match states::quick_load(&settings) {
+                                Ok(hardware) => {
+                                    machine.hardware = hardware;
+                                    history.reset(&machine.hardware);
+                                },
+
+                                Err(error) => println!("Could not load state: {}", error),
+                            }
                         }
                     }
                 }
+                Event::JoystickButtonPressed {joystickid: _, button} => {
+                    handle_key_pressed(&mut machine.hardware, movie.as_mut(), &mut key_map, Input::Button(button), is_input_enabled);
+                }
+
+                Event::JoystickButtonReleased {joystickid: _, button} => {
+                    handle_key_released(&mut machine.hardware, movie.as_mut(), &mut key_map, Input::Button(button), is_input_enabled);
+                }
+
+                Event::JoystickMoved {joystickid: _, axis, position} => {
+                    for (value, is_pressed) in key_map.axis_moved(axis, position) {
+                        set_key(&mut machine.hardware, movie.as_mut(), value, is_pressed, is_input_enabled);
+                    }
+                }
+
                 _ => (),
             }
         }
 
-        if !is_paused {
-            let time_since_last_instruction = Instant::now() - last_instruction_at;
-            let duration_micro = time_since_last_instruction.as_secs() * 1_000_000 + time_since_last_instruction.subsec_micros() as u64;
-            let instructions_since_last_frame = duration_micro / instruction_micro as u64;
-
-            for _ in 0..instructions_since_last_frame {
-                execute_next_instruction(&mut hardware);
-                hardware.key_released_since_last_instruction = None;
+        // Holding backspace while running scrubs backwards through history at normal speed
+        let is_rewinding = !is_paused && movie.is_none() && Key::BackSpace.is_pressed();
+        if is_rewinding {
+            for _ in 0..settings.timing.max_instructions_per_tick(settings.cycles_per_tick) {
+                if !history.rewind(&mut machine.hardware) {
+                    break;
+                }
             }
+        }
 
-            last_instruction_at = Instant::now();
+        // Time spent paused or rewinding doesn't count towards running the machine
+        let now = Instant::now();
+        match is_paused || is_rewinding {
+            true => scheduler.discard_due_time(),
+            false => scheduler.advance(now - last_update_at),
         }
 
-        if !is_paused {
-            hardware.simulate_timer_tick();
+        last_update_at = now;
+
+        while let Some(event) = scheduler.next_event() {
+            match event {
+                ScheduledEvent::Cycle => {
+                    let cycles = machine.next_instruction_cycles(&scheduler);
+                    if let Some(ref mut movie) = movie {
+                        movie.begin_instruction(&mut machine);
+                    }
+
+                    if let Some(reason) = debugger.check(&machine.hardware) {
+                        println!("Paused on {}", reason);
+                        is_paused = true;
+                        break;
+                    }
+
+                    if let Err(error) = step(&mut machine, &mut instruction_log, &mut tracer) {
+                        match errors::handle_execution_error(error, &mut machine.hardware, &instruction_log, &settings) {
+                            ErrorAction::Pause(message) => {
+                                render_state.show_error(message);
+                                is_paused = true;
+                                break;
+                            }
+
+                            ErrorAction::Exit => exit_after_crash(wav_recorder, tracer, movie),
+                            ErrorAction::Continue => (),
+                        }
+                    }
+
+                    if let Some(ref mut movie) = movie {
+                        movie.end_instruction();
+                    }
+
+                    history.record(&machine.hardware);
+                    scheduler.complete_cycles(cycles);
+                }
+
+                ScheduledEvent::TimerTick => {
+                    if let Some(ref mut recorder) = wav_recorder {
+                        recorder.record_tick(machine.hardware.is_buzzer_active()).unwrap();
+                    }
+
+                    if let Some(ref mut movie) = movie {
+                        movie.end_frame(&mut machine);
+                    }
+
+                    machine.hardware.simulate_timer_tick();
+
+                    scheduler.complete_event();
+                }
+            }
         }
 
-        render_state = rendering::render(&mut window, &mut hardware, &font, render_state, is_paused);
+        sound::update_buzzer(&mut buzzer, machine.hardware.is_buzzer_active() && !is_paused);
+
+        render_state = rendering::render(&mut window, &mut machine.hardware, &font, render_state, is_paused);
     }
+
+    finish_recordings(wav_recorder, tracer, movie);
 }
 
-fn execute_next_instruction(hardware: &mut Hardware) {
-    let (byte1, byte2) = hardware.get_current_instruction_bytes();
-    let instruction = r8_core::get_instruction(byte1, byte2);
-    r8_core::execute_instruction(instruction, hardware).unwrap();
+// Runs the next instruction, keeping the crash report log and the trace file up to date
+fn step(machine: &mut Machine, instruction_log: &mut InstructionLog, tracer: &mut Option<FileTracer>) -> Result<(), ExecutionError> {
+    instruction_log.record(&machine.hardware);
+    let pending_trace = traces::before_step(tracer, &machine.hardware);
+    machine.step()?;
+    traces::after_step(tracer, pending_trace, &machine.hardware);
+
+    Ok(())
 }
 
-fn handle_key_pressed(hardware: &mut Hardware, key: Key) -> bool {
-    match get_key_value(key) {
-        Some(x) => {
-            hardware.current_key_down = Some(x);
-            true
-        },
+fn finish_recordings(wav_recorder: Option<WavRecorder>, tracer: Option<FileTracer>, movie: Option<MovieSession>) {
+    if let Some(recorder) = wav_recorder {
+        recorder.finish().unwrap();
+    }
+
+    if let Some(tracer) = tracer {
+        tracer.finish().unwrap();
+    }
 
-        None => false,
+    if let Some(movie) = movie {
+        movie.finish().unwrap();
     }
 }
 
-fn handle_key_released(hardware: &mut Hardware, key: Key) -> bool {
-    match get_key_value(key) {
-        Some(x) => {
-            if hardware.current_key_down == Some(x) {
-                hardware.current_key_down = None;
-                hardware.key_released_since_last_instruction = Some(x);
-            }
+// A movie of the run that crashed is kept, so the crash can be reproduced
+fn exit_after_crash(wav_recorder: Option<WavRecorder>, tracer: Option<FileTracer>, movie: Option<MovieSession>) -> ! {
+    finish_recordings(wav_recorder, tracer, movie);
+    std::process::exit(1);
+}
+
+fn create_machine(settings: &Settings) -> Machine {
+    let mut machine = Machine::new(settings.platform);
+    machine.hardware.quirks = settings.quirks;
+    machine.timing = settings.timing;
+    machine.set_seed(settings.seed);
+    roms::load_from_file(&mut machine, settings).unwrap();
 
-            true // valid key was pressed
-        },
+    machine
+}
+
+fn create_debugger(settings: &Settings) -> Debugger {
+    let mut debugger = Debugger::new();
+    for breakpoint in &settings.breakpoints {
+        debugger.add_breakpoint(breakpoint.clone());
+    }
 
-        None => false, // unmapped key was pressed
+    for watchpoint in &settings.watchpoints {
+        debugger.add_watchpoint(*watchpoint);
     }
+
+    debugger
 }
 
-fn get_key_value(key: Key) -> Option<u8> {
-    match key {
-        Key::Num1 => Some(1),
-        Key::Num2 => Some(2),
-        Key::Num3 => Some(3),
-        Key::Q => Some(4),
-        Key::W => Some(5),
-        Key::E => Some(6),
-        Key::A => Some(7),
-        Key::S => Some(8),
-        Key::D => Some(9),
-        Key::Z => Some(0xa),
-        Key::X => Some(0),
-        Key::C => Some(0xb),
-        Key::Num4 => Some(0xc),
-        Key::R => Some(0xd),
-        Key::F => Some(0xe),
-        Key::V => Some(0xf),
-        _ => None,
+// Returns false for keys that aren't bound, so they can control the runner instead
+fn handle_key_pressed(hardware: &mut Hardware, movie: Option<&mut MovieSession>, key_map: &mut KeyMap<Key, Axis>,
+                      input: Input<Key, Axis>, is_input_enabled: bool) -> bool {
+    if let Some(value) = key_map.press(input) {
+        set_key(hardware, movie, value, true, is_input_enabled);
     }
-}
\ No newline at end of file
+
+    key_map.binding(input).is_some()
+}
+
+fn handle_key_released(hardware: &mut Hardware, movie: Option<&mut MovieSession>, key_map: &mut KeyMap<Key, Axis>,
+                       input: Input<Key, Axis>, is_input_enabled: bool) -> bool {
+    if let Some(value) = key_map.release(input) {
+        set_key(hardware, movie, value, false, is_input_enabled);
+    }
+
+    key_map.binding(input).is_some()
+}
+
+fn set_key(hardware: &mut Hardware, movie: Option<&mut MovieSession>, value: u8, is_pressed: bool, is_input_enabled: bool) {
+    if !is_input_enabled {
+        return;
+    }
+
+    if let Some(movie) = movie {
+        movie.record_key(hardware, value, is_pressed);
+    }
+
+    match is_pressed {
+        true => hardware.press_key(value),
+        false => hardware.release_key(value),
+    }
+}
@@ -0,0 +1,125 @@
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use r8_core::{Hardware, Machine, Movie, MovieError};
+use crate::settings::Settings;
+
+enum Mode {
+    Recording {file_name: String},
+    Playing,
+}
+
+// Records each key press and release with how many of the frame's instructions had run, and plays
+// them back between the same two instructions, so taps and changes made while paused are repeated
+// exactly.
+pub struct MovieSession {
+    mode: Mode,
+    movie: Movie,
+    frame: u64,
+    instruction: u64, // How many of the frame's instructions have run
+    is_instruction_started: bool,
+}
+
+impl MovieSession {
+    // Playing a movie back also replaces the platform, quirks, seed, speed and timing in the settings
+    // with the ones it was recorded with
+    pub fn from_settings(settings: &mut Settings) -> Result<Option<Self>, MovieError> {
+        let (mode, movie) = match (&settings.record_movie_file, &settings.play_movie_file) {
+            (_, Some(file_name)) => {
+                let movie = Movie::load(&mut BufReader::new(File::open(file_name)?))?;
+                movie.create_machine(&fs::read(&settings.rom_file)?)?; // Fails if the ROM is different
+                println!("Playing movie: {} ({} frames)", file_name, movie.length);
+
+                settings.platform = movie.platform;
+                settings.quirks = movie.quirks;
+                settings.seed = movie.seed;
+                settings.cycles_per_tick = movie.cycles_per_frame.max(1);
+                settings.timing = movie.timing;
+                (Mode::Playing, movie)
+            }
+
+            (Some(file_name), None) => {
+                println!("Recording movie to: {}", file_name);
+                let rom = fs::read(&settings.rom_file)?;
+                let mut movie = Movie::new(settings.platform, settings.quirks, settings.seed, settings.cycles_per_tick, &rom);
+                movie.timing = settings.timing;
+                (Mode::Recording {file_name: file_name.clone()}, movie)
+            }
+
+            (None, None) => return Ok(None),
+        };
+
+        Ok(Some(MovieSession {mode, movie, frame: 0, instruction: 0, is_instruction_started: false}))
+    }
+
+    pub fn is_playing(&self) -> bool {
+        match self.mode {
+            Mode::Playing => true,
+            Mode::Recording {..} => false,
+        }
+    }
+
+    // Should be called as a key is pressed or released, before the hardware sees it.  Presses of
+    // keys already held down, such as from key repeat, aren't recorded.
+    pub fn record_key(&mut self, hardware: &Hardware, key: u8, is_pressed: bool) {
+        if let Mode::Recording {..} = self.mode {
+            if hardware.is_key_down(key) != is_pressed {
+                self.movie.record_key(self.frame, self.instruction, key, is_pressed);
+            }
+        }
+    }
+
+    // Should be called before each instruction.  Calling it again before the instruction runs, such
+    // as after stopping on a breakpoint, does nothing.
+    pub fn begin_instruction(&mut self, machine: &mut Machine) {
+        if self.is_instruction_started {
+            return;
+        }
+
+        if self.is_playing() {
+            self.movie.apply_key_events(machine, self.frame, self.instruction);
+        }
+
+        self.is_instruction_started = true;
+    }
+
+    pub fn end_instruction(&mut self) {
+        self.instruction += 1;
+        self.is_instruction_started = false;
+    }
+
+    // Should be called just before the frame's timer tick
+    pub fn end_frame(&mut self, machine: &mut Machine) {
+        self.begin_instruction(machine); // Keys changed after the frame's last instruction
+        if let Mode::Recording {..} = self.mode {
+            self.movie.record_frame(self.frame);
+        }
+
+        self.frame += 1;
+        self.instruction = 0;
+        self.is_instruction_started = false;
+        if self.is_playing() && self.frame == self.movie.length {
+            println!("Movie finished after {} frames", self.frame);
+        }
+    }
+
+    // Starts again from the first frame, such as after the ROM is reloaded
+    pub fn restart(&mut self) {
+        self.frame = 0;
+        self.instruction = 0;
+        self.is_instruction_started = false;
+        if let Mode::Recording {..} = self.mode {
+            self.movie.clear();
+        }
+    }
+
+    pub fn finish(self) -> Result<(), MovieError> {
+        if let Mode::Recording {file_name} = self.mode {
+            let mut file = BufWriter::new(File::create(&file_name)?);
+            self.movie.save(&mut file)?;
+            println!("Saved {} frame movie to: {}", self.movie.length, file_name);
+        }
+
+        Ok(())
+    }
+}
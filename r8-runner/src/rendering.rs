@@ -4,13 +4,15 @@ use sfml::system::Vector2f;
 use sfml::graphics::{RenderWindow, Color, RenderTarget, Font, Text};
 use sfml::graphics::{RectangleShape, Shape, Transformable, Image, Sprite, Texture};
 
-const PLAY_AREA_SCALING_FACTOR: u32 = 8;
+// The layout's size before the window is scaled
+pub const WINDOW_WIDTH: u32 = 800;
+pub const WINDOW_HEIGHT: u32 = 600;
+const PLAY_AREA_WIDTH: u32 = 512;
 const PLAY_AREA_THICKNESS: u32 = 5;
 const PLAY_AREA_START_X: u32 = 0 + PLAY_AREA_THICKNESS;
 const PLAY_AREA_START_Y: u32 = 0 + PLAY_AREA_THICKNESS;
-const PIXEL_ON_COLOR: &Color = &Color {r: 68, g: 68, b: 41, a: 255};
-const PIXEL_OFF_COLOR: &Color = &Color {r: 115, g: 130, b: 92, a: 255};
 const REGISTER_START_Y: u32 = 300;
+const ERROR_START_Y: u32 = 272;
 const ADDRESS_SPACE_BORDER_THICKNESS: u32 = 5;
 const ADDRESS_SPACE_START_X: u32 = 550 + ADDRESS_SPACE_BORDER_THICKNESS;
 const ADDRESS_SPACE_START_Y: u32 = 30 + ADDRESS_SPACE_BORDER_THICKNESS;
@@ -18,37 +20,94 @@ const ADDRESS_DISPLAY_COUNT: u32 = 22;
 const SPRITE_DISPLAY_START_X: u32 = 400;
 const SPRITE_DISPLAY_START_Y: u32 = 300;
 
+// Which debugger panels are drawn around the play area
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DebuggerLayout {
+    pub registers: bool,
+    pub disassembly: bool,
+    pub sprite: bool,
+}
+
+impl Default for DebuggerLayout {
+    fn default() -> Self {
+        DebuggerLayout {registers: true, disassembly: true, sprite: true}
+    }
+}
+
+impl DebuggerLayout {
+    pub const PANEL_NAMES: [&'static str; 3] = ["registers", "disassembly", "sprite"];
+
+    // Only the panels named are shown, so an empty list leaves just the play area
+    pub fn from_names(names: &[String]) -> Self {
+        let is_shown = |panel: &str| names.iter().any(|x| x == panel);
+        DebuggerLayout {registers: is_shown("registers"), disassembly: is_shown("disassembly"), sprite: is_shown("sprite")}
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        let shown = [self.registers, self.disassembly, self.sprite];
+        DebuggerLayout::PANEL_NAMES.iter().zip(shown.iter()).filter(|(_, is_shown)| **is_shown).map(|(name, _)| *name).collect()
+    }
+}
+
 pub struct RenderState {
     lowest_visible_address: u16,
     highest_visible_address: u16,
+    error: Option<String>,
+    pixel_colors: [Color; 4],
+    layout: DebuggerLayout,
 }
 
 impl RenderState {
-    pub fn new() -> Self {
+    pub fn new(palette: &[[u8; 3]; 4], layout: DebuggerLayout) -> Self {
         RenderState {
             lowest_visible_address: 512,
             highest_visible_address: 512 + ADDRESS_DISPLAY_COUNT as u16,
+            error: None,
+            pixel_colors: palette.map(|[red, green, blue]| Color::rgb(red, green, blue)),
+            layout,
         }
     }
+
+    // Shown under the play area until execution continues
+    pub fn show_error(&mut self, message: String) {
+        self.error = Some(message);
+    }
+
+    pub fn clear_error(&mut self) {
+        self.error = None;
+    }
 }
 
 pub fn render(window: &mut RenderWindow, hardware: &Hardware, font: &Font, mut last_render_state: RenderState, is_paused: bool) -> RenderState {
     window.set_active(true);
     window.clear(&Color::BLACK);
 
-    render_framebuffer(window, &hardware);
-    render_registers(window, &hardware, font);
-    render_assembly_display(window, hardware, font, &mut last_render_state, is_paused);
-    render_next_sprite_display(window, hardware, font);
+    render_framebuffer(window, &hardware, &last_render_state.pixel_colors);
+    render_paused_indicator(window, font, is_paused);
+    if last_render_state.layout.registers {
+        render_registers(window, &hardware, font);
+    }
+
+    if last_render_state.layout.disassembly {
+        render_assembly_display(window, hardware, font, &mut last_render_state);
+    }
+
+    if last_render_state.layout.sprite {
+        render_next_sprite_display(window, hardware, font);
+    }
+
+    render_error(window, font, &last_render_state);
 
     window.display();
 
     last_render_state
 }
 
-fn render_framebuffer(window: &mut RenderWindow, hardware: &Hardware) {
-    let width = hardware.framebuffer[0].len() as u32 * 8 * PLAY_AREA_SCALING_FACTOR; // * 8 to expand byte compaction
-    let height = hardware.framebuffer.len() as u32 * PLAY_AREA_SCALING_FACTOR;
+fn render_framebuffer(window: &mut RenderWindow, hardware: &Hardware, pixel_colors: &[Color; 4]) {
+    // High resolution mode has twice as many pixels in the same play area
+    let scaling_factor = PLAY_AREA_WIDTH / hardware.display_width() as u32;
+    let width = hardware.display_width() as u32 * scaling_factor;
+    let height = hardware.display_height() as u32 * scaling_factor;
 
     // First display the play area and border
     let mut shape = RectangleShape::new();
@@ -63,24 +122,19 @@ fn render_framebuffer(window: &mut RenderWindow, hardware: &Hardware) {
     let mut image = Image::new(width, height);
     let mut current_y = 0;
     let mut current_x = 0;
-    for row in 0..hardware.framebuffer.len() {
-        for column_set in 0..hardware.framebuffer[row].len() {
-            const BIT_MASK: u8 = 0b0000001;
-            let byte = hardware.framebuffer[row][column_set];
-            for shift in 0..8 {
-                let pixel_on = if (byte >> (7 - shift)) & BIT_MASK == 1 { true } else { false };
-                for scale_y in 0..PLAY_AREA_SCALING_FACTOR {
-                    for scale_x in 0..PLAY_AREA_SCALING_FACTOR {
-                        let color = if pixel_on { PIXEL_ON_COLOR } else { PIXEL_OFF_COLOR };
-                        image.set_pixel(scale_x + current_x, scale_y + current_y, color);
-                    }
+    for row in 0..hardware.display_height() {
+        for column in 0..hardware.display_width() {
+            let color = &pixel_colors[hardware.pixel(column, row) as usize];
+            for scale_y in 0..scaling_factor {
+                for scale_x in 0..scaling_factor {
+                    image.set_pixel(scale_x + current_x, scale_y + current_y, color);
                 }
-
-                current_x += PLAY_AREA_SCALING_FACTOR;
             }
+
+            current_x += scaling_factor;
         }
 
-        current_y += PLAY_AREA_SCALING_FACTOR;
+        current_y += scaling_factor;
         current_x = 0;
     }
 
@@ -103,8 +157,8 @@ fn render_registers(window: &mut RenderWindow, hardware: &Hardware, font: &Font)
     let st_string = format!("ST: {:0>2x}", hardware.sound_timer);
     let i_string = format!("I : {:0>3x}", hardware.i_register);
 
-    let key_down_code = if let Some(x) = hardware.current_key_down {format!("{:x}", x)} else { "".to_owned() };
-    let input_string = format!("Key: {}", key_down_code);
+    let key_down_codes = (0..16_u8).filter(|x| hardware.is_key_down(*x)).map(|x| format!("{:x}", x)).collect::<String>();
+    let input_string = format!("Key: {}", key_down_codes);
 
     render_register_value(window, font, dt_string, &mut current_x, &mut current_y);
     render_register_value(window, font, st_string, &mut current_x, &mut current_y);
@@ -116,25 +170,27 @@ fn render_register_value(window: &mut RenderWindow, font: &Font, display: String
     draw_text(window, font, 25, display.as_ref(), *current_x as f32, *current_y as f32);
 
     *current_y += 30;
-    if *current_y + 25 > 600 {
+    if *current_y + 25 > WINDOW_HEIGHT {
         *current_y = REGISTER_START_Y;
         *current_x += 125;
     }
 }
 
-fn render_assembly_display(window: &mut RenderWindow, hardware: &Hardware, font: &Font, render_state: &mut RenderState, is_paused: bool) {
-    const FONT_SIZE: u32 = 20;
-    const FONT_SPACING: f32 = 5.0;
-    const MIN_HIGH_ADDRESS_BUFFER: u16 = 3;
-
+fn render_paused_indicator(window: &mut RenderWindow, font: &Font, is_paused: bool) {
     if is_paused {
-        let mut text = Text::new("DEBUGGING", &font, FONT_SIZE);
+        let mut text = Text::new("DEBUGGING", font, 20);
         text.set_fill_color(&Color::RED);
 
         let text_x = ADDRESS_SPACE_START_X as f32;
         text.set_position(Vector2f::new(text_x, 0.0));
         window.draw(&text);
     }
+}
+
+fn render_assembly_display(window: &mut RenderWindow, hardware: &Hardware, font: &Font, render_state: &mut RenderState) {
+    const FONT_SIZE: u32 = 20;
+    const FONT_SPACING: f32 = 5.0;
+    const MIN_HIGH_ADDRESS_BUFFER: u16 = 3;
 
     let first_memory_address = if render_state.lowest_visible_address % 2 != hardware.program_counter % 2 {
         // We changed even vs odd, so reset boundaries
@@ -154,13 +210,11 @@ fn render_assembly_display(window: &mut RenderWindow, hardware: &Hardware, font:
         render_state.lowest_visible_address
     };
 
-    let width = window.size().x - ADDRESS_SPACE_START_X - ADDRESS_SPACE_BORDER_THICKNESS;
+    let width = WINDOW_WIDTH - ADDRESS_SPACE_START_X - ADDRESS_SPACE_BORDER_THICKNESS;
     for x in 0..ADDRESS_DISPLAY_COUNT {
         let address = first_memory_address as usize + (x * 2) as usize;
 
-        let byte1 = hardware.memory[address];
-        let byte2 = hardware.memory[address + 1];
-        let instruction = r8_core::get_instruction(byte1, byte2);
+        let instruction = r8_core::get_instruction_at(&hardware.memory, address);
         let display = format!("{:0>3x}: {}", address, instruction);
 
         let mut text = Text::new(display.as_ref(), &font, FONT_SIZE);
@@ -189,8 +243,7 @@ fn render_next_sprite_display(window: &mut RenderWindow, hardware: &Hardware, fo
     const FONT_SIZE: u32 = 17;
     const Y_SPACING: f32 = 0.0;
 
-    let (byte1, byte2) = hardware.get_current_instruction_bytes();
-    if let Instruction::DrawSprite {x_register: _, y_register: _, height} = r8_core::get_instruction(byte1, byte2) {
+    if let Instruction::DrawSprite {x_register: _, y_register: _, height} = hardware.get_current_instruction() {
         let start_memory_address = hardware.i_register;
         let mut current_y = SPRITE_DISPLAY_START_Y as f32;
 
@@ -206,6 +259,15 @@ fn render_next_sprite_display(window: &mut RenderWindow, hardware: &Hardware, fo
     }
 }
 
+fn render_error(window: &mut RenderWindow, font: &Font, render_state: &RenderState) {
+    if let Some(ref message) = render_state.error {
+        let mut text = Text::new(message, font, 16);
+        text.set_fill_color(&Color::RED);
+        text.set_position(Vector2f::new(PLAY_AREA_START_X as f32, ERROR_START_Y as f32));
+        window.draw(&text);
+    }
+}
+
 fn draw_text(window: &mut RenderWindow, font: &Font, font_size: u32, string: &str, x: f32, y: f32) {
     let mut text = Text::new(string, &font, font_size);
     text.set_position(Vector2f::new(x, y));
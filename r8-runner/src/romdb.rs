@@ -0,0 +1,16 @@
+use std::fs;
+use std::path::Path;
+use r8_core::RomInfo;
+
+// A copy of programs.json from the community CHIP-8 database (github.com/chip-8/chip-8-database)
+pub const DEFAULT_ROM_DATABASE_FILE: &str = "programs.json";
+
+// Returns None if there's no database file or it doesn't have the ROM
+pub fn look_up(database_file: &str, rom: &[u8]) -> Result<Option<RomInfo>, String> {
+    if !Path::new(database_file).exists() {
+        return Ok(None);
+    }
+
+    let text = fs::read_to_string(database_file).map_err(|error| format!("{}: {}", database_file, error))?;
+    r8_core::find_rom_info(&text, rom).map_err(|error| format!("{}: {}", database_file, error))
+}
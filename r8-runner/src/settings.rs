@@ -1,19 +1,362 @@
-use clap::App;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use clap::{App, ArgMatches};
+use crate::config;
+use crate::errors::ErrorPolicy;
+use crate::input;
+use crate::rendering::DebuggerLayout;
+use crate::romdb;
+use r8_core::{RomInfo, ConfigEntry, ConfigValue, Preferences, PreferenceLayer};
+use r8_core::{Platform, Quirks, Random, Breakpoint, Watchpoint, Condition, TraceFormat, TraceFilter, TimingModel, TIMER_FREQUENCY, VIP_CYCLES_PER_TICK, DEFAULT_PALETTE};
+
+const GLOBAL_CONFIG_FILE: &str = "config.toml"; // In an r8 folder in the user's config directory
+const CONFIG_FILE: &str = "r8.toml"; // For every ROM in the same directory
+const ROM_CONFIG_FILE_EXTENSION: &str = "toml"; // Added to the ROM's file name for settings just for it
+
+// A config file's name and the entries read from it
+pub type ConfigFile = (String, Vec<ConfigEntry>);
+
+// Every setting that can be kept in a config file, by its name there (section.key for ones in a
+// section) and its command line option
+const SETTINGS: [(&str, &str); 15] = [
+    ("platform", "platform"),
+    ("quirks", "quirks"),
+    ("timing", "timing"),
+    ("ips", "ips"),
+    ("cycles_per_tick", "cycles-per-tick"),
+    ("start_paused", "paused"),
+    ("rewind_depth", "rewind-depth"),
+    ("on_error", "on-error"),
+    ("rom_database", "rom-database"),
+    ("keymap", "keymap"),
+    ("palette", "palette"),
+    ("window.scale", "scale"),
+    ("audio.tone", "tone"),
+    ("audio.volume", "volume"),
+    ("debugger.panels", "debugger-panels"),
+];
+
+// Settings that are two ways of giving the same thing, so setting one clears the other
+const ALTERNATIVES: [(&str, &str); 1] = [("ips", "cycles_per_tick")];
+
+const TIMING_MODELS: [&str; 2] = ["fixed", "vip"];
+const ERROR_POLICIES: [&str; 3] = ["pause", "skip", "exit"];
 
 pub struct Settings {
     pub rom_file: String,
     pub start_paused: bool,
-    pub instructions_per_second: u16,
+    pub cycles_per_tick: u32, // In the timing model's cycles
+    pub timing: TimingModel,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub key_map_file: Option<String>,
+    pub rom_key_bindings: Vec<(u8, String)>, // From the ROM database
+    pub palette: [[u8; 3]; 4],
+    pub window_scale: f32,
+    pub debugger_layout: DebuggerLayout,
+    pub config_files: Vec<ConfigFile>, // Lowest priority first, kept for their [keys] sections
+    pub tone_frequency: f32,
+    pub volume: f32,
+    pub wav_file: Option<String>,
+    pub seed: u64,
+    pub rewind_depth: usize,
+    pub error_policy: ErrorPolicy,
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    pub record_movie_file: Option<String>,
+    pub play_movie_file: Option<String>,
+    pub trace_file: Option<String>,
+    pub trace_format: TraceFormat,
+    pub trace_filter: TraceFilter,
 }
 
 impl Settings {
+    // Settings come from config files, the ROM database, R8_ environment variables and the command
+    // line, with later ones winning (see load_preferences)
     pub fn from_cli_arguments() -> Self {
         let yaml = load_yaml!("cli.yml");
         let matches = App::from_yaml(yaml).get_matches();
-        Self {
-            rom_file: matches.value_of("INPUT").unwrap().to_owned(),
-            start_paused: matches.is_present("paused"),
-            instructions_per_second: value_t!(matches, "ips", u16).unwrap_or(60 * 5),
+        let rom_file = matches.value_of("INPUT").unwrap().to_owned();
+        let (preferences, config_files, rom_info) = or_exit(load_preferences(&matches, &rom_file));
+
+        let platform = or_exit(preferences.choice("platform", &Platform::NAMES)).as_deref()
+            .and_then(Platform::from_name)
+            .unwrap_or(Platform::Chip8);
+
+        // The database's quirks are for the platform it gave, and only beat a preset from the global file
+        let database_quirks = rom_info.as_ref().and_then(|x| x.platform).filter(|(x, _)| *x == platform).map(|(_, x)| x);
+        let quirk_preset = or_exit(preferences.choice("quirks", &Quirks::PRESET_NAMES)).as_deref().and_then(Quirks::preset);
+        let (quirks, quirks_origin) = match (quirk_preset, database_quirks) {
+            (Some(quirks), _) if preferences.layer("quirks") > Some(PreferenceLayer::RomDatabase) => (quirks, preferences.origin("quirks")),
+            (_, Some(quirks)) => (quirks, "ROM database"),
+            (Some(quirks), None) => (quirks, preferences.origin("quirks")),
+            (None, None) => (platform.default_quirks(), "default for the platform"),
+        };
+
+        let mut palette = DEFAULT_PALETTE;
+        for (color, text) in palette.iter_mut().zip(or_exit(preferences.list("palette")).unwrap_or_default()) {
+            *color = r8_core::parse_color(&text).unwrap_or_else(|| {
+                eprintln!("error: palette from {}: '{}' is not a color such as #ffcc00", preferences.origin("palette"), text);
+                std::process::exit(1);
+            });
+        }
+
+        let mut breakpoints = Vec::new();
+        for address in matches.values_of("break").into_iter().flatten() {
+            breakpoints.push(Breakpoint::Address(or_exit(r8_core::parse_address(address))));
+        }
+
+        for condition in matches.values_of("break-if").into_iter().flatten() {
+            breakpoints.push(Breakpoint::Condition(or_exit(Condition::parse(condition))));
+        }
+
+        for name in matches.values_of("break-on").into_iter().flatten() {
+            breakpoints.push(or_exit(Breakpoint::parse_opcode(name)));
+        }
+
+        let watchpoints = matches.values_of("watch").into_iter().flatten()
+            .map(|range| or_exit(Watchpoint::parse(range)))
+            .collect();
+
+        let trace_filter = TraceFilter {
+            address_range: matches.value_of("trace-range").map(|range| or_exit(TraceFilter::parse_address_range(range))),
+            instruction_names: matches.values_of("trace-only").into_iter().flatten().map(|x| or_exit(r8_core::parse_instruction_name(x))).collect(),
+        };
+
+        let timing = match or_exit(preferences.choice("timing", &TIMING_MODELS)).as_deref() {
+            Some("vip") => TimingModel::CosmacVip,
+            _ => TimingModel::Fixed,
+        };
+
+        let cycles_per_tick = match (timing, or_exit(preferences.number::<u32>("cycles_per_tick")), or_exit(preferences.number::<u32>("ips"))) {
+            (TimingModel::CosmacVip, _, _) => VIP_CYCLES_PER_TICK,
+            (TimingModel::Fixed, Some(cycles_per_tick), _) => cycles_per_tick,
+            (TimingModel::Fixed, _, Some(instructions_per_second)) => instructions_per_second / TIMER_FREQUENCY,
+            (TimingModel::Fixed, None, None) => 5,
+        }.max(1);
+
+        let instructions_per_second = timing.max_instructions_per_tick(cycles_per_tick) * TIMER_FREQUENCY;
+
+        let window_scale = or_exit(preferences.number::<f32>("window.scale")).unwrap_or(1.0);
+        if !window_scale.is_finite() || window_scale <= 0.0 {
+            eprintln!("error: window.scale from {}: expected a number above 0", preferences.origin("window.scale"));
+            std::process::exit(1);
+        }
+
+        let debugger_layout = match or_exit(preferences.list("debugger.panels")) {
+            Some(names) => {
+                if let Some(name) = names.iter().find(|x| *x != "none" && !DebuggerLayout::PANEL_NAMES.contains(&x.as_str())) {
+                    eprintln!("error: debugger.panels from {}: '{}' is not a panel, expected {}",
+                              preferences.origin("debugger.panels"), name, DebuggerLayout::PANEL_NAMES.join(", "));
+                    std::process::exit(1);
+                }
+
+                DebuggerLayout::from_names(&names)
+            }
+
+            None => DebuggerLayout::default(),
+        };
+
+        let settings = Self {
+            rom_file,
+            start_paused: or_exit(preferences.flag("start_paused")).unwrap_or(false),
+            cycles_per_tick,
+            timing,
+            platform,
+            quirks,
+            key_map_file: or_exit(preferences.text("keymap")),
+            rom_key_bindings: rom_info.map(|x| x.key_bindings).unwrap_or_default(),
+            palette,
+            window_scale,
+            debugger_layout,
+            config_files,
+            tone_frequency: or_exit(preferences.number::<f32>("audio.tone")).unwrap_or(r8_core::DEFAULT_TONE_FREQUENCY),
+            volume: or_exit(preferences.number::<f32>("audio.volume")).map(|x| x / 100.0).unwrap_or(r8_core::DEFAULT_VOLUME),
+            wav_file: matches.value_of("wav").map(|x| x.to_owned()),
+            seed: value_t!(matches, "seed", u64).unwrap_or_else(|_| Random::generate_seed()),
+            rewind_depth: or_exit(preferences.number::<usize>("rewind_depth")).unwrap_or(instructions_per_second as usize * 10),
+            error_policy: match or_exit(preferences.choice("on_error", &ERROR_POLICIES)).as_deref() {
+                Some("skip") => ErrorPolicy::Skip,
+                Some("exit") => ErrorPolicy::Exit,
+                _ => ErrorPolicy::Pause,
+            },
+            breakpoints,
+            watchpoints,
+            record_movie_file: matches.value_of("record-movie").map(|x| x.to_owned()),
+            play_movie_file: matches.value_of("play-movie").map(|x| x.to_owned()),
+            trace_file: matches.value_of("trace").map(|x| x.to_owned()),
+            trace_format: match matches.value_of("trace-format") {
+                Some("binary") => TraceFormat::Binary,
+                _ => TraceFormat::Text,
+            },
+            trace_filter,
+        };
+
+        if matches.is_present("print-config") {
+            print_config(&settings, &preferences, quirks_origin);
+            std::process::exit(0);
+        }
+
+        settings
+    }
+}
+
+// Reads every layer of settings.  From lowest to highest priority they are the global config
+// file, the ROM database, r8.toml in the ROM's directory, <rom>.toml next to the ROM, R8_
+// environment variables and then the command line.
+fn load_preferences(matches: &ArgMatches, rom_file: &str) -> Result<(Preferences, Vec<ConfigFile>, Option<RomInfo>), String> {
+    let mut preferences = Preferences::new(&SETTINGS, &ALTERNATIVES);
+    let mut config_files = Vec::new();
+    let directory_file = Path::new(rom_file).with_file_name(CONFIG_FILE).to_string_lossy().into_owned();
+    let rom_config_file = format!("{}.{}", rom_file, ROM_CONFIG_FILE_EXTENSION);
+    let files = [
+        (PreferenceLayer::GlobalFile, global_config_file(matches)),
+        (PreferenceLayer::DirectoryFile, Some(directory_file)),
+        (PreferenceLayer::RomFile, Some(rom_config_file)),
+    ];
+
+    for (layer, file_name) in files.iter() {
+        let file_name = match file_name {
+            Some(file_name) if !config_files.iter().any(|(x, _)| x == file_name) => file_name,
+            _ => continue,
+        };
+
+        if let Some(entries) = config::read_file(file_name)? {
+            preferences.add_file(*layer, file_name, &entries).map_err(|error| error.to_string())?;
+            config_files.push((file_name.clone(), entries));
+        }
+    }
+
+    preferences.add_environment(|variable| env::var(variable).ok());
+    preferences.add_command_line(|option| match matches.is_present(option) {
+        true => Some(matches.values_of(option).into_iter().flatten().map(|x| x.to_owned()).collect()),
+        false => None,
+    });
+
+    // Where the database is can itself be set in any of the other layers
+    let database_file = preferences.text("rom_database").map_err(|error| error.to_string())?.unwrap_or_else(|| romdb::DEFAULT_ROM_DATABASE_FILE.to_owned());
+    let rom_info = look_up_rom(&database_file, rom_file);
+    if let Some(rom_info) = &rom_info {
+        let origin = || "ROM database".to_owned();
+        if let Some((platform, _)) = rom_info.platform {
+            preferences.set("platform", ConfigValue::String(platform.name().to_owned()), PreferenceLayer::RomDatabase, origin());
+        }
+
+        if let Some(tickrate) = rom_info.tickrate {
+            preferences.set("cycles_per_tick", ConfigValue::Integer(tickrate as i64), PreferenceLayer::RomDatabase, origin());
         }
+
+        if !rom_info.palette.is_empty() {
+            let colors = rom_info.palette.iter().map(|x| ConfigValue::String(r8_core::format_color(*x))).collect();
+            preferences.set("palette", ConfigValue::Array(colors), PreferenceLayer::RomDatabase, origin());
+        }
+    }
+
+    Ok((preferences, config_files, rom_info))
+}
+
+// Given with --config or R8_CONFIG, otherwise r8/config.toml in the user's config directory
+fn global_config_file(matches: &ArgMatches) -> Option<String> {
+    if let Some(file_name) = matches.value_of("config").map(|x| x.to_owned()).or_else(|| env::var("R8_CONFIG").ok()) {
+        return Some(file_name);
     }
+
+    let config_directory = env::var("APPDATA").ok() // Windows
+        .or_else(|| env::var("XDG_CONFIG_HOME").ok())
+        .or_else(|| env::var("HOME").ok().map(|x| format!("{}/.config", x)))?;
+
+    Some(Path::new(&config_directory).join("r8").join(GLOBAL_CONFIG_FILE).to_string_lossy().into_owned())
+}
+
+// Written as a config file that gives the same settings, with where each came from
+fn print_config(settings: &Settings, preferences: &Preferences, quirks_origin: &str) {
+    println!("# Effective settings for {}", settings.rom_file);
+    for (file_name, _) in &settings.config_files {
+        println!("# Read {}", file_name);
+    }
+
+    let speed_origin = match (settings.timing, preferences.layer("ips")) {
+        (TimingModel::CosmacVip, _) => preferences.origin("timing"),
+        (TimingModel::Fixed, Some(_)) => preferences.origin("ips"),
+        (TimingModel::Fixed, None) => preferences.origin("cycles_per_tick"),
+    };
+
+    let timing = match settings.timing {
+        TimingModel::Fixed => "fixed",
+        TimingModel::CosmacVip => "vip",
+    };
+
+    let on_error = match settings.error_policy {
+        ErrorPolicy::Pause => "pause",
+        ErrorPolicy::Skip => "skip",
+        ErrorPolicy::Exit => "exit",
+    };
+
+    let quirk_preset = settings.quirks.preset_name();
+    let key_map_file = settings.key_map_file.clone().unwrap_or_else(|| input::DEFAULT_KEY_MAP_FILE.to_owned());
+    let rom_database = preferences.text("rom_database").ok().flatten().unwrap_or_else(|| romdb::DEFAULT_ROM_DATABASE_FILE.to_owned());
+    let strings = |values: Vec<String>| ConfigValue::Array(values.into_iter().map(ConfigValue::String).collect());
+    let values = [
+        ("platform", ConfigValue::String(settings.platform.name().to_owned()), preferences.origin("platform")),
+        ("quirks", ConfigValue::String(quirk_preset.unwrap_or_default().to_owned()), quirks_origin),
+        ("timing", ConfigValue::String(timing.to_owned()), preferences.origin("timing")),
+        ("cycles_per_tick", ConfigValue::Integer(settings.cycles_per_tick as i64), speed_origin),
+        ("start_paused", ConfigValue::Boolean(settings.start_paused), preferences.origin("start_paused")),
+        ("rewind_depth", ConfigValue::Integer(settings.rewind_depth as i64), preferences.origin("rewind_depth")),
+        ("on_error", ConfigValue::String(on_error.to_owned()), preferences.origin("on_error")),
+        ("rom_database", ConfigValue::String(rom_database), preferences.origin("rom_database")),
+        ("keymap", ConfigValue::String(key_map_file), preferences.origin("keymap")),
+        ("palette", strings(settings.palette.iter().map(|x| r8_core::format_color(*x)).collect()), preferences.origin("palette")),
+        ("window.scale", ConfigValue::Float(settings.window_scale as f64), preferences.origin("window.scale")),
+        ("audio.tone", ConfigValue::Float(settings.tone_frequency as f64), preferences.origin("audio.tone")),
+        ("audio.volume", ConfigValue::Float((settings.volume * 100.0) as f64), preferences.origin("audio.volume")),
+        ("debugger.panels", strings(settings.debugger_layout.names().iter().map(|x| x.to_string()).collect()), preferences.origin("debugger.panels")),
+    ];
+
+    let mut current_section = "";
+    for (key, value, origin) in values.iter() {
+        let (section, name) = match key.find('.') {
+            Some(index) => (&key[..index], &key[index + 1..]),
+            None => ("", *key),
+        };
+
+        if section != current_section {
+            println!("\n[{}]", section);
+            current_section = section;
+        }
+
+        // Quirks from the ROM database can be a mix no preset gives
+        if *key == "quirks" && quirk_preset.is_none() {
+            println!("# quirks matching no preset ({}): {:?}", origin, settings.quirks);
+            continue;
+        }
+
+        println!("{} = {}  # {}", name, r8_core::format_config_value(value), origin);
+    }
+}
+
+// A broken or missing database only means the ROM runs with the usual defaults
+fn look_up_rom(database_file: &str, rom_file: &str) -> Option<RomInfo> {
+    let rom = fs::read(rom_file).ok()?;
+    match romdb::look_up(database_file, &rom) {
+        Ok(Some(rom_info)) => {
+            println!("Found {} in ROM database", rom_info.title);
+            Some(rom_info)
+        }
+
+        Ok(None) => None,
+        Err(error) => {
+            eprintln!("Could not read ROM database: {}", error);
+            None
+        }
+    }
+}
+
+fn or_exit<T, E: fmt::Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    })
 }
\ No newline at end of file
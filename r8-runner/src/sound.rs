@@ -0,0 +1,58 @@
+use std::io;
+use std::io::BufWriter;
+use std::fs::File;
+use r8_core::{ToneGenerator, WavWriter};
+use sfml::audio::{Sound, SoundBuffer, SoundStatus};
+use crate::settings::Settings;
+
+pub fn create_tone_generator(settings: &Settings) -> ToneGenerator {
+    ToneGenerator::new(r8_core::DEFAULT_SAMPLE_RATE, settings.tone_frequency, settings.volume)
+}
+
+// One second of the tone, which is looped for as long as the buzzer is active
+pub fn create_tone_buffer(settings: &Settings) -> SoundBuffer {
+    let mut generator = create_tone_generator(settings);
+    let samples = generator.generate(true, generator.sample_rate() as usize);
+    SoundBuffer::from_samples(&samples, 1, generator.sample_rate()).unwrap()
+}
+
+pub fn update_buzzer(sound: &mut Sound, is_active: bool) {
+    match (is_active, sound.status()) {
+        (true, SoundStatus::Playing) => (),
+        (true, _) => sound.play(),
+        (false, SoundStatus::Playing) => sound.pause(),
+        (false, _) => (),
+    }
+}
+
+// Writes the tone to a WAV file one timer tick at a time, so it can be checked without a sound card
+pub struct WavRecorder {
+    generator: ToneGenerator,
+    writer: WavWriter<BufWriter<File>>,
+}
+
+impl WavRecorder {
+    pub fn from_settings(settings: &Settings) -> io::Result<Option<Self>> {
+        match settings.wav_file {
+            Some(ref file_name) => {
+                println!("Recording audio to: {}", file_name);
+                let generator = create_tone_generator(settings);
+                let file = BufWriter::new(File::create(file_name)?);
+                let writer = WavWriter::new(file, generator.sample_rate())?;
+                Ok(Some(WavRecorder {generator, writer}))
+            },
+
+            None => Ok(None),
+        }
+    }
+
+    pub fn record_tick(&mut self, is_buzzer_active: bool) -> io::Result<()> {
+        let sample_count = self.generator.samples_per_tick();
+        let samples = self.generator.generate(is_buzzer_active, sample_count);
+        self.writer.write_samples(&samples)
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        self.writer.finish().map(|_| ())
+    }
+}
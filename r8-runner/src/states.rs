@@ -0,0 +1,31 @@
+use std::io;
+use std::io::{BufReader, BufWriter};
+use std::fs::File;
+use r8_core::{Hardware, SaveStateError};
+use crate::settings::Settings;
+
+// Quick save states are kept next to the ROM they were made from
+fn quick_save_file(settings: &Settings) -> String {
+    format!("{}.state", settings.rom_file)
+}
+
+pub fn quick_save(hardware: &Hardware, settings: &Settings) -> Result<(), SaveStateError> {
+    let file_name = quick_save_file(settings);
+    let mut file = BufWriter::new(File::create(&file_name)?);
+    hardware.save_state(&mut file)?;
+
+    println!("Saved state to: {}", file_name);
+    Ok(())
+}
+
+pub fn quick_load(settings: &Settings) -> Result<Hardware, SaveStateError> {
+    let file_name = quick_save_file(settings);
+    let mut file = BufReader::new(File::open(&file_name).map_err(|source| match source.kind() {
+        io::ErrorKind::NotFound => io::Error::new(source.kind(), format!("no save state at {}", file_name)),
+        _ => source,
+    })?);
+
+    let hardware = Hardware::load_state(&mut file)?;
+    println!("Loaded state from: {}", file_name);
+    Ok(hardware)
+}
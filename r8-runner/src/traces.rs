@@ -0,0 +1,30 @@
+use std::io;
+use std::io::BufWriter;
+use std::fs::File;
+use r8_core::{Hardware, Tracer, PendingTrace};
+use crate::settings::Settings;
+
+pub type FileTracer = Tracer<BufWriter<File>>;
+
+pub fn create_tracer(settings: &Settings) -> io::Result<Option<FileTracer>> {
+    match settings.trace_file {
+        Some(ref file_name) => {
+            println!("Tracing instructions to: {}", file_name);
+            let file = BufWriter::new(File::create(file_name)?);
+            Tracer::new(file, settings.trace_format, settings.trace_filter.clone()).map(Some)
+        },
+
+        None => Ok(None),
+    }
+}
+
+// Captures what the trace needs from before the next instruction runs, if tracing is enabled
+pub fn before_step(tracer: &Option<FileTracer>, hardware: &Hardware) -> Option<PendingTrace> {
+    tracer.as_ref().map(|_| PendingTrace::capture(hardware))
+}
+
+pub fn after_step(tracer: &mut Option<FileTracer>, pending: Option<PendingTrace>, hardware: &Hardware) {
+    if let (Some(tracer), Some(pending)) = (tracer.as_mut(), pending) {
+        tracer.record(&pending, hardware).unwrap();
+    }
+}
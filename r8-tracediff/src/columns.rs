@@ -0,0 +1,188 @@
+use crate::diff::State;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Cycle,
+    ProgramCounter,
+    Opcode,
+    Register(u8),
+    I,
+    DelayTimer,
+    SoundTimer,
+    Ignored,
+}
+
+// Describes a log with one instruction per line, such as `--columns pc,op,v0-vf,i`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnFormat {
+    columns: Vec<Column>,
+    separator: Option<char>, // Whitespace if not set
+}
+
+impl ColumnFormat {
+    pub fn parse(text: &str, separator: Option<char>) -> Result<Self, String> {
+        let mut columns = Vec::new();
+        for name in text.split(',').map(|x| x.trim().to_lowercase()) {
+            if let Some(index) = name.find('-') {
+                let first = parse_register_name(&name[..index]);
+                let last = parse_register_name(&name[index + 1..]);
+                match (first, last) {
+                    (Some(first), Some(last)) if first <= last => columns.extend((first..=last).map(Column::Register)),
+                    _ => return Err(format!("'{}' is not a register range such as v0-vf", name)),
+                }
+
+                continue;
+            }
+
+            columns.push(match name.as_str() {
+                "cycle" => Column::Cycle,
+                "pc" => Column::ProgramCounter,
+                "op" | "opcode" => Column::Opcode,
+                "i" => Column::I,
+                "dt" => Column::DelayTimer,
+                "st" => Column::SoundTimer,
+                "_" => Column::Ignored,
+                _ => match parse_register_name(&name) {
+                    Some(number) => Column::Register(number),
+                    None => return Err(format!("Unknown column '{}'", name)),
+                },
+            });
+        }
+
+        Ok(ColumnFormat {columns, separator})
+    }
+
+    // Reads every line except blank ones and comments starting with `#`
+    pub fn read(&self, text: &str) -> Result<Vec<State>, String> {
+        let mut states = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let state = self.read_line(line).map_err(|error| format!("Line {}: {}", index + 1, error))?;
+            states.push(state);
+        }
+
+        Ok(states)
+    }
+
+    fn read_line(&self, line: &str) -> Result<State, String> {
+        let values = match self.separator {
+            Some(separator) => line.split(separator).map(|x| x.trim()).collect::<Vec<&str>>(),
+            None => line.split_whitespace().collect::<Vec<&str>>(),
+        };
+
+        if values.len() < self.columns.len() {
+            return Err(format!("expected {} columns, found {}", self.columns.len(), values.len()));
+        }
+
+        let mut state = State::default();
+        for (column, text) in self.columns.iter().zip(values) {
+            let value = match column {
+                Column::Ignored => continue,
+                Column::Cycle => strip_label(text).parse::<u64>().map_err(|_| format!("'{}' is not a cycle number", text))?,
+                _ => parse_value(text).ok_or_else(|| format!("'{}' is not a hex value", text))?,
+            };
+
+            let too_big = || format!("'{}' is too big for {:?}", text, column);
+            match column {
+                Column::Cycle => state.cycle = Some(value),
+                Column::ProgramCounter => state.program_counter = Some(narrow(value).ok_or_else(too_big)?),
+                Column::Opcode => {
+                    // XO-CHIP's long load is logged as all four of its bytes
+                    let size = if hex_digits(text).len() > 4 { 4 } else { 2 };
+                    state.bytes = value.to_be_bytes()[8 - size..].to_vec();
+                }
+
+                Column::Register(number) => state.gen_registers[*number as usize] = Some(narrow(value).ok_or_else(too_big)?),
+                Column::I => state.i_register = Some(narrow(value).ok_or_else(too_big)?),
+                Column::DelayTimer => state.delay_timer = Some(narrow(value).ok_or_else(too_big)?),
+                Column::SoundTimer => state.sound_timer = Some(narrow(value).ok_or_else(too_big)?),
+                Column::Ignored => (),
+            }
+        }
+
+        Ok(state)
+    }
+}
+
+fn parse_register_name(name: &str) -> Option<u8> {
+    match name.strip_prefix('v') {
+        Some(number) if number.len() == 1 => u8::from_str_radix(number, 16).ok(),
+        _ => None,
+    }
+}
+
+// Logs often label their values, as in `PC:0200` or `I=0300`
+fn strip_label(text: &str) -> &str {
+    let text = text.trim_end_matches(',');
+    match text.rfind(['=', ':']) {
+        Some(index) => &text[index + 1..],
+        None => text,
+    }
+}
+
+fn hex_digits(text: &str) -> &str {
+    let text = strip_label(text);
+    text.strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .or_else(|| text.strip_prefix('$'))
+        .unwrap_or(text)
+}
+
+fn parse_value(text: &str) -> Option<u64> {
+    u64::from_str_radix(hex_digits(text), 16).ok()
+}
+
+fn narrow<T: std::convert::TryFrom<u64>>(value: u64) -> Option<T> {
+    T::try_from(value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_register_ranges() {
+        let format = ColumnFormat::parse("pc, _, v0-v2, i", None).unwrap();
+        let expected = vec![Column::ProgramCounter, Column::Ignored, Column::Register(0), Column::Register(1), Column::Register(2), Column::I];
+        assert_eq!(format.columns, expected, "Incorrect columns");
+    }
+
+    #[test]
+    fn unknown_column_is_an_error() {
+        assert!(ColumnFormat::parse("pc,sp", None).is_err(), "Expected unknown column to be an error");
+        assert!(ColumnFormat::parse("v3-v1", None).is_err(), "Expected backwards range to be an error");
+    }
+
+    #[test]
+    fn can_read_labelled_values() {
+        let format = ColumnFormat::parse("pc,op,v0,v1,i", None).unwrap();
+        let states = format.read("# comment\nPC:0x0202 OP:6a05 V0=$10 V1=ff, I=0300\n\n").unwrap();
+
+        assert_eq!(states.len(), 1, "Incorrect number of states");
+        assert_eq!(states[0].program_counter, Some(0x202), "Incorrect program counter");
+        assert_eq!(states[0].bytes, vec![0x6a, 0x05], "Incorrect bytes");
+        assert_eq!(states[0].gen_registers[0], Some(0x10), "Incorrect V0");
+        assert_eq!(states[0].gen_registers[1], Some(0xff), "Incorrect V1");
+        assert_eq!(states[0].gen_registers[2], None, "V2 should not be known");
+        assert_eq!(states[0].i_register, Some(0x300), "Incorrect I");
+    }
+
+    #[test]
+    fn can_use_custom_separator() {
+        let format = ColumnFormat::parse("cycle,pc", Some('|')).unwrap();
+        let states = format.read("5 | 0x0210").unwrap();
+        assert_eq!(states[0].cycle, Some(5), "Incorrect cycle");
+        assert_eq!(states[0].program_counter, Some(0x210), "Incorrect program counter");
+    }
+
+    #[test]
+    fn short_line_reports_line_number() {
+        let format = ColumnFormat::parse("pc,op,i", None).unwrap();
+        let error = format.read("0200 6a05 0000\n0202 a300").unwrap_err();
+        assert_eq!(error, "Line 2: expected 3 columns, found 2", "Incorrect error");
+    }
+}
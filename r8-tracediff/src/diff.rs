@@ -0,0 +1,145 @@
+use r8_core::{Instruction, TraceEntry, get_instruction_at};
+
+// The machine state just before an instruction runs.  Logs from other emulators rarely have
+// every value, so anything a log doesn't have is left out of the comparison.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct State {
+    pub cycle: Option<u64>,
+    pub program_counter: Option<u16>,
+    pub bytes: Vec<u8>,
+    pub gen_registers: [Option<u8>; 16],
+    pub i_register: Option<u16>,
+    pub delay_timer: Option<u8>,
+    pub sound_timer: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub name: String,
+    pub left: String,
+    pub right: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Identical {count: usize},
+    Diverged {index: usize, differences: Vec<Difference>},
+    LeftEnded {index: usize},
+    RightEnded {index: usize},
+}
+
+impl State {
+    pub fn from_entry(entry: &TraceEntry) -> Self {
+        let mut gen_registers = [None; 16];
+        for (state, value) in gen_registers.iter_mut().zip(entry.before.gen_registers.iter()) {
+            *state = Some(*value);
+        }
+
+        State {
+            cycle: Some(entry.cycle),
+            program_counter: Some(entry.address),
+            bytes: entry.bytes.clone(),
+            gen_registers,
+            i_register: Some(entry.before.i_register),
+            delay_timer: Some(entry.before.delay_timer),
+            sound_timer: Some(entry.before.sound_timer),
+        }
+    }
+
+    pub fn instruction(&self) -> Option<Instruction> {
+        match self.bytes.len() {
+            0 | 1 => None,
+            _ => Some(get_instruction_at(&self.bytes, 0)),
+        }
+    }
+
+    pub fn opcode(&self) -> Option<u16> {
+        match self.bytes.len() {
+            0 | 1 => None,
+            _ => Some(((self.bytes[0] as u16) << 8) | self.bytes[1] as u16),
+        }
+    }
+
+    // Each value that can be compared, with its name and how many hex digits it's shown with
+    fn values(&self) -> Vec<(String, Option<u16>, usize)> {
+        let mut values = vec![
+            ("PC".to_owned(), self.program_counter, 4),
+            ("opcode".to_owned(), self.opcode(), 4),
+        ];
+
+        for (index, value) in self.gen_registers.iter().enumerate() {
+            values.push((format!("V{:X}", index), value.map(|x| x as u16), 2));
+        }
+
+        values.push(("I".to_owned(), self.i_register, 4));
+        values.push(("DT".to_owned(), self.delay_timer.map(|x| x as u16), 2));
+        values.push(("ST".to_owned(), self.sound_timer.map(|x| x as u16), 2));
+        values
+    }
+}
+
+// Lists the values that both states have but disagree on
+pub fn compare(left: &State, right: &State) -> Vec<Difference> {
+    left.values().into_iter().zip(right.values())
+        .filter_map(|((name, left, width), (_, right, _))| match (left, right) {
+            (Some(left), Some(right)) if left != right => Some(Difference {
+                name,
+                left: format!("{:0width$x}", left, width = width),
+                right: format!("{:0width$x}", right, width = width),
+            }),
+
+            _ => None,
+        })
+        .collect()
+}
+
+// Finds the first entry where the traces disagree.  Since each entry is the state before an
+// instruction runs, the instruction before that entry is usually the one that behaved differently.
+pub fn first_divergence(left: &[State], right: &[State]) -> Outcome {
+    for (index, (left_state, right_state)) in left.iter().zip(right).enumerate() {
+        let differences = compare(left_state, right_state);
+        if !differences.is_empty() {
+            return Outcome::Diverged {index, differences};
+        }
+    }
+
+    match left.len().cmp(&right.len()) {
+        std::cmp::Ordering::Less => Outcome::LeftEnded {index: left.len()},
+        std::cmp::Ordering::Greater => Outcome::RightEnded {index: right.len()},
+        std::cmp::Ordering::Equal => Outcome::Identical {count: left.len()},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(program_counter: u16, v0: u8) -> State {
+        let mut state = State {program_counter: Some(program_counter), ..State::default()};
+        state.gen_registers[0] = Some(v0);
+        state
+    }
+
+    #[test]
+    fn finds_first_differing_entry() {
+        let left = vec![state(0x200, 0), state(0x202, 1), state(0x204, 2)];
+        let right = vec![state(0x200, 0), state(0x202, 1), state(0x204, 3)];
+
+        let expected = vec![Difference {name: "V0".to_owned(), left: "02".to_owned(), right: "03".to_owned()}];
+        assert_eq!(first_divergence(&left, &right), Outcome::Diverged {index: 2, differences: expected});
+    }
+
+    #[test]
+    fn missing_values_are_not_compared() {
+        let left = vec![state(0x200, 5)];
+        let right = vec![State {program_counter: Some(0x200), ..State::default()}];
+        assert_eq!(first_divergence(&left, &right), Outcome::Identical {count: 1});
+    }
+
+    #[test]
+    fn shorter_trace_is_reported() {
+        let left = vec![state(0x200, 0), state(0x202, 0)];
+        let right = vec![state(0x200, 0)];
+        assert_eq!(first_divergence(&left, &right), Outcome::RightEnded {index: 1});
+    }
+}
@@ -0,0 +1,146 @@
+#[macro_use] extern crate clap;
+extern crate r8_core;
+
+mod columns;
+mod diff;
+mod replay;
+mod settings;
+
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use r8_core::{Machine, read_trace};
+use crate::columns::ColumnFormat;
+use crate::diff::{State, Outcome, Difference};
+use crate::settings::Settings;
+
+fn main() {
+    let settings = Settings::from_cli_arguments();
+    let left = load_states(&settings.left_file, &settings.left_columns);
+    let right = load_states(&settings.right_file, &settings.right_columns);
+
+    let index = match diff::first_divergence(&left, &right) {
+        Outcome::Identical {count} => {
+            println!("Traces match for all {} instructions", count);
+            return;
+        }
+
+        Outcome::Diverged {index, differences} => {
+            println!("Traces diverge at entry {}{}", index + 1, describe_cycle(&left[index]));
+            print_context(&left, index, settings.context);
+            print_differences(&settings, &differences);
+            index
+        }
+
+        Outcome::LeftEnded {index} => {
+            println!("{} ends after {} instructions but {} continues", settings.left_file, index, settings.right_file);
+            print_context(&left, index, settings.context);
+            index
+        }
+
+        Outcome::RightEnded {index} => {
+            println!("{} ends after {} instructions but {} continues", settings.right_file, index, settings.left_file);
+            print_context(&left, index, settings.context);
+            index
+        }
+    };
+
+    if let Some(ref rom_file) = settings.rom_file {
+        print_memory_writes(&settings, rom_file, &left[..index]);
+    }
+
+    std::process::exit(1);
+}
+
+fn load_states(file_name: &str, columns: &Option<ColumnFormat>) -> Vec<State> {
+    let result = match columns {
+        Some(format) => fs::read_to_string(file_name)
+            .map_err(|error| error.to_string())
+            .and_then(|text| format.read(&text)),
+
+        None => File::open(file_name)
+            .map_err(|error| error.to_string())
+            .and_then(|file| read_trace(&mut BufReader::new(file)).map_err(|error| error.to_string()))
+            .map(|entries| entries.iter().map(State::from_entry).collect()),
+    };
+
+    result.unwrap_or_else(|error| {
+        eprintln!("Could not read {}: {}", file_name, error);
+        std::process::exit(1);
+    })
+}
+
+fn describe_cycle(state: &State) -> String {
+    match state.cycle {
+        Some(cycle) => format!(" (cycle {})", cycle),
+        None => String::new(),
+    }
+}
+
+fn describe_instruction(state: &State) -> String {
+    let address = match state.program_counter {
+        Some(address) => format!("{:#06x}", address),
+        None => "??????".to_owned(),
+    };
+
+    match state.instruction() {
+        Some(instruction) => format!("{}: {}", address, instruction),
+        None => format!("{}: ??", address),
+    }
+}
+
+// The last instruction before the traces diverge is the one most likely to behave differently
+fn print_context(left: &[State], index: usize, context: usize) {
+    if index == 0 {
+        println!("The traces differ before the first instruction");
+        return;
+    }
+
+    println!();
+    println!("Last matching instructions:");
+    for (offset, state) in left[index.saturating_sub(context)..index].iter().enumerate() {
+        let entry = index.saturating_sub(context) + offset + 1;
+        let marker = if entry == index { "  <- probably behaved differently" } else { "" };
+        println!("  {:>8} {}{}", entry, describe_instruction(state), marker);
+    }
+}
+
+fn print_differences(settings: &Settings, differences: &[Difference]) {
+    println!();
+    println!("Differences ({} / {}):", settings.left_file, settings.right_file);
+    for difference in differences {
+        println!("  {:<6} {} / {}", difference.name, difference.left, difference.right);
+    }
+}
+
+fn print_memory_writes(settings: &Settings, rom_file: &str, states: &[State]) {
+    let last = match states.last() {
+        Some(last) => last,
+        None => return,
+    };
+
+    let rom = fs::read(rom_file).unwrap_or_else(|error| {
+        eprintln!("Could not read {}: {}", rom_file, error);
+        std::process::exit(1);
+    });
+
+    let mut machine = Machine::new(settings.platform);
+    machine.hardware.quirks = settings.quirks;
+    if let Err(error) = machine.load_rom(&rom) {
+        eprintln!("Could not load {}: {}", rom_file, error);
+        std::process::exit(1);
+    }
+
+    println!();
+    match replay::memory_written_by_last(&mut machine, states) {
+        Ok(ref writes) if writes.is_empty() => println!("{} wrote no memory when replayed", describe_instruction(last)),
+        Ok(writes) => {
+            println!("Memory written by {} when replayed:", describe_instruction(last));
+            for write in writes {
+                println!("  {:#06x}: {:02x} -> {:02x}", write.address, write.old, write.new);
+            }
+        }
+
+        Err(error) => println!("{}", error),
+    }
+}
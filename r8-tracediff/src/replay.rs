@@ -0,0 +1,84 @@
+use r8_core::Machine;
+use crate::diff::State;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryWrite {
+    pub address: usize,
+    pub old: u8,
+    pub new: u8,
+}
+
+// Traces don't include memory, so it's rebuilt by replaying the ROM.  Before each instruction
+// the registers are set to the ones in the trace, which keeps memory the same as in the traced run
+// even though timers, key presses and random numbers aren't reproduced.  Returns the memory that
+// the last of the given instructions wrote.
+pub fn memory_written_by_last(machine: &mut Machine, states: &[State]) -> Result<Vec<MemoryWrite>, String> {
+    let mut writes = Vec::new();
+    for (index, state) in states.iter().enumerate() {
+        apply_state(machine, state);
+
+        let memory_before = match index + 1 == states.len() {
+            true => Some(machine.hardware.memory.clone()),
+            false => None,
+        };
+
+        let address = machine.hardware.program_counter;
+        machine.step().map_err(|error| format!("Could not replay entry {} at {:#06x}: {}", index + 1, address, error))?;
+
+        if let Some(memory_before) = memory_before {
+            writes = memory_before.iter().zip(&machine.hardware.memory).enumerate()
+                .filter(|(_, (old, new))| old != new)
+                .map(|(address, (old, new))| MemoryWrite {address, old: *old, new: *new})
+                .collect();
+        }
+    }
+
+    Ok(writes)
+}
+
+fn apply_state(machine: &mut Machine, state: &State) {
+    let hardware = &mut machine.hardware;
+    if let Some(program_counter) = state.program_counter {
+        hardware.program_counter = program_counter;
+    }
+
+    for (register, value) in hardware.gen_registers.iter_mut().zip(state.gen_registers.iter()) {
+        if let Some(value) = value {
+            *register = *value;
+        }
+    }
+
+    if let Some(i_register) = state.i_register {
+        hardware.i_register = i_register;
+    }
+
+    if let Some(delay_timer) = state.delay_timer {
+        hardware.delay_timer = delay_timer;
+    }
+
+    if let Some(sound_timer) = state.sound_timer {
+        hardware.sound_timer = sound_timer;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use r8_core::Platform;
+
+    #[test]
+    fn finds_memory_written_by_last_instruction() {
+        // 200: RND V0, ff / 202: LD [I], V0
+        let mut machine = Machine::new(Platform::Chip8);
+        machine.load_rom(&[0xc0, 0xff, 0xf0, 0x55]).unwrap();
+
+        // The random number comes from the trace rather than the replay
+        let mut first = State {program_counter: Some(0x200), i_register: Some(0x300), ..State::default()};
+        first.gen_registers[0] = Some(0);
+        let mut second = State {program_counter: Some(0x202), i_register: Some(0x300), ..State::default()};
+        second.gen_registers[0] = Some(0x42);
+
+        let writes = memory_written_by_last(&mut machine, &[first, second]).unwrap();
+        assert_eq!(writes, vec![MemoryWrite {address: 0x300, old: 0, new: 0x42}], "Incorrect writes");
+    }
+}
@@ -0,0 +1,41 @@
+use clap::App;
+use r8_core::{Platform, Quirks};
+use crate::columns::ColumnFormat;
+
+pub struct Settings {
+    pub left_file: String,
+    pub right_file: String,
+    pub left_columns: Option<ColumnFormat>,
+    pub right_columns: Option<ColumnFormat>,
+    pub context: usize,
+    pub rom_file: Option<String>,
+    pub platform: Platform,
+    pub quirks: Quirks,
+}
+
+impl Settings {
+    pub fn from_cli_arguments() -> Self {
+        let yaml = load_yaml!("cli.yml");
+        let matches = App::from_yaml(yaml).get_matches();
+        let platform = matches.value_of("platform").and_then(Platform::from_name).unwrap_or(Platform::Chip8);
+
+        let separator = matches.value_of("separator").and_then(|x| x.chars().next());
+        let columns = |name| matches.value_of(name).map(|text| {
+            ColumnFormat::parse(text, separator).unwrap_or_else(|error| {
+                eprintln!("error: {}", error);
+                std::process::exit(1);
+            })
+        });
+
+        Self {
+            left_file: matches.value_of("LEFT").unwrap().to_owned(),
+            right_file: matches.value_of("RIGHT").unwrap().to_owned(),
+            left_columns: columns("left-columns"),
+            right_columns: columns("right-columns"),
+            context: value_t!(matches, "context", usize).unwrap_or(5),
+            rom_file: matches.value_of("rom").map(|x| x.to_owned()),
+            platform,
+            quirks: matches.value_of("quirks").and_then(Quirks::preset).unwrap_or_else(|| platform.default_quirks()),
+        }
+    }
+}